@@ -0,0 +1,110 @@
+#ifndef CRC32_H
+#define CRC32_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Returned by functions which succeeded.
+#define CRC32_OK 0
+
+// Returned by functions which failed, see `crc32_last_error`.
+#define CRC32_ERROR -1
+
+// Status of a verified file.
+typedef enum Crc32Status {
+  CRC32_STATUS_OK,
+  CRC32_STATUS_FAIL,
+  // The file does not exist.
+  CRC32_STATUS_MISSING,
+  // The file exists but is not listed in the checksum file.
+  CRC32_STATUS_EXTRA,
+  // The file cannot be read.
+  CRC32_STATUS_ERROR,
+} Crc32Status;
+
+// Counts of processed files by status.
+typedef struct Crc32Summary {
+  size_t files;
+  size_t ok;
+  size_t failed;
+  size_t missing;
+  size_t extra;
+  size_t errors;
+  // Total size of the files which were read.
+  uint64_t bytes;
+} Crc32Summary;
+
+// Callback receiving the path, status and user data of each verified file. The path is only valid during the call.
+typedef void (*Crc32ResultCallback)(const char *path, enum Crc32Status status, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Computes the checksum of the file at `path` using `algorithm`, or CRC32 if it is null, and stores it as a hex string
+// in `checksum`, which must be released with `crc32_free_string`.
+//
+// # Safety
+//
+// `path` must be a valid NUL-terminated string, `algorithm` null or a valid NUL-terminated string and `checksum` a
+// valid pointer.
+int crc32_hash_file(const char *path,
+                    const char *algorithm,
+                    char **checksum);
+
+// Computes the checksums of the `count` files or directories in `paths` using `algorithm`, or CRC32 if it is null, and
+// writes them to the checksum file `out_file`, like `crc32 -o`.
+//
+// The format of the checksum file is detected from its name. Directories are searched recursively if `recursive` is
+// true. Hashing stops at the first file which cannot be read. The counts of hashed files are stored in `summary` unless
+// it is null.
+//
+// # Safety
+//
+// `paths` must point to `count` valid NUL-terminated strings, `out_file` must be a valid NUL-terminated string,
+// `algorithm` null or a valid NUL-terminated string and `summary` null or a valid pointer.
+int crc32_create_manifest(const char *const *paths,
+                          size_t count,
+                          const char *out_file,
+                          const char *algorithm,
+                          bool recursive,
+                          struct Crc32Summary *summary);
+
+// Verifies the checksum file `manifest`, like `crc32 --verify`.
+//
+// Listed files are hashed using `algorithm`, or the algorithm detected from the checksum file if it is null. Relative
+// paths are resolved against the directory containing the checksum file. `on_result` is called with the result of
+// each file unless it is null, on the calling thread and in the order the files are listed, passing `user_data`. The
+// counts of verified files by status are stored in `summary` unless it is null. Files which do not match, are missing
+// or cannot be read are not errors, only reading the checksum file itself can fail.
+//
+// # Safety
+//
+// `manifest` must be a valid NUL-terminated string, `algorithm` null or a valid NUL-terminated string and `summary`
+// null or a valid pointer. `on_result` must be safe to call with `user_data`.
+int crc32_verify(const char *manifest,
+                 const char *algorithm,
+                 Crc32ResultCallback on_result,
+                 void *user_data,
+                 struct Crc32Summary *summary);
+
+// Returns the message of the last error on the calling thread, or null if no function failed yet.
+//
+// The message is owned by the library and valid until the next call failing on the same thread.
+const char *crc32_last_error(void);
+
+// Releases a string returned by the library. Does nothing if `string` is null.
+//
+// # Safety
+//
+// `string` must be null or a string returned by the library which was not released yet.
+void crc32_free_string(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CRC32_H */
@@ -0,0 +1,399 @@
+//! Members of archives, which are read without extracting them to disk.
+use std::{
+    cell::OnceCell,
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Error, Result};
+use sevenz_rust2::{ArchiveReader, Password};
+use zip::{ZipArchive, read::ZipReadOptions};
+
+use crate::walk::path_from_bytes;
+
+/// Size up to which nested ZIP and 7z archives are read into memory, larger ones are copied to a temporary file.
+const SPOOL_MEMORY: u64 = 64 * 1024 * 1024;
+
+/// Format of an archive.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    SevenZip,
+}
+
+impl ArchiveKind {
+    /// Detects the format of the archive at `path` from its extension, returns `None` if it is not an archive.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "zip" => Some(ArchiveKind::Zip),
+            "tar" => Some(ArchiveKind::Tar),
+            "7z" => Some(ArchiveKind::SevenZip),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a path listed in a checksum file such as `backup.zip/photos/img001.jpg` into the archive containing it and
+/// the path of the member within the archive.
+///
+/// Returns `None` if `path` exists on disk or none of its parents is an archive file.
+pub fn split_member_path(path: &Path) -> Option<(&Path, &Path)> {
+    if fs::symlink_metadata(path).is_ok() {
+        return None;
+    }
+
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|archive| ArchiveKind::detect(archive).is_some() && archive.is_file())?;
+    Some((archive, path.strip_prefix(archive).ok()?))
+}
+
+/// File stored in an archive.
+pub struct Member {
+    /// Path of the member within the archive.
+    pub path: PathBuf,
+    /// Size in bytes after decompression.
+    pub size: u64,
+    /// Size in bytes as stored in the archive, or 0 if it is unknown, e.g. for members of solid 7z archives.
+    pub compressed_size: u64,
+    /// CRC-32 stored in the archive, if the format stores one.
+    pub crc: Option<u32>,
+}
+
+/// Returns the path of a member stored as `name` in an archive, without leading `./` or `/`.
+fn member_path(name: &[u8]) -> PathBuf {
+    path_from_bytes(name)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_) | Component::ParentDir))
+        .collect()
+}
+
+/// Calls `visit` with each file stored in the archive at `path` and a reader of its decompressed contents.
+///
+/// The format is detected with `ArchiveKind::detect`. Members need not be read to the end. Archives stored in the
+/// archive are read as well if `descend` returns true for their path, and their members are passed to `visit` with
+/// paths such as `inner.zip/file.txt`. Nested tar archives are read while they are decompressed, while nested ZIP and
+/// 7z archives must be seekable and are copied to memory or, above `SPOOL_MEMORY`, to a temporary file. Nested archives
+/// which are identical to an archive containing them would never end and are passed to `visit` like other files
+/// instead. See `zip_members` for how errors are handled. Other formats fail as a whole if a member cannot be
+/// decompressed. Nested archives which cannot be read are passed to `visit` with the error.
+pub fn archive_members<D, F>(path: &Path, descend: D, visit: F) -> Result<()>
+where
+    D: Fn(&Path) -> bool,
+    F: FnMut(&Member, Result<&mut dyn Read>) -> Result<()>,
+{
+    let kind = ArchiveKind::detect(path)
+        .ok_or_else(|| Error::msg(format!("{} is not a supported archive", path.display())))?;
+    let file =
+        File::open(path).with_context(|| format!("Failed to open file {}", path.display()))?;
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut walker = Walker {
+        descend,
+        visit,
+        archive: path,
+        size,
+        hash: OnceCell::new(),
+        ancestors: Vec::new(),
+        stopped: false,
+    };
+    walker.read(kind, BufReader::new(file), path, Path::new(""))
+}
+
+/// Calls `visit` with each file stored in the ZIP archive at `path` and a reader of its decompressed contents.
+///
+/// Directories are skipped. The stored CRCs are not checked while reading, so that `visit` can compare them itself.
+/// Members which cannot be decompressed, e.g. because of an unsupported compression method, are passed to `visit` with
+/// an error instead of a reader. Errors returned by `visit` stop reading and are propagated, errors opening the
+/// archive are returned with added context.
+pub fn zip_members<F>(path: &Path, mut visit: F) -> Result<()>
+where
+    F: FnMut(&Member, Result<&mut dyn Read>) -> Result<()>,
+{
+    let file =
+        File::open(path).with_context(|| format!("Failed to open file {}", path.display()))?;
+    read_zip(BufReader::new(file), path, |member, reader| {
+        visit(&member, reader)
+    })
+}
+
+/// State of reading an archive along with the archives nested in it.
+struct Walker<'a, D, F> {
+    descend: D,
+    visit: F,
+    /// Path of the outermost archive.
+    archive: &'a Path,
+    /// Size of the outermost archive in bytes.
+    size: u64,
+    /// Hash of the outermost archive, only computed once a nested archive of the same size is found.
+    hash: OnceCell<Option<blake3::Hash>>,
+    /// Sizes and hashes of the nested ZIP and 7z archives currently being read.
+    ancestors: Vec<(u64, blake3::Hash)>,
+    /// Whether `visit` returned an error, which stops reading.
+    stopped: bool,
+}
+
+impl<D, F> Walker<'_, D, F>
+where
+    D: Fn(&Path) -> bool,
+    F: FnMut(&Member, Result<&mut dyn Read>) -> Result<()>,
+{
+    /// Reads the archive `source` in the given format, shown as `name` in errors, whose members are below `prefix`.
+    fn read<R>(&mut self, kind: ArchiveKind, source: R, name: &Path, prefix: &Path) -> Result<()>
+    where
+        R: Read + Seek,
+    {
+        let visit = |member: Member, reader: Result<&mut dyn Read>| {
+            self.member(name, prefix, member, reader)
+        };
+        match kind {
+            ArchiveKind::Zip => read_zip(source, name, visit),
+            ArchiveKind::Tar => read_tar(source, name, visit),
+            ArchiveKind::SevenZip => read_sevenz(source, name, visit),
+        }
+    }
+
+    /// Passes a member of the archive shown as `name` to `visit`, or reads it if it is an archive to descend into.
+    fn member(
+        &mut self,
+        name: &Path,
+        prefix: &Path,
+        mut member: Member,
+        reader: Result<&mut dyn Read>,
+    ) -> Result<()> {
+        let relative = member.path;
+        member.path = prefix.join(&relative);
+        let kind = ArchiveKind::detect(&member.path).filter(|_| (self.descend)(&member.path));
+        let (kind, reader) = match (kind, reader) {
+            (Some(kind), Ok(reader)) => (kind, reader),
+            (_, reader) => return self.pass(&member, reader),
+        };
+
+        let nested = name.join(&relative);
+        // Tar archives are larger than their members, so only compressed archives can contain themselves.
+        if kind == ArchiveKind::Tar {
+            let result = read_tar(reader, &nested, |inner, reader| {
+                self.member(&nested, &member.path, inner, reader)
+            });
+            return self.nested(&member, result);
+        }
+
+        let decompress_error = |e| {
+            Error::new(e).context(format!(
+                "Failed to decompress {} in {}",
+                relative.display(),
+                name.display()
+            ))
+        };
+        let (mut spool, size, hash) = match Spool::new(reader) {
+            Ok(spooled) => spooled,
+            Err(e) => return self.pass(&member, Err(decompress_error(e))),
+        };
+        if self.is_ancestor(size, &hash) {
+            let reader = match spool.rewind() {
+                Ok(()) => Ok(&mut spool as &mut dyn Read),
+                Err(e) => Err(decompress_error(e)),
+            };
+            return self.pass(&member, reader);
+        }
+
+        self.ancestors.push((size, hash));
+        let result = self.read(kind, spool, &nested, &member.path);
+        self.ancestors.pop();
+        self.nested(&member, result)
+    }
+
+    /// Handles the `result` of reading the nested archive `member`, which is passed to `visit` with the error if
+    /// reading it failed without `visit` failing.
+    fn nested(&mut self, member: &Member, result: Result<()>) -> Result<()> {
+        match result {
+            Err(e) if !self.stopped => self.pass(member, Err(e)),
+            result => result,
+        }
+    }
+
+    /// Calls `visit`, remembering whether it failed.
+    fn pass(&mut self, member: &Member, reader: Result<&mut dyn Read>) -> Result<()> {
+        let result = (self.visit)(member, reader);
+        self.stopped |= result.is_err();
+        result
+    }
+
+    /// Whether a nested archive of `size` bytes with `hash` is identical to one of the archives being read.
+    fn is_ancestor(&self, size: u64, hash: &blake3::Hash) -> bool {
+        self.ancestors.contains(&(size, *hash))
+            || (size == self.size && self.archive_hash() == Some(hash))
+    }
+
+    /// Returns the hash of the outermost archive, or `None` if it cannot be read.
+    fn archive_hash(&self) -> Option<&blake3::Hash> {
+        self.hash
+            .get_or_init(|| {
+                let mut hasher = blake3::Hasher::new();
+                hasher.update_reader(File::open(self.archive).ok()?).ok()?;
+                Some(hasher.finalize())
+            })
+            .as_ref()
+    }
+}
+
+/// Copy of a nested archive, which can be read with seeking unlike the decompressed member.
+enum Spool {
+    Memory(Cursor<Vec<u8>>),
+    File(BufReader<File>),
+}
+
+impl Spool {
+    /// Copies the contents of `reader` to memory, or to a temporary file if there are more than `SPOOL_MEMORY` bytes.
+    ///
+    /// Returns the copy positioned at its start along with the size and hash of the contents.
+    fn new(reader: &mut dyn Read) -> io::Result<(Spool, u64, blake3::Hash)> {
+        let mut hasher = blake3::Hasher::new();
+        let mut data = Vec::new();
+        reader.take(SPOOL_MEMORY).read_to_end(&mut data)?;
+        hasher.update(&data);
+        let mut size = data.len() as u64;
+        if size < SPOOL_MEMORY {
+            return Ok((Spool::Memory(Cursor::new(data)), size, hasher.finalize()));
+        }
+
+        let mut file = tempfile::tempfile()?;
+        file.write_all(&data)?;
+        let mut data = vec![0; 64 * 1024];
+        loop {
+            let n = match reader.read(&mut data) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            hasher.update(&data[..n]);
+            file.write_all(&data[..n])?;
+            size += n as u64;
+        }
+        file.rewind()?;
+        Ok((Spool::File(BufReader::new(file)), size, hasher.finalize()))
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Spool::Memory(cursor) => cursor.read(buf),
+            Spool::File(file) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Spool::Memory(cursor) => cursor.seek(pos),
+            Spool::File(file) => file.seek(pos),
+        }
+    }
+}
+
+/// Calls `visit` with each file stored in the ZIP archive `source` shown as `name`, see `zip_members`.
+fn read_zip<R, F>(source: R, name: &Path, mut visit: F) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(Member, Result<&mut dyn Read>) -> Result<()>,
+{
+    let mut archive = ZipArchive::new(source)
+        .with_context(|| format!("Failed to read ZIP archive {}", name.display()))?;
+    for i in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(i)
+            .with_context(|| format!("Failed to read member {i} of {}", name.display()))?;
+        if entry.is_dir() {
+            continue;
+        }
+
+        let member = Member {
+            path: member_path(entry.name_raw()),
+            size: entry.size(),
+            compressed_size: entry.compressed_size(),
+            crc: Some(entry.crc32()),
+        };
+        drop(entry);
+        match archive.by_index_with_options(i, ZipReadOptions::new().ignore_crc32(true)) {
+            Ok(mut entry) => visit(member, Ok(&mut entry))?,
+            Err(e) => {
+                let e = Error::new(e).context(format!(
+                    "Failed to decompress {} in {}",
+                    member.path.display(),
+                    name.display()
+                ));
+                visit(member, Err(e))?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Calls `visit` with each regular file stored in the tar archive `source` shown as `name`, see `archive_members`.
+fn read_tar<R, F>(source: R, name: &Path, mut visit: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(Member, Result<&mut dyn Read>) -> Result<()>,
+{
+    let mut archive = tar::Archive::new(source);
+    let entries = archive
+        .entries()
+        .with_context(|| format!("Failed to read tar archive {}", name.display()))?;
+    for entry in entries {
+        let mut entry =
+            entry.with_context(|| format!("Failed to read tar archive {}", name.display()))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let member = Member {
+            path: member_path(&entry.path_bytes()),
+            size: entry.size(),
+            compressed_size: entry.size(),
+            crc: None,
+        };
+        visit(member, Ok(&mut entry))?;
+    }
+
+    Ok(())
+}
+
+/// Calls `visit` with each file stored in the 7z archive `source` shown as `name`, see `archive_members`.
+///
+/// Encrypted archives are not supported.
+fn read_sevenz<R, F>(source: R, name: &Path, mut visit: F) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(Member, Result<&mut dyn Read>) -> Result<()>,
+{
+    let mut archive = ArchiveReader::new(source, Password::empty())
+        .with_context(|| format!("Failed to read 7z archive {}", name.display()))?;
+    let mut result = Ok(());
+    archive
+        .for_each_entries(|entry, reader| {
+            if entry.is_directory() || entry.is_anti_item {
+                return Ok(true);
+            }
+
+            let member = Member {
+                path: member_path(entry.name().as_bytes()),
+                size: entry.size,
+                compressed_size: entry.compressed_size,
+                crc: entry.has_crc.then_some(entry.crc as u32),
+            };
+            result = visit(member, Ok(&mut *reader));
+            // Members of solid archives are decompressed in sequence, so the rest of this one must be skipped.
+            io::copy(reader, &mut io::sink())?;
+            Ok(result.is_ok())
+        })
+        .with_context(|| format!("Failed to read 7z archive {}", name.display()))?;
+
+    result
+}
@@ -0,0 +1,154 @@
+//! Hashing and verification for tokio applications, available with the `tokio` feature.
+//!
+//! Files are read with `tokio::fs` and any `AsyncRead` can be hashed, so that e.g. uploads can be indexed by a service
+//! without blocking its runtime. Several files are processed concurrently as tasks of the runtime, so that they are
+//! hashed in parallel on a multi-threaded runtime. Hashing a chunk is not interrupted, so tasks yield between chunks of
+//! `CHUNK_SIZE` bytes.
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use tokio::{
+    fs::{self, File},
+    io::{self, AsyncRead, AsyncReadExt},
+    task::JoinSet,
+};
+
+use crate::{
+    CHUNK_SIZE, Record, Summary,
+    error::{Error, Result},
+    hash::{Algorithm, Hasher},
+    manifest::{Entry, ManifestFormat},
+};
+
+/// Reads `reader` to the end and computes the checksum using `algorithm`, returned as a hex string.
+pub async fn hash_reader<R>(reader: &mut R, algorithm: Algorithm) -> io::Result<String>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut hasher = Hasher::new(algorithm);
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(algorithm.to_hex(&hasher.finalize()));
+        }
+
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Computes the checksum of `file` using `algorithm`, like `hash::hash_file`.
+pub async fn checksum(file: &Path, algorithm: Algorithm) -> Result<String> {
+    let mut fp = File::open(file).await.map_err(|source| Error::OpenFailed {
+        path: file.to_path_buf(),
+        source,
+    })?;
+    hash_reader(&mut fp, algorithm)
+        .await
+        .map_err(|source| Error::ReadFailed {
+            path: file.to_path_buf(),
+            source,
+        })
+}
+
+/// Computes the checksums of `files` using `algorithm`, hashing up to `jobs` files concurrently.
+///
+/// Returns each file along with its checksum or the error hashing it, in the order of `files`.
+pub async fn checksum_files(
+    files: Vec<PathBuf>,
+    algorithm: Algorithm,
+    jobs: usize,
+) -> Vec<(PathBuf, Result<String>)> {
+    let results = map_concurrent(files.clone(), jobs, move |file| async move {
+        checksum(&file, algorithm).await
+    })
+    .await;
+    files.into_iter().zip(results).collect()
+}
+
+/// Verifies the checksum file at `manifest` like `verify_sfv`, hashing up to `jobs` files concurrently.
+///
+/// The format of the checksum file is detected from its name and contents. Its entries are hashed with `algorithm`,
+/// unless they name their own algorithm. Relative paths are resolved against the directory containing the checksum
+/// file. Nothing is printed, instead the records of all listed files are returned in the order they are listed along
+/// with their counts by status. Errors reading the checksum file are returned.
+pub async fn verify(
+    manifest: &Path,
+    algorithm: Algorithm,
+    jobs: usize,
+) -> Result<(Vec<Record>, Summary)> {
+    let data = fs::read(manifest)
+        .await
+        .map_err(|source| Error::ReadFailed {
+            path: manifest.to_path_buf(),
+            source,
+        })?;
+    let entries = ManifestFormat::sniff(manifest, &data).read(&data, algorithm, false)?;
+    let base_dir = manifest.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let records = map_concurrent(entries, jobs, move |entry| {
+        let base_dir = base_dir.clone();
+        async move { verify_entry(&base_dir, entry, algorithm).await }
+    })
+    .await;
+    let mut summary = Summary::default();
+    for record in &records {
+        summary.add_record(record);
+    }
+    Ok((records, summary))
+}
+
+/// Hashes the file listed by `entry` relative to `base_dir` and returns the record of comparing its checksum.
+async fn verify_entry(base_dir: &Path, entry: Entry, algorithm: Algorithm) -> Record {
+    let file = base_dir.join(&entry.path);
+    let computed = checksum(&file, entry.algorithm.unwrap_or(algorithm))
+        .await
+        .map_err(anyhow::Error::from);
+    let size = fs::metadata(&file)
+        .await
+        .ok()
+        .map(|metadata| metadata.len());
+    Record {
+        size,
+        ..Record::checked(entry.path.display().to_string(), &entry.checksum, computed)
+    }
+}
+
+/// Applies `map` to each of `items` as tasks of the runtime, running up to `jobs` at once, and returns the results in
+/// the order of `items`.
+///
+/// Panics if a task panics.
+async fn map_concurrent<T, R, M, F>(items: Vec<T>, jobs: usize, map: M) -> Vec<R>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    M: Fn(T) -> F,
+    F: Future<Output = R> + Send + 'static,
+{
+    let mut results: Vec<Option<R>> = items.iter().map(|_| None).collect();
+    let mut tasks = JoinSet::new();
+    for (i, item) in items.into_iter().enumerate() {
+        if tasks.len() >= jobs.max(1) {
+            let (i, result) = join(&mut tasks).await;
+            results[i] = Some(result);
+        }
+        let task = map(item);
+        tasks.spawn(async move { (i, task.await) });
+    }
+    while !tasks.is_empty() {
+        let (i, result) = join(&mut tasks).await;
+        results[i] = Some(result);
+    }
+    results.into_iter().flatten().collect()
+}
+
+/// Waits for the next task of `tasks` to finish, resuming its panic if it panicked.
+async fn join<R: 'static>(tasks: &mut JoinSet<(usize, R)>) -> (usize, R) {
+    match tasks.join_next().await {
+        Some(Ok(result)) => result,
+        Some(Err(e)) => std::panic::resume_unwind(e.into_panic()),
+        None => unreachable!("joined an empty set of tasks"),
+    }
+}
@@ -0,0 +1,196 @@
+//! Cache of checksums of files, so that files which have not changed since they were last hashed are not read again.
+//!
+//! Files are identified by their device and inode number, or by their absolute path on platforms without inodes.
+//! Cached checksums are only used if the size, modification time and, on Unix, status change time of the file are
+//! unchanged. Data corrupted without any of them changing is not detected, which is why the cache is only used when
+//! asked for and never when verifying.
+use std::{
+    env,
+    fs::{self, Metadata},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::{hash::Algorithm, paths::absolute_path};
+
+/// Version of `SCHEMA` stored as the `user_version` of the database. Caches of other versions are emptied, since they
+/// only save time.
+const VERSION: i64 = 1;
+
+/// Statements creating the table if the cache is new.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS checksums (
+    file TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    mtime TEXT NOT NULL,
+    ctime TEXT NOT NULL,
+    algorithm TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    PRIMARY KEY (file, algorithm)
+);
+";
+
+/// Identity and metadata of a file which must match for a cached checksum to be used.
+pub struct Key {
+    file: String,
+    size: i64,
+    mtime: String,
+    /// Status change time, which also changes when the modification time is set back, or empty where it is unknown.
+    ctime: String,
+}
+
+impl Key {
+    /// Returns the key of the regular file at `path`, or `None` if it is not a regular file or its metadata cannot be
+    /// read.
+    pub fn new(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok().filter(Metadata::is_file)?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Key {
+            file: file_id(path, &metadata)?,
+            size: i64::try_from(metadata.len()).ok()?,
+            mtime: format!("{}.{:09}", mtime.as_secs(), mtime.subsec_nanos()),
+            ctime: change_time(&metadata),
+        })
+    }
+}
+
+/// Returns the status change time of a file.
+#[cfg(unix)]
+fn change_time(metadata: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+
+    format!("{}.{:09}", metadata.ctime(), metadata.ctime_nsec())
+}
+
+/// Returns an empty string, since the status change time is not available on stable Rust.
+#[cfg(not(unix))]
+fn change_time(_metadata: &Metadata) -> String {
+    String::new()
+}
+
+/// Returns the device and inode number of a file.
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &Metadata) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(format!("{}:{}", metadata.dev(), metadata.ino()))
+}
+
+/// Returns the absolute path of a file, since file IDs are not available on stable Rust.
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &Metadata) -> Option<String> {
+    absolute_path(path)
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Returns the default location of the cache, in the user's cache directory.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        PathBuf::from(dir)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library/Caches")
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".cache")
+    };
+    Some(dir.join("crc32").join("cache.sqlite"))
+}
+
+/// Open cache of checksums, which can be shared between threads.
+///
+/// New checksums are kept in memory and written by `save`, so that they are written in a single transaction.
+pub struct Cache {
+    conn: Mutex<Connection>,
+    path: PathBuf,
+    pending: Mutex<Vec<(Key, Algorithm, String)>>,
+}
+
+impl Cache {
+    /// Opens the cache at `path`, creating it and its directory if they do not exist. Any error is propagated with added
+    /// context.
+    pub fn open(path: &Path) -> Result<Self> {
+        let context = || format!("Failed to open cache {}", path.display());
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(context)?;
+        }
+        let conn = Connection::open(path)
+            .and_then(|conn| {
+                let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+                if version != VERSION {
+                    conn.execute_batch(&format!(
+                        "DROP TABLE IF EXISTS checksums; PRAGMA user_version = {VERSION};"
+                    ))?;
+                }
+                conn.execute_batch(SCHEMA).map(|()| conn)
+            })
+            .with_context(context)?;
+        Ok(Cache {
+            conn: Mutex::new(conn),
+            path: absolute_path(path)?,
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Returns the cached checksum of the file identified by `key` using `algorithm`, if any.
+    pub fn get(&self, key: &Key, algorithm: Algorithm) -> Option<String> {
+        let conn = self.conn.lock().expect("Cache lock should not be poisoned");
+        conn.query_row(
+            "SELECT checksum FROM checksums
+             WHERE file = ?1 AND algorithm = ?2 AND size = ?3 AND mtime = ?4 AND ctime = ?5",
+            params![key.file, algorithm.tag(), key.size, key.mtime, key.ctime],
+            |row| row.get(0),
+        )
+        .optional()
+        .ok()
+        .flatten()
+    }
+
+    /// Adds the checksum of the file identified by `key` using `algorithm`, replacing any older checksum of the file.
+    pub fn insert(&self, key: Key, algorithm: Algorithm, checksum: String) {
+        self.pending
+            .lock()
+            .expect("Cache lock should not be poisoned")
+            .push((key, algorithm, checksum));
+    }
+
+    /// Writes the checksums added since the cache was opened. Any error is propagated with added context.
+    pub fn save(self) -> Result<()> {
+        let context = || format!("Failed to write to cache {}", self.path.display());
+        let pending = self
+            .pending
+            .into_inner()
+            .expect("Cache lock should not be poisoned");
+        let mut conn = self
+            .conn
+            .into_inner()
+            .expect("Cache lock should not be poisoned");
+        let tx = conn.transaction().with_context(context)?;
+        {
+            let mut insert = tx
+                .prepare(
+                    "INSERT OR REPLACE INTO checksums (file, size, mtime, ctime, algorithm, checksum)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .with_context(context)?;
+            for (key, algorithm, checksum) in pending {
+                insert
+                    .execute(params![
+                        key.file,
+                        key.size,
+                        key.mtime,
+                        key.ctime,
+                        algorithm.tag(),
+                        checksum
+                    ])
+                    .with_context(context)?;
+            }
+        }
+        tx.commit().with_context(context)
+    }
+}
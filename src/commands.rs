@@ -0,0 +1,308 @@
+//! Subcommands selecting what is done, e.g. `crc32 verify album.sfv` instead of `crc32 --verify album.sfv`.
+//!
+//! Each subcommand stands for the flags selecting a mode, which keep working for compatibility. A subcommand given as
+//! the first argument is replaced by its flags before the arguments are parsed, so that all options and the checks of
+//! which options can be combined are shared with the flags, e.g. `crc32 verify --par2 10 a.sfv` is rejected like
+//! `crc32 --verify --par2 10 a.sfv`.
+use std::{
+    ffi::OsString,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+use clap::Command;
+use clap_mangen::{
+    Man,
+    roff::{Roff, bold, roman},
+};
+
+use crc32::{EXIT_CANCELLED, EXIT_ERROR, EXIT_MISMATCH, EXIT_MISSING};
+
+/// Subcommand along with the flags it stands for.
+struct Subcommand {
+    name: &'static str,
+    flags: &'static [&'static str],
+    /// Arguments other than options, if they differ from paths.
+    arguments: Option<&'static str>,
+    about: &'static str,
+}
+
+/// All subcommands, in the order listed by `--help`.
+const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand {
+        name: "create",
+        flags: &[],
+        arguments: None,
+        about: "Compute checksums of files [default]",
+    },
+    Subcommand {
+        name: "verify",
+        flags: &["--verify"],
+        arguments: None,
+        about: "Verify checksum files or a database",
+    },
+    Subcommand {
+        name: "expect",
+        flags: &["--expect"],
+        arguments: Some("<CHECKSUM> <PATH>"),
+        about: "Check a single file against a checksum",
+    },
+    Subcommand {
+        name: "compare",
+        flags: &["--compare"],
+        arguments: Some("<LEFT> <RIGHT>"),
+        about: "Compare two files or directory trees",
+    },
+    Subcommand {
+        name: "diff",
+        flags: &["--diff"],
+        arguments: Some("<OLD> <NEW>"),
+        about: "Report changes between two checksum files",
+    },
+    Subcommand {
+        name: "rename",
+        flags: &["--rename"],
+        arguments: None,
+        about: "Rename files to include their checksum",
+    },
+    Subcommand {
+        name: "untag",
+        flags: &["--untag"],
+        arguments: None,
+        about: "Check files against CRCs in their names and remove them",
+    },
+    Subcommand {
+        name: "retag",
+        flags: &["--retag"],
+        arguments: None,
+        about: "Check files against CRCs in their names and replace those which do not match",
+    },
+    Subcommand {
+        name: "check-names",
+        flags: &["--check-names"],
+        arguments: None,
+        about: "Check files against CRCs in their names",
+    },
+    Subcommand {
+        name: "check-zip",
+        flags: &["--check-zip"],
+        arguments: None,
+        about: "Check the members of ZIP archives against their stored CRCs",
+    },
+    Subcommand {
+        name: "check-xattr",
+        flags: &["--check-xattr"],
+        arguments: None,
+        about: "Check files against checksums stored in their extended attributes",
+    },
+    Subcommand {
+        name: "torrent",
+        flags: &["--torrent"],
+        arguments: Some("<TORRENT>"),
+        about: "Verify downloaded files against a torrent",
+    },
+    Subcommand {
+        name: "merge",
+        flags: &["--merge"],
+        arguments: None,
+        about: "Merge checksum files into the output file",
+    },
+    Subcommand {
+        name: "convert",
+        flags: &["--convert"],
+        arguments: None,
+        about: "Convert checksum files to the format of the output file",
+    },
+    Subcommand {
+        name: "fix",
+        flags: &["--fix"],
+        arguments: None,
+        about: "Sort, deduplicate and normalize checksum files in place",
+    },
+    Subcommand {
+        name: "watch",
+        flags: &["--watch"],
+        arguments: None,
+        about: "Keep the output file up to date until interrupted",
+    },
+    Subcommand {
+        name: "scrub",
+        flags: &["--verify", "--scrub"],
+        arguments: None,
+        about: "Verify checksum files or a database periodically until interrupted",
+    },
+    Subcommand {
+        name: "help",
+        flags: &["--help"],
+        arguments: None,
+        about: "Print help",
+    },
+];
+
+/// Note on how subcommands are given.
+const NOTE: &str = "A command must be the first argument, e.g. `crc32 verify -r /archive`. Each command is \
+    equivalent to the option of the same name, e.g. `crc32 --verify -r /archive`. Paths which are named like a \
+    command can be given as `./verify`.";
+
+/// Examples shown by `--help` and in the man page, each with a description and command lines.
+const EXAMPLES: &[(&str, &[&str])] = &[
+    (
+        "Create an SFV file of a directory tree",
+        &["crc32 -r photos -o photos.sfv"],
+    ),
+    (
+        "Verify it, only printing files which do not match",
+        &["crc32 verify -q photos.sfv"],
+    ),
+    (
+        "Verify all checksum files below a directory, each relative to its own directory",
+        &["crc32 verify -r /archive"],
+    ),
+    (
+        "Create and verify SHA-256 checksums in the format of sha256sum",
+        &[
+            "crc32 -a sha256 -r release -o SHA256SUMS",
+            "crc32 verify SHA256SUMS",
+        ],
+    ),
+    (
+        "Update a checksum file after files were added or modified",
+        &["crc32 -r photos -o photos.sfv --update"],
+    ),
+    (
+        "Report what changed between two checksum files",
+        &["crc32 diff 2024-01.sfv 2024-06.sfv"],
+    ),
+    (
+        "Rename files to include their CRC, checking the new names first",
+        &["crc32 rename --dry-run *.mkv"],
+    ),
+    (
+        "Verify a seventh of the files of an archive every day",
+        &["crc32 scrub -q -r /data --interval 1d --parts 7"],
+    ),
+];
+
+/// Exit codes shown by `--help` and in the man page.
+const EXIT_STATUS: &[(u8, &str)] = &[
+    (0, "All files were processed successfully"),
+    (
+        EXIT_MISMATCH,
+        "Files do not match their checksums, differ when comparing or are not listed with --check-extra",
+    ),
+    (
+        EXIT_MISSING,
+        "Files are missing, unless --ignore-missing is given",
+    ),
+    (
+        EXIT_ERROR,
+        "Files cannot be read or processing failed otherwise",
+    ),
+    (
+        EXIT_CANCELLED,
+        "Interrupted by Ctrl-C, only the files done so far are reported",
+    ),
+];
+
+/// Returns the name of a subcommand followed by its arguments.
+fn usage(subcommand: &Subcommand) -> String {
+    match subcommand.arguments {
+        Some(arguments) => format!("{} {arguments}", subcommand.name),
+        None => subcommand.name.to_string(),
+    }
+}
+
+/// Returns the text shown after the options by `-h`, i.e. the subcommands.
+pub fn help() -> String {
+    let usages: Vec<_> = SUBCOMMANDS.iter().map(usage).collect();
+    let width = usages.iter().map(String::len).max().unwrap_or_default();
+    let mut help = String::from("Commands:\n");
+    for (usage, subcommand) in usages.iter().zip(SUBCOMMANDS) {
+        let _ = writeln!(help, "  {usage:<width$}  {}", subcommand.about);
+    }
+    let _ = write!(help, "\n{NOTE}");
+    help
+}
+
+/// Returns the text shown after the options by `--help`, i.e. the subcommands, examples and exit codes.
+pub fn long_help() -> String {
+    let mut help = help();
+    help.push_str("\n\nExamples:\n");
+    for (description, lines) in EXAMPLES {
+        let _ = writeln!(help, "  {description}:");
+        for line in *lines {
+            let _ = writeln!(help, "    {line}");
+        }
+    }
+    help.push_str("\nExit status:\n");
+    for (code, description) in EXIT_STATUS {
+        let _ = writeln!(help, "  {code}  {description}");
+    }
+    help.pop();
+    help
+}
+
+/// Writes a man page of `command` in roff format, with sections for the subcommands, examples and exit codes.
+pub fn write_man_page(command: Command, out: &mut dyn Write) -> io::Result<()> {
+    let man = Man::new(
+        command
+            .after_help(None::<&str>)
+            .after_long_help(None::<&str>),
+    );
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+
+    let mut roff = Roff::new();
+    roff.control("SH", ["COMMANDS"]);
+    for subcommand in SUBCOMMANDS {
+        roff.control("TP", []);
+        roff.text([bold(usage(subcommand))]);
+        roff.text([roman(subcommand.about)]);
+    }
+    roff.control("PP", []);
+    roff.text([roman(NOTE)]);
+
+    roff.control("SH", ["EXAMPLES"]);
+    for (description, lines) in EXAMPLES {
+        roff.control("PP", []);
+        roff.text([roman(format!("{description}:"))]);
+        roff.control("RS", []);
+        roff.control("nf", []);
+        for line in *lines {
+            roff.text([bold(*line)]);
+        }
+        roff.control("fi", []);
+        roff.control("RE", []);
+    }
+
+    roff.control("SH", ["EXIT STATUS"]);
+    for (code, description) in EXIT_STATUS {
+        roff.control("TP", []);
+        roff.text([bold(code.to_string())]);
+        roff.text([roman(*description)]);
+    }
+    roff.to_writer(out)?;
+
+    man.render_version_section(out)
+}
+
+/// Returns the command line arguments `args`, starting with the program name, with a subcommand given as the first
+/// argument replaced by its flags. Other arguments are returned unchanged.
+pub fn expand<I>(args: I) -> Vec<OsString>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args: Vec<_> = args.into_iter().collect();
+    let subcommand = args.get(1).and_then(|arg| {
+        SUBCOMMANDS
+            .iter()
+            .find(|subcommand| arg.to_str() == Some(subcommand.name))
+    });
+    if let Some(subcommand) = subcommand {
+        args.splice(1..2, subcommand.flags.iter().map(OsString::from));
+    }
+    args
+}
@@ -0,0 +1,63 @@
+//! Compatibility with the output of other SFV tools.
+use std::{io, path::Path};
+
+use anyhow::Error;
+use clap::ValueEnum;
+
+use crate::{Record, Status};
+
+/// Width of the separator lines printed by cksfv.
+const CKSFV_WIDTH: usize = 80;
+
+/// Tools whose output can be mimicked.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compat {
+    /// cksfv, including its exit codes and verification messages on stderr
+    Cksfv,
+}
+
+/// Project web site line written by cksfv after the generator line.
+pub const CKSFV_WEB_SITE: &str = "; Project web site: http://zakalwe.fi/~shd/foss/cksfv/";
+
+/// Returns the message of an error without context, like `strerror` does for I/O errors.
+pub fn error_message(error: &Error) -> String {
+    match error.root_cause().downcast_ref::<io::Error>() {
+        Some(e) => {
+            let message = e.to_string();
+            match message.rfind(" (os error ") {
+                Some(i) => message[..i].to_string(),
+                None => message,
+            }
+        }
+        None => error.root_cause().to_string(),
+    }
+}
+
+/// Returns the line printed by cksfv before verifying `sfv_file`.
+pub fn cksfv_banner(sfv_file: &Path) -> String {
+    let banner = format!("--( Verifying: {} )", sfv_file.display());
+    let width = CKSFV_WIDTH.saturating_sub(banner.chars().count());
+    format!("{banner}{}", "-".repeat(width))
+}
+
+/// Returns the line printed by cksfv for a verified file, using the message of `error` for files which could not be
+/// read, see `error_message`.
+pub fn cksfv_line(record: &Record, error: Option<&str>) -> String {
+    let message = match record.status {
+        Status::Ok => "OK",
+        Status::Fail => "different CRC",
+        Status::Extra => "not listed",
+        Status::Missing | Status::Error => error.unwrap_or_default(),
+    };
+    format!("{:<49} {message}", record.path)
+}
+
+/// Returns the lines printed by cksfv after verifying all files.
+pub fn cksfv_summary(ok: bool) -> [String; 2] {
+    let result = if ok {
+        "Everything OK"
+    } else {
+        "Errors Occured"
+    };
+    ["-".repeat(CKSFV_WIDTH), result.to_string()]
+}
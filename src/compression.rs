@@ -0,0 +1,88 @@
+//! Decompression of compressed files, so that checksums can be computed over their contents.
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use lzma_rust2::XzReader;
+use ruzstd::decoding::{FrameDecoder, StreamingDecoder};
+
+/// Compression format of a file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Detects the compression format of the file at `path` from its extension, returns `None` if it is not
+    /// compressed.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gz" => Some(Compression::Gzip),
+            "xz" => Some(Compression::Xz),
+            "zst" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// Returns a reader of the decompressed contents of `reader`.
+    ///
+    /// Files consisting of several concatenated streams, as written by parallel compressors, are read to the end.
+    pub fn decoder<'a, R>(self, reader: R) -> io::Result<Box<dyn Read + 'a>>
+    where
+        R: Read + 'a,
+    {
+        Ok(match self {
+            Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Compression::Xz => Box::new(XzReader::new(reader, true)),
+            Compression::Zstd => Box::new(ZstdReader::new(reader)?),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+/// Reader of all frames of a Zstandard stream, since `StreamingDecoder` stops after the first one.
+struct ZstdReader<R: Read> {
+    /// Decoder of the current frame, `None` once the stream is finished or failed.
+    decoder: Option<StreamingDecoder<BufReader<R>, FrameDecoder>>,
+}
+
+impl<R: Read> ZstdReader<R> {
+    fn new(reader: R) -> io::Result<Self> {
+        let decoder = StreamingDecoder::new(BufReader::new(reader)).map_err(io::Error::other)?;
+        Ok(ZstdReader {
+            decoder: Some(decoder),
+        })
+    }
+}
+
+impl<R: Read> Read for ZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(mut decoder) = self.decoder.take() else {
+                return Ok(0);
+            };
+            let n = decoder.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.decoder = Some(decoder);
+                return Ok(n);
+            }
+
+            // The frame is finished, but another one may follow.
+            let (mut source, frame) = decoder.into_parts();
+            if source.fill_buf()?.is_empty() {
+                return Ok(0);
+            }
+            let decoder =
+                StreamingDecoder::new_with_decoder(source, frame).map_err(io::Error::other)?;
+            self.decoder = Some(decoder);
+        }
+    }
+}
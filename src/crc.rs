@@ -0,0 +1,273 @@
+//! Generic table-driven CRC engine for arbitrary parameters.
+//!
+//! CRCs are described using the Rocksoft model: width, polynomial, initial value, input and output reflection and a
+//! final XOR value. Widths from 1 to 64 bits are supported.
+use std::str::FromStr;
+
+/// Parameters of a CRC algorithm.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CrcParams {
+    pub name: &'static str,
+    pub width: u8,
+    pub poly: u64,
+    pub init: u64,
+    pub refin: bool,
+    pub refout: bool,
+    pub xorout: u64,
+}
+
+/// Name of parameter sets given by the user, which are told apart by `CrcParams::tag` instead.
+pub const CUSTOM: &str = "crc";
+
+/// Catalog of well-known CRC algorithms.
+#[rustfmt::skip]
+pub const CATALOG: &[CrcParams] = &[
+    CrcParams::new("crc-8/smbus", 8, 0x07, 0x00, false, false, 0x00),
+    CrcParams::new("crc-8/maxim-dow", 8, 0x31, 0x00, true, true, 0x00),
+    CrcParams::new("crc-16/arc", 16, 0x8005, 0x0000, true, true, 0x0000),
+    CrcParams::new("crc-16/ccitt", 16, 0x1021, 0x0000, true, true, 0x0000),
+    CrcParams::new("crc-16/ibm-3740", 16, 0x1021, 0xffff, false, false, 0x0000),
+    CrcParams::new("crc-16/xmodem", 16, 0x1021, 0x0000, false, false, 0x0000),
+    CrcParams::new("crc-16/modbus", 16, 0x8005, 0xffff, true, true, 0x0000),
+    CrcParams::new("crc-32/bzip2", 32, 0x04c11db7, 0xffffffff, false, false, 0xffffffff),
+    CrcParams::new("crc-32/mpeg-2", 32, 0x04c11db7, 0xffffffff, false, false, 0x00000000),
+    CrcParams::new("crc-32/cksum", 32, 0x04c11db7, 0x00000000, false, false, 0xffffffff),
+    CrcParams::new("crc-32/jamcrc", 32, 0x04c11db7, 0xffffffff, true, true, 0x00000000),
+    CrcParams::new("crc-64/xz", 64, 0x42f0e1eba9ea3693, 0xffffffffffffffff, true, true, 0xffffffffffffffff),
+    CrcParams::new("crc-64/ecma-182", 64, 0x42f0e1eba9ea3693, 0x0000000000000000, false, false, 0x0000000000000000),
+    CrcParams::new("crc-64/go-iso", 64, 0x000000000000001b, 0xffffffffffffffff, true, true, 0xffffffffffffffff),
+];
+
+impl CrcParams {
+    /// Creates a new set of CRC parameters.
+    pub const fn new(
+        name: &'static str,
+        width: u8,
+        poly: u64,
+        init: u64,
+        refin: bool,
+        refout: bool,
+        xorout: u64,
+    ) -> Self {
+        CrcParams {
+            name,
+            width,
+            poly,
+            init,
+            refin,
+            refout,
+            xorout,
+        }
+    }
+
+    /// Name of the algorithm as stored in checksum files, caches and databases.
+    ///
+    /// Catalog entries are named like `CRC-16/ARC`. Parameter sets given by the user are named after all of their
+    /// parameters, e.g. `CRC-w32-p04C11DB7-iFFFFFFFF-xFFFFFFFF-rr`, with the polynomial, initial value and final XOR
+    /// value in hex and the last two letters telling whether the input and output are reflected (`r`) or not (`n`).
+    pub fn tag(&self) -> String {
+        if self.name != CUSTOM {
+            return self.name.to_uppercase();
+        }
+
+        let digits = usize::from(self.width).div_ceil(4);
+        let reflected = |reflect| if reflect { 'r' } else { 'n' };
+        format!(
+            "CRC-w{}-p{:0digits$X}-i{:0digits$X}-x{:0digits$X}-{}{}",
+            self.width,
+            self.poly,
+            self.init,
+            self.xorout,
+            reflected(self.refin),
+            reflected(self.refout)
+        )
+    }
+
+    /// Returns the parameter set named by `tag` as returned by `tag` for parameter sets given by the user, ignoring
+    /// case.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let tag = tag.to_ascii_lowercase();
+        let mut fields = tag.strip_prefix("crc-w")?.split('-');
+        let width = fields.next()?;
+        let poly = fields.next()?.strip_prefix('p')?;
+        let init = fields.next()?.strip_prefix('i')?;
+        let xorout = fields.next()?.strip_prefix('x')?;
+        let reflected = |flag| match flag {
+            'r' => Some(true),
+            'n' => Some(false),
+            _ => None,
+        };
+        let mut flags = fields.next()?.chars();
+        let (refin, refout) = (reflected(flags.next()?)?, reflected(flags.next()?)?);
+        if flags.next().is_some() || fields.next().is_some() {
+            return None;
+        }
+
+        format!("width={width},poly=0x{poly},init=0x{init},xorout=0x{xorout},refin={refin},refout={refout}")
+            .parse()
+            .ok()
+    }
+
+    /// Number of bytes needed to store a checksum.
+    pub fn bytes(&self) -> usize {
+        usize::from(self.width).div_ceil(8)
+    }
+
+    /// Mask with the lowest `width` bits set.
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.width)
+    }
+}
+
+/// Parses custom CRC parameters.
+///
+/// The expected format is a comma separated list of `key=value` pairs, e.g.
+/// `width=16,poly=0x1021,init=0xffff,refin=false,refout=false,xorout=0`. `width` and `poly` are required, all other
+/// parameters default to zero or `false`. Numbers may be given in decimal or as hexadecimal with a `0x` prefix.
+impl FromStr for CrcParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn number(value: &str) -> Result<u64, String> {
+            match value
+                .strip_prefix("0x")
+                .or_else(|| value.strip_prefix("0X"))
+            {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => value.parse(),
+            }
+            .map_err(|e| format!("invalid number {value}: {e}"))
+        }
+
+        fn boolean(value: &str) -> Result<bool, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid boolean {value}, expected true or false"))
+        }
+
+        let mut params = CrcParams::new(CUSTOM, 0, 0, 0, false, false, 0);
+        let mut poly = None;
+        for pair in s.split(',') {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found {pair}"))?;
+            let value = value.trim();
+            match key.trim() {
+                "width" => {
+                    params.width = value
+                        .parse()
+                        .map_err(|e| format!("invalid width {value}: {e}"))?
+                }
+                "poly" => poly = Some(number(value)?),
+                "init" => params.init = number(value)?,
+                "refin" => params.refin = boolean(value)?,
+                "refout" => params.refout = boolean(value)?,
+                "xorout" => params.xorout = number(value)?,
+                key => return Err(format!("unknown parameter {key}")),
+            }
+        }
+
+        if !(1..=64).contains(&params.width) {
+            return Err("width must be between 1 and 64".to_string());
+        }
+        params.poly = poly.ok_or("poly is required")?;
+        let mask = params.mask();
+        if params.poly & !mask != 0 || params.init & !mask != 0 || params.xorout & !mask != 0 {
+            return Err(format!("parameters must fit in {} bits", params.width));
+        }
+
+        Ok(params)
+    }
+}
+
+/// Reverses the lowest `width` bits of `value`.
+fn reflect(value: u64, width: u8) -> u64 {
+    value.reverse_bits() >> (64 - width)
+}
+
+/// Incremental CRC computation for arbitrary parameters.
+pub struct Crc {
+    params: CrcParams,
+    table: [u64; 256],
+    crc: u64,
+}
+
+impl Crc {
+    /// Creates a new CRC computation, building the lookup table for `params`.
+    ///
+    /// Reflected CRCs keep the register in reflected form, all other CRCs keep it aligned to the top of a `u64` so that
+    /// widths below 8 bits need no special handling.
+    pub fn new(params: CrcParams) -> Self {
+        let mut table = [0; 256];
+        if params.refin {
+            let poly = reflect(params.poly, params.width);
+            for (i, entry) in table.iter_mut().enumerate() {
+                let mut r = i as u64;
+                for _ in 0..8 {
+                    r = if r & 1 != 0 { (r >> 1) ^ poly } else { r >> 1 };
+                }
+                *entry = r;
+            }
+        } else {
+            let poly = params.poly << (64 - params.width);
+            for (i, entry) in table.iter_mut().enumerate() {
+                let mut r = (i as u64) << 56;
+                for _ in 0..8 {
+                    r = if r & (1 << 63) != 0 {
+                        (r << 1) ^ poly
+                    } else {
+                        r << 1
+                    };
+                }
+                *entry = r;
+            }
+        }
+
+        let crc = if params.refin {
+            reflect(params.init, params.width)
+        } else {
+            params.init << (64 - params.width)
+        };
+
+        Crc { params, table, crc }
+    }
+
+    /// Parameters of this CRC.
+    pub fn params(&self) -> &CrcParams {
+        &self.params
+    }
+
+    /// Processes `data`, updating the register.
+    pub fn update(&mut self, data: &[u8]) {
+        if self.params.refin {
+            for &b in data {
+                self.crc = self.table[usize::from(self.crc as u8 ^ b)] ^ (self.crc >> 8);
+            }
+        } else {
+            for &b in data {
+                self.crc = self.table[usize::from((self.crc >> 56) as u8 ^ b)] ^ (self.crc << 8);
+            }
+        }
+    }
+
+    /// Returns the final CRC value.
+    pub fn finalize(&self) -> u64 {
+        let params = &self.params;
+        let crc = if params.refin {
+            if params.refout {
+                self.crc
+            } else {
+                reflect(self.crc, params.width)
+            }
+        } else {
+            let crc = self.crc >> (64 - params.width);
+            if params.refout {
+                reflect(crc, params.width)
+            } else {
+                crc
+            }
+        };
+
+        (crc ^ params.xorout) & params.mask()
+    }
+}
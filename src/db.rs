@@ -0,0 +1,161 @@
+//! Checksums stored in a SQLite database, an alternative to checksum files for large collections of files.
+//!
+//! Besides the checksum, the database records the size and modification time of each file and the results of every
+//! verification, so that the history of a file can be queried with `sqlite3`.
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error, Result};
+use rusqlite::{Connection, params};
+
+use crate::{hash::Algorithm, walk::path_from_bytes};
+
+/// Statements creating the tables if the database is new.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    size INTEGER,
+    mtime TEXT,
+    algorithm TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    hashed TEXT NOT NULL,
+    last_verified TEXT,
+    last_status TEXT
+);
+CREATE TABLE IF NOT EXISTS verifications (
+    path TEXT NOT NULL,
+    time TEXT NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS verifications_path ON verifications (path);
+";
+
+/// File listed in the database.
+pub struct Row {
+    /// Path as written when creating checksums, relative to the directory of the database unless absolute.
+    pub path: PathBuf,
+    pub size: Option<u64>,
+    /// Modification time in RFC 3339 format.
+    pub mtime: Option<String>,
+    pub algorithm: Algorithm,
+    pub checksum: String,
+}
+
+/// Open SQLite database of checksums.
+pub struct Database {
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it if it does not exist. Any error is propagated with added context.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .and_then(|conn| conn.execute_batch(SCHEMA).map(|()| conn))
+            .with_context(|| format!("Failed to open database {}", path.display()))?;
+        Ok(Database {
+            conn,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Stores `rows` hashed at `time`, replacing any rows with the same paths.
+    ///
+    /// The verification history of replaced files is kept, but their last verification is reset since it refers to
+    /// the old checksum. Any error is propagated with added context.
+    pub fn store(&mut self, rows: &[Row], time: &str) -> Result<()> {
+        let context = || format!("Failed to write to {}", self.path.display());
+        let tx = self.conn.transaction().with_context(context)?;
+        {
+            let mut insert = tx
+                .prepare(
+                    "INSERT INTO files (path, size, mtime, algorithm, checksum, hashed)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                     ON CONFLICT (path) DO UPDATE SET
+                         size = excluded.size,
+                         mtime = excluded.mtime,
+                         algorithm = excluded.algorithm,
+                         checksum = excluded.checksum,
+                         hashed = excluded.hashed,
+                         last_verified = NULL,
+                         last_status = NULL",
+                )
+                .with_context(context)?;
+            for row in rows {
+                insert
+                    .execute(params![
+                        row.path.to_string_lossy(),
+                        row.size.and_then(|size| i64::try_from(size).ok()),
+                        row.mtime,
+                        row.algorithm.tag(),
+                        row.checksum,
+                        time,
+                    ])
+                    .with_context(context)?;
+            }
+        }
+        tx.commit().with_context(context)
+    }
+
+    /// Returns all files listed in the database ordered by path. Any error, including an unknown algorithm, is
+    /// propagated with added context.
+    pub fn rows(&self) -> Result<Vec<Row>> {
+        let context = || format!("Failed to read database {}", self.path.display());
+        let mut select = self
+            .conn
+            .prepare("SELECT path, size, mtime, algorithm, checksum FROM files ORDER BY path")
+            .with_context(context)?;
+        let rows = select
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .and_then(Iterator::collect::<rusqlite::Result<Vec<_>>>)
+            .with_context(context)?;
+
+        rows.into_iter()
+            .map(|(path, size, mtime, algorithm, checksum)| {
+                let algorithm = Algorithm::from_tag(&algorithm).ok_or_else(|| {
+                    Error::msg(format!("Unknown algorithm {algorithm} for {path}"))
+                        .context(context())
+                })?;
+                Ok(Row {
+                    path: path_from_bytes(path.as_bytes()),
+                    size: size.and_then(|size| u64::try_from(size).ok()),
+                    mtime,
+                    algorithm,
+                    checksum,
+                })
+            })
+            .collect()
+    }
+
+    /// Records the status of verified files, given as pairs of path as listed and status, for a verification at
+    /// `time`. Any error is propagated with added context.
+    pub fn record_verification(&mut self, results: &[(PathBuf, &str)], time: &str) -> Result<()> {
+        let context = || format!("Failed to write to {}", self.path.display());
+        let tx = self.conn.transaction().with_context(context)?;
+        {
+            let mut update = tx
+                .prepare("UPDATE files SET last_verified = ?2, last_status = ?3 WHERE path = ?1")
+                .with_context(context)?;
+            let mut insert = tx
+                .prepare("INSERT INTO verifications (path, time, status) VALUES (?1, ?2, ?3)")
+                .with_context(context)?;
+            for (path, status) in results {
+                let path = path.to_string_lossy();
+                update
+                    .execute(params![path, time, status])
+                    .with_context(context)?;
+                insert
+                    .execute(params![path, time, status])
+                    .with_context(context)?;
+            }
+        }
+        tx.commit().with_context(context)
+    }
+}
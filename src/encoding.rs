@@ -0,0 +1,115 @@
+//! Text encodings of checksum files.
+//!
+//! Checksum files are handled as UTF-8 internally. Legacy Windows tools write them in the ANSI or OEM code page, which
+//! are converted from and to UTF-8 when reading and writing.
+use std::{borrow::Cow, sync::LazyLock};
+
+use anyhow::{Error, Result};
+use clap::ValueEnum;
+
+/// Byte order mark at the start of UTF-8 files written by some Windows tools.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Characters of bytes `0x80` to `0xFF` in code page 1252. Bytes undefined in CP1252 map to the C1 control character
+/// with the same value, like Windows does.
+const CP1252_HIGH: &str = concat!(
+    "€\u{81}‚ƒ„…†‡ˆ‰Š‹Œ\u{8D}Ž\u{8F}",
+    "\u{90}‘’“”•–—˜™š›œ\u{9D}žŸ",
+    "\u{A0}¡¢£¤¥¦§¨©ª«¬\u{AD}®¯",
+    "°±²³´µ¶·¸¹º»¼½¾¿",
+    "ÀÁÂÃÄÅÆÇÈÉÊËÌÍÎÏ",
+    "ÐÑÒÓÔÕÖ×ØÙÚÛÜÝÞß",
+    "àáâãäåæçèéêëìíîï",
+    "ðñòóôõö÷øùúûüýþÿ",
+);
+
+/// Characters of bytes `0x80` to `0xFF` in code page 437, the original IBM PC character set.
+const CP437_HIGH: &str = concat!(
+    "ÇüéâäàåçêëèïîìÄÅ",
+    "ÉæÆôöòûùÿÖÜ¢£¥₧ƒ",
+    "áíóúñÑªº¿⌐¬½¼¡«»",
+    "░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+    "└┴┬├─┼╞╟╚╔╩╦╠═╬╧",
+    "╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+    "αßΓπΣσµτΦΘΩδ∞φε∩",
+    "≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{A0}",
+);
+
+/// Decoding table of code page 1252.
+static CP1252: LazyLock<Vec<char>> = LazyLock::new(|| CP1252_HIGH.chars().collect());
+/// Decoding table of code page 437.
+static CP437: LazyLock<Vec<char>> = LazyLock::new(|| CP437_HIGH.chars().collect());
+
+/// Supported encodings of checksum files.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// UTF-8, a byte order mark is ignored when reading
+    #[value(name = "utf-8")]
+    Utf8,
+    /// UTF-8 with a byte order mark, as expected by some Windows tools
+    #[value(name = "utf-8-bom")]
+    Utf8Bom,
+    /// Windows-1252, the ANSI code page of western Windows systems
+    Cp1252,
+    /// Code page 437, the OEM code page used by DOS tools
+    Cp437,
+}
+
+impl Encoding {
+    /// Characters of bytes `0x80` to `0xFF`, `None` for UTF-8.
+    fn high_chars(self) -> Option<&'static [char]> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => None,
+            Encoding::Cp1252 => Some(&CP1252),
+            Encoding::Cp437 => Some(&CP437),
+        }
+    }
+
+    /// Converts the contents of a checksum file to UTF-8.
+    ///
+    /// A leading byte order mark is removed from UTF-8 files. Bytes which are not valid UTF-8 are kept as they are, so
+    /// that file names which are not valid UTF-8 still verify.
+    pub fn decode(self, data: &[u8]) -> Cow<'_, [u8]> {
+        let Some(high) = self.high_chars() else {
+            return Cow::Borrowed(data.strip_prefix(BOM).unwrap_or(data));
+        };
+
+        let text: String = data
+            .iter()
+            .map(|&b| match b {
+                0x00..=0x7F => char::from(b),
+                _ => high[usize::from(b - 0x80)],
+            })
+            .collect();
+        Cow::Owned(text.into_bytes())
+    }
+
+    /// Converts UTF-8 text to be written to a checksum file.
+    ///
+    /// For UTF-8 the text is written as is, with a byte order mark prepended for `Utf8Bom`. Returns an error if the text
+    /// contains characters which cannot be represented in a code page.
+    pub fn encode(self, text: &[u8]) -> Result<Vec<u8>> {
+        let Some(high) = self.high_chars() else {
+            let bom = if self == Encoding::Utf8Bom { BOM } else { b"" };
+            return Ok([bom, text].concat());
+        };
+
+        let text = str::from_utf8(text).map_err(|_| {
+            Error::msg("File names which are not valid UTF-8 cannot be written in a code page")
+        })?;
+        text.chars()
+            .map(|c| match c {
+                '\0'..='\x7F' => Ok(c as u8),
+                _ => high
+                    .iter()
+                    .position(|&h| h == c)
+                    .map(|i| 0x80 + i as u8)
+                    .ok_or_else(|| {
+                        Error::msg(format!(
+                            "Character {c} cannot be represented in the selected encoding"
+                        ))
+                    }),
+            })
+            .collect()
+    }
+}
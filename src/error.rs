@@ -0,0 +1,117 @@
+//! Errors of hashing files and reading or writing checksum files, which applications can match on.
+//!
+//! Modes of the command return `anyhow` errors, which add context to these errors, so that they can still be found
+//! with `anyhow::Error::downcast_ref` or in its `chain`.
+use std::{io, path::PathBuf};
+
+/// Any error which caused parsing or serializing a checksum file to fail.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Result of the core library.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Number of malformed line numbers listed in messages.
+const MAX_LISTED: usize = 20;
+
+/// Errors of the core library.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A file could not be opened.
+    #[error("Failed to open file {}", path.display())]
+    OpenFailed {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A file could not be read after it was opened.
+    #[error("Error while reading file {}", path.display())]
+    ReadFailed {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// The target of a symbolic link could not be read.
+    #[error("Failed to read link {}", path.display())]
+    ReadLinkFailed {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// Lines of an SFV style checksum file could not be parsed, with their line numbers starting at 1.
+    #[error("Malformed lines {}", list_lines(line_numbers))]
+    MalformedLines { line_numbers: Vec<usize> },
+    /// A checksum file in another format could not be parsed, `what` names the format and the part which failed.
+    #[error("Failed to parse {what}")]
+    ParseFailed {
+        what: String,
+        #[source]
+        source: BoxError,
+    },
+    /// A table lacks a column with paths or checksums.
+    #[error("Header row lacks path or checksum column")]
+    MissingColumns,
+    /// A checksum file names an algorithm other than the one it was read with.
+    #[error("Checksum file uses algorithm {found}, select it with --algorithm")]
+    WrongAlgorithm { found: String },
+    /// A checksum file listing checksums of several algorithms has none of the one it was read with.
+    #[error(
+        "Checksum file contains no {algorithm} checksums, select another algorithm with --algorithm"
+    )]
+    NoChecksums { algorithm: String },
+    /// A checksum file lists a path with different checksums.
+    #[error("Conflicting checksums {first} and {second} for {}", path.display())]
+    ConflictingChecksums {
+        path: PathBuf,
+        first: String,
+        second: String,
+    },
+    /// A path is listed both as a file and as a directory, or more than once.
+    #[error("Conflicting entries for {}", path.display())]
+    ConflictingEntries { path: PathBuf },
+    /// A path cannot be written to a format which requires UTF-8.
+    #[error("File name {} is not valid UTF-8 and cannot be written to {format}", path.display())]
+    NotUtf8 { path: PathBuf, format: &'static str },
+    /// A checksum file could not be serialized.
+    #[error("Failed to serialize {format}")]
+    SerializeFailed {
+        format: &'static str,
+        #[source]
+        source: BoxError,
+    },
+    /// Hashing was cancelled, see `progress::Observer::is_cancelled`.
+    #[error("Cancelled")]
+    Cancelled,
+    /// A file does not match its expected checksum.
+    #[error("{} has checksum {actual} instead of {expected}", path.display())]
+    Mismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Error {
+    /// Whether the error was caused by a file which does not exist.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::OpenFailed { source, .. }
+            | Error::ReadFailed { source, .. }
+            | Error::ReadLinkFailed { source, .. } => source.kind() == io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
+}
+
+/// Returns the first `MAX_LISTED` of `line_numbers` separated by commas, followed by the count of the others.
+fn list_lines(line_numbers: &[usize]) -> String {
+    let mut listed = line_numbers
+        .iter()
+        .take(MAX_LISTED)
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if line_numbers.len() > MAX_LISTED {
+        listed.push_str(&format!(" and {} more", line_numbers.len() - MAX_LISTED));
+    }
+    listed
+}
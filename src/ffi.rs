@@ -0,0 +1,353 @@
+//! C ABI of the core engine, available with the `ffi` feature, so that C and C++ tools can link against it.
+//!
+//! The shared library is built with `cargo build --release --features ffi`, e.g. as `target/release/libcrc32.so`. The
+//! header `include/crc32.h` is generated from this module by cbindgen using `cbindgen.toml`. Functions return
+//! `CRC32_OK` on success and `CRC32_ERROR` otherwise, in which case `crc32_last_error` describes the error. Strings
+//! returned by the library are released with `crc32_free_string`. Nothing is printed on stdout, results of verification
+//! are passed to a callback instead.
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int, c_void},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+};
+
+use anyhow::{Error, Result};
+use clap::ValueEnum;
+
+use crate::{
+    Format, ManifestOptions, OutputOptions, ScanOptions, Status, Summary, VerifyOptions,
+    create_sfv,
+    encoding::Encoding,
+    hash::{self, Algorithm},
+    progress::{Event, Observer},
+    table::DEFAULT_COLUMNS,
+    verify_sfv,
+    walk::path_from_bytes,
+};
+
+/// Returned by functions which succeeded.
+pub const CRC32_OK: c_int = 0;
+/// Returned by functions which failed, see `crc32_last_error`.
+pub const CRC32_ERROR: c_int = -1;
+
+thread_local! {
+    /// Message of the last error on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Status of a verified file.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum Crc32Status {
+    Ok,
+    Fail,
+    /// The file does not exist.
+    Missing,
+    /// The file exists but is not listed in the checksum file.
+    Extra,
+    /// The file cannot be read.
+    Error,
+}
+
+impl From<Status> for Crc32Status {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Ok => Crc32Status::Ok,
+            Status::Fail => Crc32Status::Fail,
+            Status::Missing => Crc32Status::Missing,
+            Status::Extra => Crc32Status::Extra,
+            Status::Error => Crc32Status::Error,
+        }
+    }
+}
+
+/// Counts of processed files by status.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Crc32Summary {
+    pub files: usize,
+    pub ok: usize,
+    pub failed: usize,
+    pub missing: usize,
+    pub extra: usize,
+    pub errors: usize,
+    /// Total size of the files which were read.
+    pub bytes: u64,
+}
+
+impl From<&Summary> for Crc32Summary {
+    fn from(summary: &Summary) -> Self {
+        Crc32Summary {
+            files: summary.files,
+            ok: summary.ok,
+            failed: summary.failed,
+            missing: summary.missing,
+            extra: summary.extra,
+            errors: summary.errors,
+            bytes: summary.bytes,
+        }
+    }
+}
+
+/// Callback receiving the path, status and user data of each verified file. The path is only valid during the call.
+pub type Crc32ResultCallback =
+    Option<unsafe extern "C" fn(path: *const c_char, status: Crc32Status, user_data: *mut c_void)>;
+
+/// Observer passing the results of files to a C callback, while dropping everything printed on stdout.
+struct Callback {
+    on_result: Crc32ResultCallback,
+    user_data: *mut c_void,
+}
+
+// Results are reported from the thread running a mode, so the callback and its user data are only used by the thread
+// which passed them.
+unsafe impl Sync for Callback {}
+
+impl Observer for Callback {
+    fn event(&self, event: &Event) {
+        let (Some(on_result), Event::FileResult(record)) = (self.on_result, event) else {
+            return;
+        };
+        let Ok(path) = CString::new(record.path.as_str()) else {
+            return;
+        };
+        // SAFETY: The caller of `crc32_verify` guarantees that the callback can be called with its user data.
+        unsafe { on_result(path.as_ptr(), record.status.into(), self.user_data) };
+    }
+
+    fn print(&self, _print: &mut dyn FnMut()) {}
+}
+
+/// Runs `f`, turning errors and panics into `CRC32_ERROR` and keeping their message for `crc32_last_error`.
+fn call<F>(f: F) -> c_int
+where
+    F: FnOnce() -> Result<()>,
+{
+    let result = panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(Error::msg("Internal error, the library panicked")));
+    match result {
+        Ok(()) => CRC32_OK,
+        Err(e) => {
+            let message = CString::new(format!("{e:#}").replace('\0', " ")).ok();
+            LAST_ERROR.with(|last| *last.borrow_mut() = message);
+            CRC32_ERROR
+        }
+    }
+}
+
+/// Converts a C string argument to a path.
+///
+/// # Safety
+///
+/// `path` must be null or a valid NUL-terminated string.
+unsafe fn path_arg(path: *const c_char, name: &str) -> Result<PathBuf> {
+    if path.is_null() {
+        return Err(Error::msg(format!("{name} must not be null")));
+    }
+    // SAFETY: Guaranteed by the caller.
+    let bytes = unsafe { CStr::from_ptr(path) }.to_bytes();
+    Ok(path_from_bytes(bytes))
+}
+
+/// Converts a C string naming an algorithm, such as `"sha256"`, to an algorithm. Null selects `None`.
+///
+/// # Safety
+///
+/// `algorithm` must be null or a valid NUL-terminated string.
+unsafe fn algorithm_arg(algorithm: *const c_char) -> Result<Option<Algorithm>> {
+    if algorithm.is_null() {
+        return Ok(None);
+    }
+    // SAFETY: Guaranteed by the caller.
+    let name = unsafe { CStr::from_ptr(algorithm) }.to_string_lossy();
+    Algorithm::from_str(&name, true)
+        .map(Some)
+        .map_err(|_| Error::msg(format!("Unknown algorithm {name}")))
+}
+
+/// Options of modes which print nothing, as results are returned to the caller instead.
+fn silent_output() -> OutputOptions {
+    OutputOptions {
+        format: Format::Text,
+        columns: DEFAULT_COLUMNS.to_vec(),
+        template: None,
+        print0: false,
+        quiet: false,
+        status: true,
+    }
+}
+
+/// Options of checksum files written to or read from `out_file`, with the format detected from their name.
+fn manifest_options(out_file: Option<PathBuf>) -> ManifestOptions {
+    ManifestOptions {
+        header: false,
+        compat: None,
+        out_file,
+        db: None,
+        cache: None,
+        par2: None,
+        xattr: false,
+        update: false,
+        encoding: Encoding::Utf8,
+        format: None,
+        strict: false,
+    }
+}
+
+/// Computes the checksum of the file at `path` using `algorithm`, or CRC32 if it is null, and stores it as a hex string
+/// in `checksum`, which must be released with `crc32_free_string`.
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string, `algorithm` null or a valid NUL-terminated string and `checksum` a
+/// valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crc32_hash_file(
+    path: *const c_char,
+    algorithm: *const c_char,
+    checksum: *mut *mut c_char,
+) -> c_int {
+    call(|| {
+        // SAFETY: Guaranteed by the caller.
+        let path = unsafe { path_arg(path, "path") }?;
+        // SAFETY: Guaranteed by the caller.
+        let algorithm = unsafe { algorithm_arg(algorithm) }?.unwrap_or(Algorithm::Crc32);
+        if checksum.is_null() {
+            return Err(Error::msg("checksum must not be null"));
+        }
+
+        let hex = hash::hash_file(&path, algorithm)?;
+        let hex = CString::new(hex).map_err(Error::msg)?;
+        // SAFETY: Guaranteed by the caller.
+        unsafe { *checksum = hex.into_raw() };
+        Ok(())
+    })
+}
+
+/// Computes the checksums of the `count` files or directories in `paths` using `algorithm`, or CRC32 if it is null, and
+/// writes them to the checksum file `out_file`, like `crc32 -o`.
+///
+/// The format of the checksum file is detected from its name. Directories are searched recursively if `recursive` is
+/// true. Hashing stops at the first file which cannot be read. The counts of hashed files are stored in `summary` unless
+/// it is null.
+///
+/// # Safety
+///
+/// `paths` must point to `count` valid NUL-terminated strings, `out_file` must be a valid NUL-terminated string,
+/// `algorithm` null or a valid NUL-terminated string and `summary` null or a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crc32_create_manifest(
+    paths: *const *const c_char,
+    count: usize,
+    out_file: *const c_char,
+    algorithm: *const c_char,
+    recursive: bool,
+    summary: *mut Crc32Summary,
+) -> c_int {
+    call(|| {
+        if paths.is_null() && count > 0 {
+            return Err(Error::msg("paths must not be null"));
+        }
+        let paths = (0..count)
+            // SAFETY: Guaranteed by the caller.
+            .map(|i| unsafe { path_arg(*paths.add(i), "path") })
+            .collect::<Result<Vec<_>>>()?;
+        // SAFETY: Guaranteed by the caller.
+        let out_file = unsafe { path_arg(out_file, "out_file") }?;
+        // SAFETY: Guaranteed by the caller.
+        let algorithm = unsafe { algorithm_arg(algorithm) }?.unwrap_or(Algorithm::Crc32);
+
+        let scan_options = ScanOptions::new().recursive(recursive).algorithm(algorithm);
+        let observer = Callback {
+            on_result: None,
+            user_data: ptr::null_mut(),
+        };
+        let result = create_sfv(
+            paths,
+            &scan_options,
+            &manifest_options(Some(out_file)),
+            Some(&observer),
+            &silent_output(),
+        )?;
+        if !summary.is_null() {
+            // SAFETY: Guaranteed by the caller.
+            unsafe { *summary = (&result).into() };
+        }
+        Ok(())
+    })
+}
+
+/// Verifies the checksum file `manifest`, like `crc32 --verify`.
+///
+/// Listed files are hashed using `algorithm`, or the algorithm detected from the checksum file if it is null. Relative
+/// paths are resolved against the directory containing the checksum file. `on_result` is called with the result of
+/// each file unless it is null, on the calling thread and in the order the files are listed, passing `user_data`. The
+/// counts of verified files by status are stored in `summary` unless it is null. Files which do not match, are missing
+/// or cannot be read are not errors, only reading the checksum file itself can fail.
+///
+/// # Safety
+///
+/// `manifest` must be a valid NUL-terminated string, `algorithm` null or a valid NUL-terminated string and `summary`
+/// null or a valid pointer. `on_result` must be safe to call with `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crc32_verify(
+    manifest: *const c_char,
+    algorithm: *const c_char,
+    on_result: Crc32ResultCallback,
+    user_data: *mut c_void,
+    summary: *mut Crc32Summary,
+) -> c_int {
+    call(|| {
+        // SAFETY: Guaranteed by the caller.
+        let manifest = unsafe { path_arg(manifest, "manifest") }?;
+        // SAFETY: Guaranteed by the caller.
+        let algorithm = unsafe { algorithm_arg(algorithm) }?;
+
+        let scan_options = ScanOptions::new().algorithm(algorithm.unwrap_or(Algorithm::Crc32));
+        let verify_options = VerifyOptions::new().detect_algorithm(algorithm.is_none());
+        let observer = Callback {
+            on_result,
+            user_data,
+        };
+        let result = verify_sfv(
+            &[manifest],
+            &scan_options,
+            &manifest_options(None),
+            &verify_options,
+            Some(&observer),
+            &silent_output(),
+        )?;
+        if !summary.is_null() {
+            // SAFETY: Guaranteed by the caller.
+            unsafe { *summary = (&result).into() };
+        }
+        Ok(())
+    })
+}
+
+/// Returns the message of the last error on the calling thread, or null if no function failed yet.
+///
+/// The message is owned by the library and valid until the next call failing on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn crc32_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Releases a string returned by the library. Does nothing if `string` is null.
+///
+/// # Safety
+///
+/// `string` must be null or a string returned by the library which was not released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn crc32_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        // SAFETY: Guaranteed by the caller.
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
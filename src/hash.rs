@@ -0,0 +1,682 @@
+//! Checksum algorithms supported for hashing files.
+use std::{
+    borrow::Cow,
+    fmt::Write,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    sync::LazyLock,
+};
+
+use clap::{ValueEnum, builder::PossibleValue};
+use digest::DynDigest;
+use xxhash_rust::{xxh3, xxh32, xxh64};
+
+use crate::{
+    crc::{CATALOG, Crc, CrcParams},
+    error::{Error, Result},
+    hash_reader,
+};
+
+/// Minimum input size for which BLAKE3 hashes data using multiple threads.
+const BLAKE3_PARALLEL_SIZE: usize = 128 * 1024;
+
+/// Supported checksum algorithms.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// CRC-32 (IEEE), as used in SFV files
+    Crc32,
+    /// CRC-32C (Castagnoli)
+    Crc32c,
+    /// CRC with arbitrary parameters
+    Crc(CrcParams),
+    /// MD5
+    Md5,
+    /// SHA-1
+    Sha1,
+    /// SHA-224
+    Sha224,
+    /// SHA-256
+    Sha256,
+    /// SHA-384
+    Sha384,
+    /// SHA-512
+    Sha512,
+    /// BLAKE3
+    Blake3,
+    /// XXH32
+    Xxh32,
+    /// XXH64
+    Xxh64,
+    /// XXH3 (64 bit)
+    Xxh3,
+    /// XXH128
+    Xxh128,
+}
+
+/// All selectable algorithms, including the CRC catalog.
+static VARIANTS: LazyLock<Vec<Algorithm>> = LazyLock::new(|| {
+    let mut variants = vec![Algorithm::Crc32, Algorithm::Crc32c];
+    variants.extend(CATALOG.iter().copied().map(Algorithm::Crc));
+    variants.extend([
+        Algorithm::Md5,
+        Algorithm::Sha1,
+        Algorithm::Sha224,
+        Algorithm::Sha256,
+        Algorithm::Sha384,
+        Algorithm::Sha512,
+        Algorithm::Blake3,
+        Algorithm::Xxh32,
+        Algorithm::Xxh64,
+        Algorithm::Xxh3,
+        Algorithm::Xxh128,
+    ]);
+    variants
+});
+
+impl ValueEnum for Algorithm {
+    fn value_variants<'a>() -> &'a [Self] {
+        &VARIANTS
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        let value = match self {
+            Algorithm::Crc32 => {
+                PossibleValue::new("crc32").help("CRC-32 (IEEE), as used in SFV files")
+            }
+            Algorithm::Crc32c => PossibleValue::new("crc32c").help("CRC-32C (Castagnoli)"),
+            Algorithm::Crc(params) => PossibleValue::new(params.name),
+            Algorithm::Md5 => PossibleValue::new("md5").help("MD5"),
+            Algorithm::Sha1 => PossibleValue::new("sha1").help("SHA-1"),
+            Algorithm::Sha224 => PossibleValue::new("sha224").help("SHA-224"),
+            Algorithm::Sha256 => PossibleValue::new("sha256").help("SHA-256"),
+            Algorithm::Sha384 => PossibleValue::new("sha384").help("SHA-384"),
+            Algorithm::Sha512 => PossibleValue::new("sha512").help("SHA-512"),
+            Algorithm::Blake3 => PossibleValue::new("blake3").help("BLAKE3"),
+            Algorithm::Xxh32 => PossibleValue::new("xxh32").help("XXH32"),
+            Algorithm::Xxh64 => PossibleValue::new("xxh64").help("XXH64"),
+            Algorithm::Xxh3 => PossibleValue::new("xxh3").help("XXH3 (64 bit)"),
+            Algorithm::Xxh128 => PossibleValue::new("xxh128").help("XXH128"),
+        };
+        Some(value)
+    }
+}
+
+impl Algorithm {
+    /// Whether checksums of this algorithm are stored in SFV files rather than coreutils style digest files.
+    pub fn is_sfv(self) -> bool {
+        matches!(
+            self,
+            Algorithm::Crc32 | Algorithm::Crc32c | Algorithm::Crc(_)
+        )
+    }
+
+    /// Extension of checksum files for this algorithm, e.g. `sfv` or `sha256` as used by `sha256sum` users.
+    pub fn extension(self) -> String {
+        if self.is_sfv() {
+            "sfv".to_string()
+        } else {
+            self.tag().to_lowercase()
+        }
+    }
+
+    /// Name of the algorithm in BSD style lines such as `SHA256 (path) = checksum`.
+    pub fn tag(self) -> String {
+        match self {
+            Algorithm::Crc32 => "CRC32".to_string(),
+            Algorithm::Crc32c => "CRC32C".to_string(),
+            Algorithm::Crc(params) => params.tag(),
+            Algorithm::Md5 => "MD5".to_string(),
+            Algorithm::Sha1 => "SHA1".to_string(),
+            Algorithm::Sha224 => "SHA224".to_string(),
+            Algorithm::Sha256 => "SHA256".to_string(),
+            Algorithm::Sha384 => "SHA384".to_string(),
+            Algorithm::Sha512 => "SHA512".to_string(),
+            Algorithm::Blake3 => "BLAKE3".to_string(),
+            Algorithm::Xxh32 => "XXH32".to_string(),
+            Algorithm::Xxh64 => "XXH64".to_string(),
+            Algorithm::Xxh3 => "XXH3".to_string(),
+            Algorithm::Xxh128 => "XXH128".to_string(),
+        }
+    }
+
+    /// Whether `tag` names this algorithm, ignoring case and dashes.
+    ///
+    /// Names used by `openssl dgst` such as `SHA2-256` are accepted as well.
+    pub fn matches_tag(self, tag: &str) -> bool {
+        fn normalize(tag: &str) -> String {
+            tag.to_uppercase().replace("SHA2-", "SHA").replace('-', "")
+        }
+
+        normalize(tag) == normalize(&self.tag())
+    }
+
+    /// Returns the algorithm named by `tag`, see `matches_tag`, including CRCs with parameters given by the user.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        VARIANTS
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.matches_tag(tag))
+            .or_else(|| CrcParams::from_tag(tag).map(Algorithm::Crc))
+    }
+
+    /// Returns the algorithm named by the file name of `path`.
+    ///
+    /// These are checksum files with the extension of an algorithm, see `extension`, and files such as `MD5SUMS` or
+    /// `SHA256SUMS` as published along with Linux distribution images.
+    pub fn from_file_name(path: &Path) -> Option<Self> {
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str())
+            && let Some(algorithm) = VARIANTS
+                .iter()
+                .copied()
+                .find(|algorithm| algorithm.extension().eq_ignore_ascii_case(ext))
+        {
+            return Some(algorithm);
+        }
+
+        let name = path.file_name()?.to_str()?.to_uppercase();
+        Self::from_tag(name.strip_suffix("SUMS")?)
+    }
+
+    /// Guesses the algorithm of a checksum from its length.
+    ///
+    /// Checksums of 8 digits are assumed to be CRC-32 if written in uppercase like in SFV files and XXH32 otherwise.
+    /// Checksums of 32 digits are assumed to be MD5 and of 64 digits SHA-256, which are far more common than XXH128 and
+    /// BLAKE3.
+    pub fn from_checksum(checksum: &str) -> Option<Self> {
+        if let Some(hex) = checksum.strip_prefix("XXH3_") {
+            return (hex.len() == 16).then_some(Algorithm::Xxh3);
+        }
+        if !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        match checksum.len() {
+            8 if checksum.bytes().any(|b| b.is_ascii_lowercase()) => Some(Algorithm::Xxh32),
+            8 => Some(Algorithm::Crc32),
+            16 => Some(Algorithm::Xxh64),
+            32 => Some(Algorithm::Md5),
+            40 => Some(Algorithm::Sha1),
+            56 => Some(Algorithm::Sha224),
+            64 => Some(Algorithm::Sha256),
+            96 => Some(Algorithm::Sha384),
+            128 => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Guesses the algorithm of a line of a checksum file, see `parse_line` for the supported forms.
+    ///
+    /// BSD style lines name the algorithm. Lines of the form `checksum  path` are digest lines, whose algorithm is
+    /// guessed from the length of the checksum, see `from_checksum`. Any other line is assumed to be an SFV line.
+    pub fn from_line(line: &[u8]) -> Option<Self> {
+        if let Some(algorithm) = Self::from_bsd_line(line) {
+            return Some(algorithm);
+        }
+
+        let line = line.strip_prefix(b"\\").unwrap_or(line);
+        let split = line.iter().position(|&b| b == b' ' || b == b'\t')?;
+        let (checksum, path) = line.split_at(split);
+        match path {
+            [b'\t', ..] | [b' ', b' ' | b'*', ..] => {
+                Self::from_checksum(str::from_utf8(checksum).ok()?)
+            }
+            _ => Some(Algorithm::Crc32),
+        }
+    }
+
+    /// Returns the algorithm named by a BSD style line, or `None` if it is not one.
+    pub fn from_bsd_line(line: &[u8]) -> Option<Self> {
+        let line = line.strip_prefix(b"\\").unwrap_or(line);
+        let open = line.iter().position(|&b| b == b'(')?;
+        let algorithm = Self::from_tag(str::from_utf8(&line[..open]).ok()?.trim())?;
+        algorithm.parse_bsd_line(line).map(|_| algorithm)
+    }
+
+    /// Formats checksum bytes as a hexadecimal string.
+    ///
+    /// SFV checksums are written in uppercase, digests in lowercase like coreutils does. XXH3 checksums are prefixed with
+    /// `XXH3_` like `xxhsum` does.
+    pub fn to_hex(self, checksum: &[u8]) -> String {
+        let prefix = match self {
+            Algorithm::Xxh3 => "XXH3_",
+            _ => "",
+        };
+        checksum.iter().fold(prefix.to_string(), |mut hex, b| {
+            let _ = if self.is_sfv() {
+                write!(hex, "{b:02X}")
+            } else {
+                write!(hex, "{b:02x}")
+            };
+            hex
+        })
+    }
+
+    /// Whether `checksum` consists of as many hex digits as checksums of this algorithm have, optionally prefixed with
+    /// `XXH3_`.
+    pub fn is_valid_hex(self, checksum: &str) -> bool {
+        let hex = match checksum.get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("XXH3_") => &checksum[5..],
+            _ => checksum,
+        };
+        let digits = Hasher::new(self).finalize().len() * 2;
+        hex.len() == digits && hex.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    /// Returns `checksum` in the form written by `to_hex`, e.g. to normalize checksums written by other tools.
+    pub fn normalize_hex(self, checksum: &str) -> String {
+        let hex = match checksum.get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("XXH3_") => &checksum[5..],
+            _ => checksum,
+        };
+        let hex = if self.is_sfv() {
+            hex.to_uppercase()
+        } else {
+            hex.to_lowercase()
+        };
+        match self {
+            Algorithm::Xxh3 => format!("XXH3_{hex}"),
+            _ => hex,
+        }
+    }
+
+    /// Formats a single line of a checksum file.
+    ///
+    /// SFV lines have the form `path CHECKSUM`, digest lines the form `checksum  path` as written by `md5sum` and
+    /// `sha256sum`. Paths are raw bytes, so names which are not valid UTF-8 are written unchanged. Names which would
+    /// break the line format are escaped, see `escape`, unless `style.raw` is specified. See `LineStyle` for the other
+    /// options.
+    pub fn format_line(self, path: &[u8], checksum: &str, style: LineStyle) -> Vec<u8> {
+        let escaped = if style.raw {
+            None
+        } else {
+            escape(path, style.backslash_separator)
+        };
+        let (marker, path) = match escaped {
+            Some(escaped) => (&b"\\"[..], Cow::Owned(escaped)),
+            None => (&b""[..], Cow::Borrowed(path)),
+        };
+        if style.bsd {
+            let checksum = checksum.strip_prefix("XXH3_").unwrap_or(checksum);
+            let tag = self.tag();
+            [
+                marker,
+                tag.as_bytes(),
+                b" (",
+                &path,
+                b") = ",
+                checksum.as_bytes(),
+            ]
+            .concat()
+        } else if self.is_sfv() {
+            [marker, &path, b" ", checksum.as_bytes()].concat()
+        } else {
+            [marker, checksum.as_bytes(), b"  ", &path].concat()
+        }
+    }
+
+    /// Parses a single non-comment line of a checksum file into path and checksum.
+    ///
+    /// Returns `None` if the line is malformed. BSD style lines are accepted if their tag matches the algorithm. The
+    /// binary mode marker `*` of digest lines is accepted and ignored. Lines starting with a backslash contain an escaped
+    /// path, see `escape`. Checksums should be compared ignoring case.
+    pub fn parse_line(self, line: &[u8]) -> Option<(Cow<'_, [u8]>, String)> {
+        if let Some(line) = line.strip_prefix(b"\\") {
+            let (path, checksum) = self.parse_unescaped_line(line)?;
+            return Some((Cow::Owned(unescape(path)?), checksum));
+        }
+
+        self.parse_unescaped_line(line)
+            .map(|(path, checksum)| (Cow::Borrowed(path), checksum))
+    }
+
+    /// Parses a line without escape marker into path and checksum.
+    ///
+    /// SFV lines are split at the last space or tab, so separators of any length are accepted. Checksums written
+    /// without leading zeros, as done by some old tools, are padded. Digest lines may separate checksum and path by a
+    /// tab as well.
+    fn parse_unescaped_line(self, line: &[u8]) -> Option<(&[u8], String)> {
+        if let Some(entry) = self.parse_bsd_line(line) {
+            return Some(entry);
+        }
+
+        if self.is_sfv() {
+            let hex_len = match self {
+                Algorithm::Crc(params) => 2 * params.bytes(),
+                _ => 8,
+            };
+            let split = line.iter().rposition(|&b| b == b' ' || b == b'\t')?;
+            let (path, checksum) = (line[..split].trim_ascii(), &line[split + 1..]);
+            if path.is_empty()
+                || !(1..=hex_len).contains(&checksum.len())
+                || !checksum.iter().all(u8::is_ascii_hexdigit)
+            {
+                return None;
+            }
+
+            let checksum = str::from_utf8(checksum).ok()?.to_uppercase();
+            Some((path, format!("{checksum:0>hex_len$}")))
+        } else {
+            let split = line.iter().position(|&b| b == b' ' || b == b'\t')?;
+            let (checksum, path) = line.split_at(split);
+            let path = match path {
+                [b'\t', path @ ..] | [b' ', b' ' | b'*', path @ ..] => path,
+                _ => return None,
+            };
+            Some((path, str::from_utf8(checksum).ok()?.to_string()))
+        }
+    }
+
+    /// Parses a BSD style line of the form `TAG (path) = checksum`, returning `None` if it is not one.
+    ///
+    /// The spacing of `openssl dgst` (`TAG(path)= checksum`) and its names such as `SHA2-256` are accepted as well.
+    fn parse_bsd_line(self, line: &[u8]) -> Option<(&[u8], String)> {
+        let open = line.iter().position(|&b| b == b'(')?;
+        let tag = str::from_utf8(&line[..open]).ok()?.trim();
+        if !self.matches_tag(tag) {
+            return None;
+        }
+
+        let rest = &line[open + 1..];
+        let close = rest.iter().rposition(|&b| b == b')')?;
+        let path = &rest[..close];
+        let checksum = str::from_utf8(&rest[close + 1..])
+            .ok()?
+            .trim_start()
+            .strip_prefix('=')?
+            .trim();
+        let checksum = match self {
+            Algorithm::Xxh3 if !checksum.starts_with("XXH3_") => format!("XXH3_{checksum}"),
+            _ if self.is_sfv() => checksum.to_uppercase(),
+            _ => checksum.to_string(),
+        };
+        Some((path, checksum))
+    }
+}
+
+/// Options controlling the form of lines written by `Algorithm::format_line`.
+#[derive(Clone, Copy, Default)]
+pub struct LineStyle {
+    /// Backslashes in paths separate components and are not escaped.
+    pub backslash_separator: bool,
+    /// Write BSD style lines of the form `TAG (path) = checksum` as written by `openssl dgst` and BSD `cksum`, without
+    /// the `XXH3_` prefix.
+    pub bsd: bool,
+    /// Write paths without escaping, e.g. for NUL separated output.
+    pub raw: bool,
+}
+
+/// Escapes a path for use in a checksum file, returning `None` if it can be written as is.
+///
+/// Like coreutils, paths containing backslashes or line breaks are escaped using `\\`, `\n` and `\r`, and the line is
+/// marked by a leading backslash. Other control characters as well as leading and trailing spaces, which would be lost
+/// when parsing the line, are escaped as well using `\t` and `\xHH`. If `backslash_separator` is specified, e.g. on
+/// Windows, backslashes are path separators and only require escaping at the start of a path.
+fn escape(path: &[u8], backslash_separator: bool) -> Option<Vec<u8>> {
+    let needs_escape = path
+        .iter()
+        .any(|&b| b.is_ascii_control() || (b == b'\\' && !backslash_separator))
+        || path.starts_with(b"\\")
+        || path.starts_with(b" ")
+        || path.ends_with(b" ");
+    if !needs_escape {
+        return None;
+    }
+
+    let mut escaped = Vec::with_capacity(path.len() + 2);
+    for (i, &b) in path.iter().enumerate() {
+        match b {
+            b'\\' => escaped.extend(b"\\\\"),
+            b'\n' => escaped.extend(b"\\n"),
+            b'\r' => escaped.extend(b"\\r"),
+            b'\t' => escaped.extend(b"\\t"),
+            b' ' if i > 0 && i < path.len() - 1 => escaped.push(b),
+            b' ' => escaped.extend(b"\\x20"),
+            _ if b.is_ascii_control() => escaped.extend(format!("\\x{b:02x}").as_bytes()),
+            _ => escaped.push(b),
+        }
+    }
+
+    Some(escaped)
+}
+
+/// Reverses `escape`, returning `None` for invalid escape sequences.
+fn unescape(path: &[u8]) -> Option<Vec<u8>> {
+    let mut unescaped = Vec::with_capacity(path.len());
+    let mut bytes = path.iter().copied();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            unescaped.push(b);
+            continue;
+        }
+
+        let b = match bytes.next()? {
+            b'\\' => b'\\',
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'x' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                u8::from_str_radix(str::from_utf8(&hex).ok()?, 16).ok()?
+            }
+            _ => return None,
+        };
+        unescaped.push(b);
+    }
+
+    Some(unescaped)
+}
+
+/// Incremental hasher for any of the supported algorithms.
+pub enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Crc32c(u32),
+    Crc(Box<Crc>),
+    Digest(Box<dyn DynDigest + Send>),
+    Blake3(Box<blake3::Hasher>),
+    Xxh32(Box<xxh32::Xxh32>),
+    Xxh64(Box<xxh64::Xxh64>),
+    Xxh3(Box<xxh3::Xxh3>),
+    Xxh128(Box<xxh3::Xxh3>),
+}
+
+impl Hasher {
+    /// Creates a new hasher for `algorithm`.
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            Algorithm::Crc32c => Hasher::Crc32c(0),
+            Algorithm::Crc(params) => Hasher::Crc(Box::new(Crc::new(params))),
+            Algorithm::Md5 => Hasher::Digest(Box::new(md5::Md5::default())),
+            Algorithm::Sha1 => Hasher::Digest(Box::new(sha1::Sha1::default())),
+            Algorithm::Sha224 => Hasher::Digest(Box::new(sha2::Sha224::default())),
+            Algorithm::Sha256 => Hasher::Digest(Box::new(sha2::Sha256::default())),
+            Algorithm::Sha384 => Hasher::Digest(Box::new(sha2::Sha384::default())),
+            Algorithm::Sha512 => Hasher::Digest(Box::new(sha2::Sha512::default())),
+            Algorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            Algorithm::Xxh32 => Hasher::Xxh32(Box::new(xxh32::Xxh32::new(0))),
+            Algorithm::Xxh64 => Hasher::Xxh64(Box::new(xxh64::Xxh64::new(0))),
+            Algorithm::Xxh3 => Hasher::Xxh3(Box::new(xxh3::Xxh3::new())),
+            Algorithm::Xxh128 => Hasher::Xxh128(Box::new(xxh3::Xxh3::new())),
+        }
+    }
+
+    /// Processes `data`, updating the internal state.
+    ///
+    /// BLAKE3 hashes large inputs using multiple threads, so even a single big file benefits from all cores.
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32(hasher) => hasher.update(data),
+            Hasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            Hasher::Crc(crc) => crc.update(data),
+            Hasher::Digest(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) if data.len() >= BLAKE3_PARALLEL_SIZE => {
+                hasher.update_rayon(data);
+            }
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Xxh32(hasher) => hasher.update(data),
+            Hasher::Xxh64(hasher) => hasher.update(data),
+            Hasher::Xxh3(hasher) | Hasher::Xxh128(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Finalizes the hasher and returns the checksum as big-endian bytes.
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Crc32(hasher) => hasher.finalize().to_be_bytes().to_vec(),
+            Hasher::Crc32c(crc) => crc.to_be_bytes().to_vec(),
+            Hasher::Crc(crc) => {
+                let bytes = crc.params().bytes();
+                crc.finalize().to_be_bytes()[8 - bytes..].to_vec()
+            }
+            Hasher::Digest(hasher) => hasher.finalize().into_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+            Hasher::Xxh32(hasher) => hasher.digest().to_be_bytes().to_vec(),
+            Hasher::Xxh64(hasher) => hasher.digest().to_be_bytes().to_vec(),
+            Hasher::Xxh3(hasher) => hasher.digest().to_be_bytes().to_vec(),
+            Hasher::Xxh128(hasher) => hasher.digest128().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+/// Computes the checksum of `file` using `algorithm`, returned as a hex string.
+pub fn hash_file(file: &Path, algorithm: Algorithm) -> Result<String> {
+    let mut fp = File::open(file).map_err(|source| Error::OpenFailed {
+        path: file.to_path_buf(),
+        source,
+    })?;
+    hash_reader(&mut fp, algorithm, |_| Ok(())).map_err(|source| Error::ReadFailed {
+        path: file.to_path_buf(),
+        source,
+    })
+}
+
+/// Checks that `file` has the `expected` checksum of `algorithm`, ignoring case. Returns `Error::Mismatch` with the
+/// actual checksum if it does not.
+pub fn verify_file(file: &Path, expected: &str, algorithm: Algorithm) -> Result<()> {
+    let actual = hash_file(file, algorithm)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(Error::Mismatch {
+            path: file.to_path_buf(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Reader hashing all data read through it, e.g. to compute the checksum of a stream while it is parsed or copied.
+///
+/// Only the bytes actually returned by the inner reader are hashed, so the checksum covers everything read so far.
+pub struct CrcReader<R> {
+    inner: R,
+    algorithm: Algorithm,
+    hasher: Hasher,
+}
+
+impl<R: Read> CrcReader<R> {
+    /// Creates a reader computing the CRC-32 of the data read from `inner`.
+    pub fn new(inner: R) -> Self {
+        Self::with_algorithm(inner, Algorithm::Crc32)
+    }
+
+    /// Creates a reader computing the checksum of the data read from `inner` using `algorithm`.
+    pub fn with_algorithm(inner: R, algorithm: Algorithm) -> Self {
+        CrcReader {
+            inner,
+            algorithm,
+            hasher: Hasher::new(algorithm),
+        }
+    }
+}
+
+impl<R> CrcReader<R> {
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader. Data read from it directly is not hashed.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the inner reader, discarding the checksum.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns the checksum of the data read so far as a hex string, in the form written to checksum files.
+    pub fn finalize(self) -> String {
+        self.algorithm.to_hex(&self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Writer hashing all data written through it, e.g. to compute the checksum of a file while it is created.
+///
+/// Only the bytes accepted by the inner writer are hashed, so partial writes are accounted for.
+pub struct CrcWriter<W> {
+    inner: W,
+    algorithm: Algorithm,
+    hasher: Hasher,
+}
+
+impl<W: io::Write> CrcWriter<W> {
+    /// Creates a writer computing the CRC-32 of the data written to `inner`.
+    pub fn new(inner: W) -> Self {
+        Self::with_algorithm(inner, Algorithm::Crc32)
+    }
+
+    /// Creates a writer computing the checksum of the data written to `inner` using `algorithm`.
+    pub fn with_algorithm(inner: W, algorithm: Algorithm) -> Self {
+        CrcWriter {
+            inner,
+            algorithm,
+            hasher: Hasher::new(algorithm),
+        }
+    }
+}
+
+impl<W> CrcWriter<W> {
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner writer. Data written to it directly is not hashed.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the inner writer, discarding the checksum. The writer is not flushed.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Returns the checksum of the data written so far as a hex string, in the form written to checksum files.
+    pub fn finalize(self) -> String {
+        self.algorithm.to_hex(&self.hasher.finalize())
+    }
+}
+
+impl<W: io::Write> io::Write for CrcWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
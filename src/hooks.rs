@@ -0,0 +1,137 @@
+//! Notifications sent when verification finds files which do not match or are missing, so that corruption is noticed
+//! without reading logs.
+//!
+//! A notification is a JSON object with the time, the counts of files by status and the files which failed, which is
+//! posted to a webhook and written to the stdin of a command. The command also gets the counts in environment
+//! variables, so that simple scripts do not need to parse JSON.
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Error, Result};
+use serde::Serialize;
+
+use crate::{Status, Summary, now, warn};
+
+/// Where notifications are sent.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    /// Command run by the shell.
+    pub command: Option<String>,
+    /// URL to which notifications are posted.
+    pub url: Option<String>,
+}
+
+/// File which failed verification.
+#[derive(Serialize)]
+struct Failure<'a> {
+    path: &'a str,
+    status: Status,
+}
+
+/// Notification about a verification which found failures.
+#[derive(Serialize)]
+struct Notification<'a> {
+    /// Time the notification was sent in RFC 3339 format.
+    time: String,
+    #[serde(flatten)]
+    summary: &'a Summary,
+    failures: Vec<Failure<'a>>,
+}
+
+impl Hooks {
+    /// Whether any notifications are configured.
+    pub fn is_empty(&self) -> bool {
+        self.command.is_none() && self.url.is_none()
+    }
+
+    /// Sends a notification if `summary` counts files which do not match or are missing, unless missing files are
+    /// ignored. Failing to send a notification is reported as a warning, so that it does not hide the results.
+    pub fn notify(&self, summary: &Summary) {
+        let missing = summary.missing > 0 && !summary.ignore_missing;
+        if self.is_empty() || (summary.failed == 0 && !missing) {
+            return;
+        }
+
+        let notification = Notification {
+            time: now(),
+            summary,
+            failures: summary
+                .failures
+                .iter()
+                .filter(|failure| matches!(failure.status, Status::Fail | Status::Missing))
+                .map(|failure| Failure {
+                    path: &failure.path,
+                    status: failure.status,
+                })
+                .collect(),
+        };
+        let json = match serde_json::to_vec(&notification) {
+            Ok(json) => json,
+            Err(e) => {
+                warn(&format!("Failed to serialize notification: {e}"));
+                return;
+            }
+        };
+
+        if let Some(command) = &self.command
+            && let Err(e) = run(command, summary, &json)
+        {
+            warn(&format!("{e:#}"));
+        }
+        if let Some(url) = &self.url
+            && let Err(e) = post(url, &json)
+        {
+            warn(&format!("{e:#}"));
+        }
+    }
+}
+
+/// Runs `command` by the shell with `json` on stdin and the counts of `summary` in the environment variables
+/// `CRC32_FILES`, `CRC32_OK`, `CRC32_FAILED`, `CRC32_MISSING`, `CRC32_EXTRA` and `CRC32_ERRORS`. A command which
+/// cannot be run or exits unsuccessfully causes an error.
+fn run(command: &str, summary: &Summary, json: &[u8]) -> Result<()> {
+    let context = || format!("Failed to run notification command {command}");
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .env("CRC32_FILES", summary.files.to_string())
+        .env("CRC32_OK", summary.ok.to_string())
+        .env("CRC32_FAILED", summary.failed.to_string())
+        .env("CRC32_MISSING", summary.missing.to_string())
+        .env("CRC32_EXTRA", summary.extra.to_string())
+        .env("CRC32_ERRORS", summary.errors.to_string())
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(context)?;
+
+    // Commands which do not read stdin close it early, which is not an error.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(json);
+    }
+    let status = child.wait().with_context(context)?;
+    if !status.success() {
+        return Err(Error::msg(format!(
+            "Notification command {command} failed with {status}"
+        )));
+    }
+    Ok(())
+}
+
+/// Posts `json` to `url`. Any error, including an unsuccessful status, is propagated with added context.
+fn post(url: &str, json: &[u8]) -> Result<()> {
+    ureq::post(url)
+        .header("Content-Type", "application/json")
+        .send(json)
+        .with_context(|| format!("Failed to post notification to {url}"))?;
+    Ok(())
+}
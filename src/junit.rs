@@ -0,0 +1,80 @@
+//! Results as a JUnit XML report, so that CI servers such as Jenkins and GitLab display them like test results.
+//!
+//! Every file is a test case named after its path. Files which do not match, are missing or are not listed are
+//! failures, files which cannot be read are errors and missing files are skipped if they are ignored.
+use std::fmt::Write as _;
+
+use quick_xml::escape::escape;
+
+use crate::{Record, Status, now};
+
+/// Name of the test suite and class name of the test cases.
+const SUITE: &str = "crc32";
+
+/// Returns a JUnit XML report of `records`. Missing files are reported as skipped if `ignore_missing` is set.
+pub fn report(records: &[Record], ignore_missing: bool) -> String {
+    let count = |statuses: &[Status]| {
+        records
+            .iter()
+            .filter(|record| statuses.contains(&record.status))
+            .count()
+    };
+    let (failures, errors, skipped) = if ignore_missing {
+        (
+            count(&[Status::Fail, Status::Extra]),
+            count(&[Status::Error]),
+            count(&[Status::Missing]),
+        )
+    } else {
+        (
+            count(&[Status::Fail, Status::Missing, Status::Extra]),
+            count(&[Status::Error]),
+            0,
+        )
+    };
+    let counts = format!(
+        "tests=\"{}\" failures=\"{failures}\" errors=\"{errors}\" skipped=\"{skipped}\"",
+        records.len()
+    );
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(xml, "<testsuites name=\"{SUITE}\" {counts}>");
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"{SUITE}\" {counts} timestamp=\"{}\">",
+        now()
+    );
+    for record in records {
+        let name = escape(&record.path);
+        let Some((element, kind, message)) = outcome(record, ignore_missing) else {
+            let _ = writeln!(xml, "    <testcase classname=\"{SUITE}\" name=\"{name}\"/>");
+            continue;
+        };
+        let _ = writeln!(xml, "    <testcase classname=\"{SUITE}\" name=\"{name}\">");
+        let _ = match kind {
+            Some(kind) => writeln!(
+                xml,
+                "      <{element} type=\"{kind}\" message=\"{}\"/>",
+                escape(&message)
+            ),
+            None => writeln!(xml, "      <{element} message=\"{}\"/>", escape(&message)),
+        };
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Returns the element describing the outcome of a test case which did not pass along with its type and message, or
+/// `None` if it passed.
+fn outcome(
+    record: &Record,
+    ignore_missing: bool,
+) -> Option<(&'static str, Option<&'static str>, String)> {
+    let message = record.problem()?;
+    Some(match record.status {
+        Status::Missing if ignore_missing => ("skipped", None, message),
+        Status::Error => ("error", Some("error"), message),
+        status => ("failure", Some(status.as_str()), message),
+    })
+}
@@ -0,0 +1,4119 @@
+//! Computes the CRC32 checksum of files provided.
+//!
+//! Can also verify SFV and create SFV files. Other algorithms such as MD5 and SHA-256 are supported as well, using the
+//! checksum file format of `md5sum` and `sha256sum`.
+//!
+//! This library contains everything the `crc32` command does, so that it can be embedded in other applications such
+//! as GUIs. Files are hashed with [`hash::Hasher`], or while they are read or written with [`hash::CrcReader`] and
+//! [`hash::CrcWriter`], found with [`walk::get_all_files`] and checksum files are read and written with
+//! [`manifest::ManifestFormat`]. Each mode of the command is a function taking options structs, such as
+//! [`create_sfv`] or [`verify_sfv`], which prints results according to [`OutputOptions`] and returns a [`Summary`] of
+//! the processed files. Progress is reported to a [`progress::Observer`], which can also cancel the mode. Hashing files
+//! and reading checksum files fail with an [`error::Error`], which the modes wrap with context in an `anyhow` error.
+//! Applications using tokio can hash and verify files without blocking with the `asynchronous` module of the `tokio`
+//! feature, C and C++ tools can link against the C ABI of the `ffi` module of the `ffi` feature and the `python` feature
+//! builds a Python module with maturin.
+mod archive;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod cache;
+pub mod compat;
+mod compression;
+pub mod crc;
+mod db;
+pub mod encoding;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hash;
+pub mod hooks;
+mod junit;
+pub mod manifest;
+mod metrics;
+pub mod names;
+mod par2;
+pub mod paths;
+pub mod progress;
+#[cfg(feature = "python")]
+mod python;
+mod reader;
+pub mod report;
+pub mod table;
+mod tap;
+pub mod template;
+mod torrent;
+pub mod walk;
+mod xattrs;
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Error, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::ValueEnum;
+use colored::Colorize;
+use glob::Pattern;
+use indicatif::BinaryBytes;
+use notify::{RecursiveMode, Watcher};
+use rayon::{ThreadPoolBuilder, prelude::*};
+use serde::Serialize;
+
+use crate::{
+    archive::{ArchiveKind, archive_members, split_member_path, zip_members},
+    cache::Cache,
+    compat::{CKSFV_WEB_SITE, Compat, cksfv_banner, cksfv_line, cksfv_summary, error_message},
+    compression::Compression,
+    encoding::Encoding,
+    hash::{Algorithm, Hasher, LineStyle},
+    hooks::Hooks,
+    manifest::{Entry, HEADER_TIME, HeaderFile, ManifestFormat, SfvFile, dedup},
+    names::{OnConflict, embedded_crc, rename_target, replace_crc, strip_crc},
+    paths::{PathOptions, absolute_path, relative_path, resolve},
+    progress::Observer,
+    reader::ChunkReader,
+    table::Column,
+    template::Template,
+    torrent::Torrent,
+    walk::{
+        SymlinkPolicy, WalkOptions, get_all_files, get_checksum_files, get_files, path_from_bytes,
+    },
+};
+
+/// Number of bytes to read at once.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Path used to read from standard input.
+const STDIN: &str = "-";
+
+/// Interval in which events are checked while watching files.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Exit code if files do not match their checksums.
+pub const EXIT_MISMATCH: u8 = 1;
+/// Exit code if files listed in a checksum file or on one side of a comparison are missing.
+pub const EXIT_MISSING: u8 = 2;
+/// Exit code if files cannot be read or processing fails otherwise.
+pub const EXIT_ERROR: u8 = 3;
+/// Exit code if a mode was cancelled, e.g. by Ctrl-C, like a shell reports a command stopped by SIGINT.
+pub const EXIT_CANCELLED: u8 = 130;
+
+/// Format of results printed on stdout.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Plain text lines
+    Text,
+    /// BSD style lines such as `CRC32 (path) = 1A2B3C4D`, also used for the output file
+    Bsd,
+    /// JSON array of result objects
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+    /// Tab separated values with a header row
+    Tsv,
+    /// One JSON event per line, printed as soon as each file is done
+    Jsonl,
+    /// JUnit XML report with one test case per file, as read by CI servers
+    Junit,
+    /// Test Anything Protocol stream with one test per file, as read by prove
+    Tap,
+}
+
+impl Format {
+    /// Delimiter between fields of tabular formats, `None` for other formats.
+    fn delimiter(self) -> Option<u8> {
+        match self {
+            Format::Csv => Some(b','),
+            Format::Tsv => Some(b'\t'),
+            Format::Text
+            | Format::Bsd
+            | Format::Json
+            | Format::Jsonl
+            | Format::Junit
+            | Format::Tap => None,
+        }
+    }
+
+    /// Name of test report formats, which describe results of files and are not available for comparisons, `None`
+    /// for other formats.
+    fn report_name(self) -> Option<&'static str> {
+        match self {
+            Format::Junit => Some("JUnit"),
+            Format::Tap => Some("TAP"),
+            Format::Text
+            | Format::Bsd
+            | Format::Json
+            | Format::Csv
+            | Format::Tsv
+            | Format::Jsonl => None,
+        }
+    }
+}
+
+/// Status of a processed file.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Fail,
+    /// The file does not exist.
+    Missing,
+    /// The file exists but is not listed in the checksum file.
+    Extra,
+    Error,
+}
+
+impl Status {
+    /// Name of the status as used in structured output.
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Fail => "fail",
+            Status::Missing => "missing",
+            Status::Extra => "extra",
+            Status::Error => "error",
+        }
+    }
+}
+
+/// Result for a single file, used for structured output.
+#[derive(Serialize)]
+pub struct Record {
+    pub path: String,
+    pub checksum: Option<String>,
+    pub expected: Option<String>,
+    pub size: Option<u64>,
+    /// Modification time in RFC 3339 format.
+    pub modified: Option<String>,
+    pub status: Status,
+    pub error: Option<String>,
+}
+
+/// File which was not verified successfully, for notifications and reports.
+#[derive(Clone)]
+pub struct Failure {
+    pub path: String,
+    pub status: Status,
+    pub checksum: Option<String>,
+    pub expected: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Counts of processed files by status.
+#[derive(Default, Serialize)]
+pub struct Summary {
+    pub files: usize,
+    pub ok: usize,
+    pub failed: usize,
+    pub missing: usize,
+    pub extra: usize,
+    pub errors: usize,
+    /// Total size of the files which were read.
+    pub bytes: u64,
+    /// Whether missing files are reported without counting as failures.
+    #[serde(skip)]
+    pub ignore_missing: bool,
+    /// Whether the mode was cancelled before all files were processed, see `Observer::is_cancelled`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// Files which do not match, are missing, are not listed or cannot be read.
+    #[serde(skip)]
+    pub failures: Vec<Failure>,
+    /// Counts of the files below each directory containing files, for reports.
+    #[serde(skip)]
+    pub directories: BTreeMap<String, Summary>,
+}
+
+impl Summary {
+    /// Counts a file with the given status.
+    fn add(&mut self, status: Status) {
+        self.files += 1;
+        match status {
+            Status::Ok => self.ok += 1,
+            Status::Fail => self.failed += 1,
+            Status::Missing => self.missing += 1,
+            Status::Extra => self.extra += 1,
+            Status::Error => self.errors += 1,
+        }
+    }
+
+    /// Adds the counts of `other`.
+    fn merge(&mut self, other: &Summary) {
+        self.files += other.files;
+        self.ok += other.ok;
+        self.failed += other.failed;
+        self.missing += other.missing;
+        self.extra += other.extra;
+        self.errors += other.errors;
+        self.bytes += other.bytes;
+        self.ignore_missing |= other.ignore_missing;
+        self.cancelled |= other.cancelled;
+        self.failures.extend(other.failures.iter().cloned());
+        for (dir, counts) in &other.directories {
+            self.directories
+                .entry(dir.clone())
+                .or_default()
+                .merge(counts);
+        }
+    }
+
+    /// Counts a file by the status of its record, adding its size if it was read, and counts it for the directories
+    /// containing it as well. Files which were not verified successfully are kept with their details.
+    fn add_record(&mut self, record: &Record) {
+        self.count(record);
+        for dir in Path::new(&record.path).ancestors().skip(1) {
+            let dir = dir.to_string_lossy();
+            if dir.is_empty() {
+                break;
+            }
+            match self.directories.get_mut(dir.as_ref()) {
+                Some(counts) => counts.count(record),
+                None => {
+                    let mut counts = Summary::default();
+                    counts.count(record);
+                    self.directories.insert(dir.into_owned(), counts);
+                }
+            }
+        }
+        if record.status != Status::Ok {
+            self.failures.push(Failure {
+                path: record.path.clone(),
+                status: record.status,
+                checksum: record.checksum.clone(),
+                expected: record.expected.clone(),
+                error: record.error.clone(),
+            });
+        }
+        let level = match record.status {
+            Status::Ok => log::Level::Debug,
+            Status::Fail | Status::Missing | Status::Extra => log::Level::Warn,
+            Status::Error => log::Level::Error,
+        };
+        log::log!(
+            level,
+            path = record.path.as_str(),
+            status = record.status.as_str(),
+            checksum = record.checksum.as_deref().unwrap_or_default(),
+            expected = record.expected.as_deref().unwrap_or_default(),
+            error = record.error.as_deref().unwrap_or_default();
+            "{} {}",
+            record.path,
+            record.status.as_str().to_uppercase()
+        );
+    }
+
+    /// Counts a file by the status of its record and adds its size if it was read, without keeping any details.
+    fn count(&mut self, record: &Record) {
+        self.add(record.status);
+        if let (Status::Ok | Status::Fail, Some(size)) = (record.status, record.size) {
+            self.bytes += size;
+        }
+    }
+
+    /// Returns a line describing the counts, total size and throughput of a run which took `elapsed`.
+    pub fn report(&self, elapsed: Duration) -> String {
+        let seconds = elapsed.as_secs_f64();
+        let throughput = if seconds > 0.0 {
+            self.bytes as f64 / seconds
+        } else {
+            0.0
+        };
+        let mut line = format!(
+            "{} files, {} OK, {} FAIL, {} MISSING, {} ERROR",
+            self.files, self.ok, self.failed, self.missing, self.errors
+        );
+        if self.extra > 0 {
+            line.push_str(&format!(", {} EXTRA", self.extra));
+        }
+        if self.cancelled {
+            line.push_str(", CANCELLED");
+        }
+        line.push_str(&format!(
+            " - {} in {seconds:.2}s ({}/s)",
+            BinaryBytes(self.bytes),
+            BinaryBytes(throughput as u64)
+        ));
+        line
+    }
+
+    /// Whether all files were processed successfully.
+    pub fn is_ok(&self) -> bool {
+        let ignored = if self.ignore_missing { self.missing } else { 0 };
+        self.ok + ignored == self.files
+    }
+
+    /// Exit code reporting the most severe result: `EXIT_CANCELLED` if the mode was cancelled, `EXIT_ERROR` if any file
+    /// could not be read, `EXIT_MISSING` if any file is missing unless `ignore_missing` is set and `EXIT_MISMATCH` if any
+    /// file does not match or is not listed.
+    pub fn exit_code(&self) -> ExitCode {
+        if self.cancelled {
+            ExitCode::from(EXIT_CANCELLED)
+        } else if self.errors > 0 {
+            ExitCode::from(EXIT_ERROR)
+        } else if self.missing > 0 && !self.ignore_missing {
+            ExitCode::from(EXIT_MISSING)
+        } else if self.failed > 0 || self.extra > 0 {
+            ExitCode::from(EXIT_MISMATCH)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Prints a warning on stderr.
+fn warn(message: &str) {
+    log::warn!("{message}");
+    eprintln!("{} {message}", "[WARNING]".yellow().bold());
+}
+
+/// Whether `error` was caused by a file which does not exist.
+fn is_not_found(error: &Error) -> bool {
+    error
+        .root_cause()
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+}
+
+/// Event of JSON Lines output.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event<'a> {
+    /// Processing of the given number of files started.
+    Start { files: usize },
+    /// A file was processed.
+    Result(&'a Record),
+    /// A file could not be read.
+    Error(&'a Record),
+    /// A path was compared.
+    #[serde(rename = "result")]
+    Compared(&'a Comparison),
+    /// A compared path could not be read.
+    #[serde(rename = "error")]
+    CompareError(&'a Comparison),
+    /// A path listed in two checksum files was compared.
+    #[serde(rename = "result")]
+    Changed(&'a Change),
+    /// All files were processed.
+    Summary(&'a Summary),
+}
+
+/// Options controlling how results are printed on stdout.
+pub struct OutputOptions {
+    pub format: Format,
+    /// Columns of CSV and TSV output.
+    pub columns: Vec<Column>,
+    /// Template of plain text lines.
+    pub template: Option<Template>,
+    /// Terminate plain text lines with NUL instead of a line break and write paths without escaping.
+    pub print0: bool,
+    /// Do not print plain text lines of files which were verified successfully.
+    pub quiet: bool,
+    /// Do not print any results, only the exit code reports them.
+    pub status: bool,
+}
+
+impl OutputOptions {
+    /// Terminator of plain text lines.
+    fn terminator(&self) -> u8 {
+        if self.print0 { b'\0' } else { b'\n' }
+    }
+}
+
+/// Options controlling how files are hashed.
+#[derive(Clone, Copy)]
+pub struct HashOptions {
+    pub algorithm: Algorithm,
+    /// Hash the target path of symbolic links instead of the file they point to.
+    pub link_target: bool,
+    /// Hash the members of archives instead of the archives themselves.
+    pub archives: bool,
+    /// Number of levels of nested archives whose members are hashed, 1 meaning only the outermost archives.
+    pub archive_depth: usize,
+    /// Hash the decompressed contents of compressed files.
+    pub decompress: bool,
+    /// How files are read.
+    pub io: IoBackend,
+    /// Bypass the page cache when reading files, so that hashing does not evict data cached for other programs.
+    pub direct: bool,
+}
+
+/// How files are read while hashing them. Files which are too small to benefit or cannot be read with the selected
+/// backend, e.g. pipes, are read with `read()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IoBackend {
+    /// Read files into a buffer with `read()`, reading the next chunk on another thread while the previous one is hashed.
+    #[default]
+    Read,
+    /// Hash files straight from a memory map, which is faster for files in the page cache. A file truncated by another
+    /// process while it is hashed raises `SIGBUS`.
+    Mmap,
+    /// Keep several reads of each file in flight with io_uring, which is faster on fast SSDs. Only available on Linux.
+    IoUring,
+}
+
+impl Default for HashOptions {
+    fn default() -> Self {
+        HashOptions {
+            algorithm: Algorithm::Crc32,
+            link_target: false,
+            archives: false,
+            archive_depth: 1,
+            decompress: false,
+            io: IoBackend::Read,
+            direct: false,
+        }
+    }
+}
+
+/// Options controlling how files are found, hashed and how their paths are written and looked up, shared by all modes.
+///
+/// The most common options can be set by chaining the methods below, e.g.
+/// `ScanOptions::new().recursive(true).algorithm(Algorithm::Sha256).jobs(4)`, others through the fields.
+#[derive(Clone, Default)]
+pub struct ScanOptions {
+    pub walk_options: WalkOptions,
+    pub path_options: PathOptions,
+    pub hash_options: HashOptions,
+    /// Number of files hashed in parallel by a thread pool built for each mode, or by the global thread pool if `None`.
+    /// Applications running modes repeatedly can size the global pool with `rayon::ThreadPoolBuilder::build_global`
+    /// instead.
+    pub jobs: Option<usize>,
+}
+
+impl ScanOptions {
+    /// Creates options which hash the files given, without searching subdirectories, using CRC32.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Searches subdirectories as well.
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.walk_options.recursive = recursive;
+        self
+    }
+
+    /// Follows links to directories as well as links to files.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.walk_options.symlinks = if follow {
+            SymlinkPolicy::Follow
+        } else {
+            SymlinkPolicy::Files
+        };
+        self
+    }
+
+    /// Only collects files matching `pattern` or another included pattern from directories.
+    pub fn include(mut self, pattern: Pattern) -> Self {
+        self.walk_options.include.push(pattern);
+        self
+    }
+
+    /// Skips files and directories matching `pattern`.
+    pub fn exclude(mut self, pattern: Pattern) -> Self {
+        self.walk_options.exclude.push(pattern);
+        self
+    }
+
+    /// Hashes files using `algorithm`.
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.hash_options.algorithm = algorithm;
+        self
+    }
+
+    /// Hashes up to `jobs` files in parallel.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Resolves relative paths in verified checksum files against `dir` instead of the directory containing them.
+    pub fn base_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.path_options.base_dir = Some(dir.into());
+        self
+    }
+}
+
+/// Options controlling which files are checked when verifying.
+///
+/// Like `ScanOptions`, the most common options can be set by chaining the methods below, e.g.
+/// `VerifyOptions::new().check_extra(true).skip(pattern)`.
+#[derive(Clone, Default)]
+pub struct VerifyOptions {
+    /// Report files in the base directory which are not listed in the checksum file.
+    pub check_extra: bool,
+    /// Only verify listed files matching any of these patterns.
+    pub only: Vec<Pattern>,
+    /// Skip listed files matching any of these patterns.
+    pub skip: Vec<Pattern>,
+    /// Report files as soon as they are verified instead of in the order listed.
+    pub unordered: bool,
+    /// Do not fail because of missing files.
+    pub ignore_missing: bool,
+    /// Detect the algorithm of each checksum file instead of using the one selected.
+    pub detect_algorithm: bool,
+    /// Notifications sent when files do not match or are missing.
+    pub hooks: Hooks,
+    /// Only verify listed files in the given part of the given number of parts, where files are assigned to parts by
+    /// the CRC-32 of their path.
+    pub part: Option<(u32, u32)>,
+}
+
+impl VerifyOptions {
+    /// Creates options which verify all listed files with the algorithm selected by `ScanOptions`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports files in the base directory which are not listed, searching it according to `ScanOptions`.
+    pub fn check_extra(mut self, check_extra: bool) -> Self {
+        self.check_extra = check_extra;
+        self
+    }
+
+    /// Only verifies listed files matching `pattern` or another pattern given to this method.
+    pub fn only(mut self, pattern: Pattern) -> Self {
+        self.only.push(pattern);
+        self
+    }
+
+    /// Skips listed files matching `pattern`.
+    pub fn skip(mut self, pattern: Pattern) -> Self {
+        self.skip.push(pattern);
+        self
+    }
+
+    /// Reports files as soon as they are verified instead of in the order listed.
+    pub fn unordered(mut self, unordered: bool) -> Self {
+        self.unordered = unordered;
+        self
+    }
+
+    /// Does not fail because of missing files.
+    pub fn ignore_missing(mut self, ignore_missing: bool) -> Self {
+        self.ignore_missing = ignore_missing;
+        self
+    }
+
+    /// Detects the algorithm of each checksum file instead of using the one selected.
+    pub fn detect_algorithm(mut self, detect_algorithm: bool) -> Self {
+        self.detect_algorithm = detect_algorithm;
+        self
+    }
+
+    /// Whether a file listed at `path` in the checksum file passes the `only` and `skip` filters.
+    fn selects(&self, path: &Path) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|p| p.matches_path(path)))
+            && !self.skip.iter().any(|p| p.matches_path(path))
+            && self.part.is_none_or(|(part, parts)| {
+                crc32fast::hash(path.as_os_str().as_encoded_bytes()) % parts == part
+            })
+    }
+}
+
+/// Options controlling when checksum files are verified again when scrubbing.
+pub struct ScrubOptions {
+    /// Time between the starts of two rounds.
+    pub interval: Duration,
+    /// Number of parts the listed files are split into, one of which is verified per round.
+    pub parts: u32,
+    /// File to which the results of each round are appended as JSON Lines.
+    pub history: Option<PathBuf>,
+    /// Search directories given for checksum files recursively.
+    pub recursive: bool,
+    /// Address on which metrics are served for Prometheus.
+    pub metrics: Option<String>,
+}
+
+/// Results of a round of scrubbing, as appended to the history file.
+#[derive(Serialize)]
+struct ScrubRound<'a> {
+    /// Start of the round in RFC 3339 format.
+    time: String,
+    /// Part of the listed files which was verified, counted from 1.
+    part: u32,
+    parts: u32,
+    #[serde(flatten)]
+    summary: &'a Summary,
+    /// Errors which ended verifying some paths early.
+    error: Option<String>,
+}
+
+/// How files are renamed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RenameMode {
+    /// Add the checksum to names without CRC.
+    Tag,
+    /// Remove the CRC from names.
+    Untag,
+    /// Replace CRCs in names which do not match.
+    Retag,
+}
+
+/// Options controlling how files are renamed to include their checksum.
+pub struct RenameOptions {
+    pub mode: RenameMode,
+    /// Template of new file names.
+    pub pattern: Template,
+    /// What to do if a new file name is already taken.
+    pub on_conflict: OnConflict,
+    /// Only print new file names without renaming files.
+    pub dry_run: bool,
+}
+
+/// Options controlling how checksum files are read and written.
+pub struct ManifestOptions {
+    /// Write a comment header with the size and modification time of each file to SFV files.
+    pub header: bool,
+    /// Tool whose output and exit codes are mimicked.
+    pub compat: Option<Compat>,
+    /// Checksum file written when creating checksums.
+    pub out_file: Option<PathBuf>,
+    /// SQLite database checksums are stored in when creating checksums.
+    pub db: Option<PathBuf>,
+    /// Cache of checksums of unchanged files used when creating checksums, `None` if disabled. Corruption which leaves
+    /// the size and modification time of a file unchanged is not detected when a cached checksum is used.
+    pub cache: Option<PathBuf>,
+    /// Redundancy in percent of PAR2 recovery data written next to the checksum file.
+    pub par2: Option<u32>,
+    /// Store checksums in extended attributes of the hashed files.
+    pub xattr: bool,
+    /// Reuse checksums of unchanged files from the existing output file instead of hashing them.
+    pub update: bool,
+    /// Text encoding of checksum files.
+    pub encoding: Encoding,
+    /// Format of checksum files, detected from their extension if `None`.
+    pub format: Option<ManifestFormat>,
+    /// Fail on malformed lines in checksum files instead of skipping them.
+    pub strict: bool,
+}
+
+impl ManifestOptions {
+    /// Returns the format of the checksum file at `path`.
+    fn format(&self, path: &Path) -> ManifestFormat {
+        self.format.unwrap_or_else(|| ManifestFormat::detect(path))
+    }
+
+    /// Returns the format of the existing checksum file at `path` with contents `data`.
+    fn read_format(&self, path: &Path, data: &[u8]) -> ManifestFormat {
+        self.format
+            .unwrap_or_else(|| ManifestFormat::sniff(path, data))
+    }
+}
+
+/// Status of a path when comparing two files or directory trees.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum CompareStatus {
+    Identical,
+    Different,
+    MissingLeft,
+    MissingRight,
+    Error,
+}
+
+impl CompareStatus {
+    /// Name of the status as used in structured output.
+    fn as_str(self) -> &'static str {
+        match self {
+            CompareStatus::Identical => "identical",
+            CompareStatus::Different => "different",
+            CompareStatus::MissingLeft => "missing-left",
+            CompareStatus::MissingRight => "missing-right",
+            CompareStatus::Error => "error",
+        }
+    }
+}
+
+/// Result of comparing a single path, used for structured output.
+#[derive(Serialize)]
+struct Comparison {
+    path: String,
+    left: Option<String>,
+    right: Option<String>,
+    status: CompareStatus,
+    error: Option<String>,
+}
+
+/// Kind of change of a path between two checksum files.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ChangeKind {
+    Unchanged,
+    Added,
+    Removed,
+    Changed,
+    Renamed,
+}
+
+impl ChangeKind {
+    /// Name of the kind as used in structured output.
+    fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Unchanged => "unchanged",
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+            ChangeKind::Renamed => "renamed",
+        }
+    }
+}
+
+/// Change of a single path between two checksum files, used for structured output.
+#[derive(Serialize)]
+struct Change {
+    path: String,
+    /// Path listed in the old checksum file if the file was renamed.
+    old_path: Option<String>,
+    old: Option<String>,
+    new: Option<String>,
+    status: ChangeKind,
+}
+
+impl Record {
+    /// Creates the record of a verified file by comparing the `computed` checksum with the `expected` one.
+    ///
+    /// Files which do not exist are reported as missing rather than as errors.
+    fn verified(path: &Path, expected: &str, computed: Result<String>) -> Self {
+        let metadata = fs::metadata(path).ok();
+        Record {
+            size: metadata.as_ref().map(fs::Metadata::len),
+            modified: metadata.as_ref().and_then(modified_time),
+            ..Record::checked(path.display().to_string(), expected, computed)
+        }
+    }
+
+    /// Creates the record of a file shown as `path` by comparing the `computed` checksum with the `expected` one,
+    /// without looking up its size and modification time, e.g. for members of archives.
+    fn checked(path: String, expected: &str, computed: Result<String>) -> Self {
+        let status = match &computed {
+            Ok(computed) if computed.eq_ignore_ascii_case(expected) => Status::Ok,
+            Ok(_) => Status::Fail,
+            Err(e) if is_not_found(e) => Status::Missing,
+            Err(_) => Status::Error,
+        };
+        let (checksum, error) = match computed {
+            Ok(computed) => (Some(computed), None),
+            Err(e) => (None, Some(format!("{e:#}"))),
+        };
+
+        Record {
+            path,
+            checksum,
+            expected: Some(expected.to_string()),
+            size: None,
+            modified: None,
+            status,
+            error,
+        }
+    }
+
+    /// Returns a sentence describing why the file was not processed successfully, or `None` if it was.
+    fn problem(&self) -> Option<String> {
+        let error = || self.error.clone().unwrap_or_default();
+        match self.status {
+            Status::Ok => None,
+            // Files which are not compared by checksum, e.g. with torrents, describe the mismatch instead.
+            Status::Fail if self.checksum.is_none() => Some(error()),
+            Status::Fail => Some(format!(
+                "Checksum {} does not match {}",
+                self.checksum.as_deref().unwrap_or_default(),
+                self.expected.as_deref().unwrap_or_default()
+            )),
+            Status::Missing => Some("File is missing".to_string()),
+            Status::Extra => Some("File is not listed in the checksum file".to_string()),
+            Status::Error => Some(error()),
+        }
+    }
+}
+
+/// Returns the current time in RFC 3339 format.
+fn now() -> String {
+    Local::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Returns the modification time of a file in RFC 3339 format.
+fn modified_time(metadata: &fs::Metadata) -> Option<String> {
+    let modified: DateTime<Local> = metadata.modified().ok()?.into();
+    Some(modified.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+/// Returns the result of verifying a file as a colored text line.
+fn verified_line(record: &Record) -> String {
+    let path = &record.path;
+    match record.status {
+        Status::Ok => format!("{path} {}", "OK".green().bold()),
+        // Files which are not compared by checksum, e.g. with torrents, describe the mismatch instead.
+        Status::Fail if record.checksum.is_none() => format!(
+            "{path} {} {}",
+            "FAIL".yellow().bold(),
+            record.error.as_deref().unwrap_or_default()
+        ),
+        Status::Fail => format!(
+            "{path} {} {} ≠ {}",
+            "FAIL".yellow().bold(),
+            record.checksum.as_deref().unwrap_or_default(),
+            record.expected.as_deref().unwrap_or_default()
+        ),
+        Status::Missing => format!("{path} {}", "MISSING".red().bold()),
+        Status::Extra => format!("{path} {}", "EXTRA".yellow().bold()),
+        Status::Error => format!(
+            "{path} {} {}",
+            "ERROR".red().bold(),
+            record.error.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+/// Prints the result of a file computed with `algorithm` as a plain text line, using `output_options.template` if
+/// given.
+fn print_text(
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+    record: &Record,
+    algorithm: Algorithm,
+) {
+    let line = match &output_options.template {
+        Some(template) => template.render(record, algorithm),
+        None => verified_line(record).into_bytes(),
+    };
+    print_terminated(progress, &line, output_options.terminator());
+}
+
+/// Prints records as a JSON array on stdout.
+fn print_json(records: &[Record]) -> Result<()> {
+    let json = serde_json::to_string_pretty(records).context("Failed to serialize results")?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Prints the records collected for formats which are printed once all files are done, i.e. a JSON array, a JUnit
+/// report or a TAP stream. Nothing is printed for other formats.
+fn print_collected(
+    output_options: &OutputOptions,
+    records: &[Record],
+    summary: &Summary,
+) -> Result<()> {
+    match output_options.format {
+        Format::Json => print_json(records)?,
+        Format::Junit => print!("{}", junit::report(records, summary.ignore_missing)),
+        Format::Tap => print!("{}", tap::report(records, summary.ignore_missing)),
+        Format::Text | Format::Bsd | Format::Csv | Format::Tsv | Format::Jsonl => {}
+    }
+
+    Ok(())
+}
+
+/// Prints a JSON Lines event on stdout.
+fn print_event(progress: Option<&dyn Observer>, event: &Event) -> Result<()> {
+    let line = serde_json::to_vec(event).context("Failed to serialize event")?;
+    print_line(progress, &line);
+    Ok(())
+}
+
+/// Prints what precedes the results of `files` files, i.e. the header row of tabular formats or the start event of
+/// JSON Lines. Nothing is printed for other formats.
+fn print_start(
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+    files: usize,
+) -> Result<()> {
+    match output_options.format {
+        Format::Csv | Format::Tsv => {
+            let delimiter = output_options.format.delimiter().unwrap_or(b',');
+            print_line(progress, &table::header(&output_options.columns, delimiter));
+        }
+        Format::Jsonl => print_event(progress, &Event::Start { files })?,
+        Format::Text | Format::Bsd | Format::Json | Format::Junit | Format::Tap => {}
+    }
+
+    Ok(())
+}
+
+/// Prints a record as a row of tabular formats or as a JSON Lines event, or collects it in `records` to be printed as
+/// JSON later.
+fn print_record(
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+    record: Record,
+    records: &mut Vec<Record>,
+) -> Result<()> {
+    match output_options.format {
+        Format::Jsonl if matches!(record.status, Status::Missing | Status::Error) => {
+            print_event(progress, &Event::Error(&record))?
+        }
+        Format::Jsonl => print_event(progress, &Event::Result(&record))?,
+        format => match format.delimiter() {
+            Some(delimiter) => print_line(
+                progress,
+                &table::record_row(&record, &output_options.columns, delimiter),
+            ),
+            None => records.push(record),
+        },
+    }
+
+    Ok(())
+}
+
+/// Prints the result of a file computed with `algorithm` as a plain text line, see `print_text`, unless it is OK and
+/// `output_options.quiet` is specified, or as a record of other formats, see `print_record`.
+fn print_result(
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+    record: Record,
+    algorithm: Algorithm,
+    records: &mut Vec<Record>,
+) -> Result<()> {
+    match output_options.format {
+        Format::Text | Format::Bsd if output_options.quiet && record.status == Status::Ok => {}
+        Format::Text | Format::Bsd => print_text(progress, output_options, &record, algorithm),
+        Format::Json | Format::Csv | Format::Tsv | Format::Jsonl | Format::Junit | Format::Tap => {
+            print_record(progress, output_options, record, records)?
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the summary event of JSON Lines, nothing for other formats.
+fn print_summary(
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+    summary: &Summary,
+) -> Result<()> {
+    if output_options.format == Format::Jsonl {
+        print_event(progress, &Event::Summary(summary))?;
+    }
+
+    Ok(())
+}
+
+/// Reports `event` to `observer` if given.
+fn observe(observer: Option<&dyn Observer>, event: progress::Event) {
+    if let Some(observer) = observer {
+        observer.event(&event);
+    }
+}
+
+/// Whether `observer` is given and cancelled the mode.
+fn is_cancelled(observer: Option<&dyn Observer>) -> bool {
+    observer.is_some_and(|observer| observer.is_cancelled())
+}
+
+/// Returns an error if `observer` is given and cancelled the mode, to stop reading a file.
+fn check_cancelled(observer: Option<&dyn Observer>) -> io::Result<()> {
+    match is_cancelled(observer) {
+        true => Err(io::Error::other(error::Error::Cancelled)),
+        false => Ok(()),
+    }
+}
+
+/// Reports the start of hashing `paths` to `observer` if given, along with their total size.
+///
+/// The total size is computed from file metadata. Files whose metadata cannot be read are counted as empty, the actual
+/// error is reported when hashing them.
+fn start_progress<I, P>(observer: Option<&dyn Observer>, paths: I)
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let Some(observer) = observer else {
+        return;
+    };
+    let mut files = 0;
+    let mut bytes = 0;
+    for path in paths {
+        files += 1;
+        bytes += fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    }
+    observer.event(&progress::Event::Started { files, bytes });
+}
+
+/// Prints a line on stdout, through `progress` so that it can hide its display.
+///
+/// Lines are raw bytes so that paths which are not valid UTF-8 are printed unchanged.
+fn print_line(progress: Option<&dyn Observer>, line: &[u8]) {
+    print_terminated(progress, line, b'\n');
+}
+
+/// Prints a line on stdout followed by `terminator` instead of a line break.
+fn print_terminated(progress: Option<&dyn Observer>, line: &[u8], terminator: u8) {
+    match progress {
+        Some(progress) => progress.print(&mut || write_line(line, terminator)),
+        None => write_line(line, terminator),
+    }
+}
+
+/// Writes a line of raw bytes followed by `terminator` on stdout.
+///
+/// Panics if writing fails, like `println!`.
+fn write_line(line: &[u8], terminator: u8) {
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(line)
+        .and_then(|()| stdout.write_all(&[terminator]))
+        .expect("Failed to write to stdout");
+}
+
+/// Computes the checksum of a file.
+///
+/// Reads the provided file in chunks of `CHUNK_SIZE` and computes the checksum using `options.algorithm`, returned as a
+/// hexadecimal string. If `file` is `-`, standard input is read instead. If `options.link_target` is specified and
+/// `file` is a symbolic link, the target path of the link is hashed. If `options.decompress` is specified and `file` is
+/// compressed, its decompressed contents are hashed. Other files are read using `options.io`, bypassing the page cache
+/// if `options.direct` is specified. If `progress` is provided it is notified after every chunk. Any error is
+/// propagated with added context.
+fn checksum<P>(file: P, options: &HashOptions, progress: Option<&dyn Observer>) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let file = file.as_ref();
+    let is_link = options.link_target && file.is_symlink();
+    let compression = (options.decompress && !is_link)
+        .then(|| Compression::detect(file))
+        .flatten();
+    let mut chunks = None;
+    let (mut fp, size): (Box<dyn Read>, u64) = if is_link {
+        let target = fs::read_link(file).map_err(|source| error::Error::ReadLinkFailed {
+            path: file.to_path_buf(),
+            source,
+        })?;
+        let target = target.into_os_string().into_encoded_bytes();
+        let size = target.len() as u64;
+        (Box::new(io::Cursor::new(target)), size)
+    } else if file.as_os_str() == STDIN {
+        (Box::new(io::stdin().lock()), 0)
+    } else {
+        // Decompressors read into their own buffers, which are not aligned for direct I/O.
+        let direct = options.direct && compression.is_none();
+        let fp = reader::open_file(file, direct).map_err(|source| error::Error::OpenFailed {
+            path: file.to_path_buf(),
+            source,
+        })?;
+        let size = fp.metadata().map(|m| m.len()).unwrap_or(0);
+        if compression.is_none() {
+            chunks = reader::open(&fp, options.io, direct);
+        }
+        (Box::new(fp), size)
+    };
+    observe(progress, progress::Event::FileStarted { path: file, size });
+    let advance = |bytes| {
+        observe(progress, progress::Event::BytesHashed { path: file, bytes });
+        check_cancelled(progress)
+    };
+    let result = match compression {
+        // Progress is still counted in compressed bytes, which add up to the size of the file.
+        Some(compression) => compression
+            .decoder(InspectReader {
+                inner: fp,
+                on_read: advance,
+            })
+            .and_then(|mut decoder| hash_reader(&mut decoder, options.algorithm, |_| Ok(()))),
+        None => match &mut chunks {
+            Some(chunks) => hash_chunks(chunks.as_mut(), options.algorithm, advance),
+            None => hash_reader(&mut fp, options.algorithm, advance),
+        },
+    };
+
+    observe(progress, progress::Event::FileDone { path: file });
+
+    Ok(result.map_err(|source| error::Error::ReadFailed {
+        path: file.to_path_buf(),
+        source,
+    })?)
+}
+
+/// Like `checksum`, but returns the checksum of `file` from `cache` if it is cached and its metadata are unchanged.
+///
+/// Otherwise the file is hashed and its checksum added to the cache. Symbolic links hashed by their target path and
+/// decompressed contents are never cached.
+fn cached_checksum(
+    file: &Path,
+    options: &HashOptions,
+    cache: Option<&Cache>,
+    progress: Option<&dyn Observer>,
+) -> Result<String> {
+    let uncached = options.decompress || (options.link_target && file.is_symlink());
+    let Some((cache, key)) = cache
+        .filter(|_| !uncached)
+        .and_then(|cache| Some((cache, cache::Key::new(file)?)))
+    else {
+        return checksum(file, options, progress);
+    };
+
+    if let Some(checksum) = cache.get(&key, options.algorithm) {
+        let size = fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+        observe(progress, progress::Event::FileSkipped { path: file, size });
+        return Ok(checksum);
+    }
+    // The key is taken before hashing, so that changes while hashing are detected by the next run.
+    let checksum = checksum(file, options, progress)?;
+    cache.insert(key, options.algorithm, checksum.clone());
+    Ok(checksum)
+}
+
+/// Reads `reader` to the end in chunks of `CHUNK_SIZE` and computes the checksum using `algorithm`, returned as a hex
+/// string. `on_read` is called with the number of bytes read after each chunk, reading stops if it returns an error.
+fn hash_reader<R, F>(reader: &mut R, algorithm: Algorithm, on_read: F) -> io::Result<String>
+where
+    R: Read + ?Sized,
+    F: FnMut(u64) -> io::Result<()>,
+{
+    hash_chunks(&mut reader::Buffered::new(reader), algorithm, on_read)
+}
+
+/// Like `hash_reader`, but reads the chunks from `reader`, e.g. a memory mapped file.
+fn hash_chunks<F>(
+    reader: &mut dyn ChunkReader,
+    algorithm: Algorithm,
+    mut on_read: F,
+) -> io::Result<String>
+where
+    F: FnMut(u64) -> io::Result<()>,
+{
+    let mut hasher = Hasher::new(algorithm);
+    loop {
+        let chunk = reader.next_chunk()?;
+        if chunk.is_empty() {
+            return Ok(algorithm.to_hex(&hasher.finalize()));
+        }
+
+        hasher.update(chunk);
+        on_read(chunk.len() as u64)?;
+    }
+}
+
+/// Computes the checksums of `file` using each of `algorithms`, reading it only once.
+///
+/// Returns the checksums in the order of the algorithms. The page cache is bypassed if `direct` is true. If `progress` is
+/// provided it is notified while reading. Any error is propagated with added context.
+fn checksums(
+    file: &Path,
+    algorithms: &[Algorithm],
+    direct: bool,
+    progress: Option<&dyn Observer>,
+) -> Result<Vec<String>> {
+    let fp = reader::open_file(file, direct).map_err(|source| error::Error::OpenFailed {
+        path: file.to_path_buf(),
+        source,
+    })?;
+    let size = fp.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut chunks = reader::open(&fp, IoBackend::Read, direct)
+        .unwrap_or_else(|| Box::new(reader::Buffered::new(fp)));
+    observe(progress, progress::Event::FileStarted { path: file, size });
+    let mut hashers: Vec<_> = algorithms
+        .iter()
+        .map(|&algorithm| Hasher::new(algorithm))
+        .collect();
+    let result = loop {
+        match chunks.next_chunk() {
+            Ok([]) => break Ok(()),
+            Ok(chunk) => {
+                for hasher in &mut hashers {
+                    hasher.update(chunk);
+                }
+                observe(
+                    progress,
+                    progress::Event::BytesHashed {
+                        path: file,
+                        bytes: chunk.len() as u64,
+                    },
+                );
+                if let Err(e) = check_cancelled(progress) {
+                    break Err(e);
+                }
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    observe(progress, progress::Event::FileDone { path: file });
+
+    result.map_err(|source| error::Error::ReadFailed {
+        path: file.to_path_buf(),
+        source,
+    })?;
+    Ok(algorithms
+        .iter()
+        .zip(hashers)
+        .map(|(algorithm, hasher)| algorithm.to_hex(&hasher.finalize()))
+        .collect())
+}
+
+/// Reader which calls `on_read` with the number of bytes read from `inner`, failing if it returns an error.
+struct InspectReader<R, F> {
+    inner: R,
+    on_read: F,
+}
+
+impl<R, F> Read for InspectReader<R, F>
+where
+    R: Read,
+    F: FnMut(u64) -> io::Result<()>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        (self.on_read)(n as u64)?;
+        Ok(n)
+    }
+}
+
+/// Path and size of a member of an archive along with its checksums, or the error reading it.
+type MemberChecksums = (PathBuf, u64, Result<Vec<String>>);
+
+/// Computes checksums of the files stored in `archive` without extracting them to disk.
+///
+/// `select` returns the algorithms used for a member given its path within the archive, members for which it returns
+/// none are skipped. Nested archives are read as well if `descend` returns true for their path. Returns the path and
+/// size of each hashed member along with its checksums in the order of the algorithms, or the error if it could not be
+/// read. If `progress` is provided it is notified after every member. Errors reading the archive itself are propagated
+/// with added context.
+fn archive_checksums<S, D>(
+    archive: &Path,
+    select: S,
+    descend: D,
+    progress: Option<&dyn Observer>,
+) -> Result<Vec<MemberChecksums>>
+where
+    S: Fn(&Path) -> Vec<Algorithm>,
+    D: Fn(&Path) -> bool,
+{
+    let size = fs::metadata(archive).map(|m| m.len()).unwrap_or(0);
+    observe(
+        progress,
+        progress::Event::FileStarted {
+            path: archive,
+            size,
+        },
+    );
+    let mut members = Vec::new();
+    let result = archive_members(archive, descend, |member, reader| {
+        let algorithms = select(&member.path);
+        if algorithms.is_empty() {
+            return Ok(());
+        }
+
+        let checksums = reader.and_then(|reader| {
+            let checksums = if let [algorithm] = algorithms[..] {
+                vec![hash_reader(reader, algorithm, |_| {
+                    check_cancelled(progress)
+                })?]
+            } else {
+                // Members selected with several algorithms are read only once.
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data)?;
+                algorithms
+                    .iter()
+                    .map(|&algorithm| hash_reader(&mut &data[..], algorithm, |_| Ok(())))
+                    .collect::<io::Result<_>>()?
+            };
+            Ok(checksums)
+        });
+        let checksums = checksums.with_context(|| {
+            format!(
+                "Error while reading {} in {}",
+                member.path.display(),
+                archive.display()
+            )
+        });
+        members.push((member.path.clone(), member.size, checksums));
+        observe(
+            progress,
+            progress::Event::BytesHashed {
+                path: archive,
+                bytes: member.compressed_size,
+            },
+        );
+        check_cancelled(progress)?;
+        Ok(())
+    });
+    observe(progress, progress::Event::FileDone { path: archive });
+
+    result.map(|()| members)
+}
+
+/// Applies `map` to all `items` in parallel and passes the results to `sink` in the original order.
+///
+/// Results are handed to `sink` as soon as all preceding items are done, so output can be streamed while hashing is
+/// still in progress. If `ordered` is false, results are handed to `sink` as soon as they are done instead, so that a
+/// slow item does not hold back the others. Any error returned by `sink` stops further processing and is propagated.
+/// Once `observer` cancels, no further items are mapped and results which were not passed to `sink` yet are dropped.
+/// Items are mapped by up to `jobs` threads if given, using a pool built for this call, otherwise by the global thread
+/// pool.
+fn par_map<T, R, M, S>(
+    items: &[T],
+    ordered: bool,
+    jobs: Option<usize>,
+    observer: Option<&dyn Observer>,
+    map: M,
+    mut sink: S,
+) -> Result<()>
+where
+    T: Sync,
+    R: Send,
+    M: Fn(&T) -> R + Sync,
+    S: FnMut(&T, R) -> Result<()>,
+{
+    let pool = jobs
+        .map(|jobs| ThreadPoolBuilder::new().num_threads(jobs).build())
+        .transpose()
+        .context("Failed to create thread pool")?;
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let map_all = || {
+                items
+                    .par_iter()
+                    .enumerate()
+                    .try_for_each_with(tx, |tx, (i, item)| match is_cancelled(observer) {
+                        true => Err(()),
+                        false => tx.send((i, map(item))).map_err(drop),
+                    })
+            };
+            match &pool {
+                Some(pool) => pool.install(map_all),
+                None => map_all(),
+            }
+        });
+
+        let mut pending = Vec::new();
+        pending.resize_with(items.len(), || None);
+        let mut next = 0;
+        for (i, result) in rx {
+            if is_cancelled(observer) {
+                break;
+            }
+            if !ordered {
+                sink(&items[i], result)?;
+                continue;
+            }
+
+            pending[i] = Some(result);
+            while let Some(result) = pending.get_mut(next).and_then(Option::take) {
+                sink(&items[next], result)?;
+                next += 1;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Computes checksums of provided paths and prints them on stdout and optionally writes a output file.
+///
+/// The output file is written in the format given by `manifest_options` or detected from its extension. SFV style files
+/// use SFV format for CRC algorithms and the format used by `md5sum`/`sha256sum` otherwise.
+///
+/// Directories in `paths` are searched for files according to `scan_options.walk_options`. If
+/// `manifest_options.out_file` is `None`, no output file is written. If `progress` is given, the progress of hashing is
+/// reported to it. Results are printed according to `output_options`. Checksums are computed according to
+/// `scan_options.hash_options`. Paths are written according to `scan_options.path_options` and the output file
+/// according to `manifest_options`. If `progress` cancels, the output file is not written.
+///
+/// Paths of files below the current directory are written relative to it, other paths as given. If
+/// `scan_options.path_options.relative_to` is specified, all paths are written relative to that directory instead,
+/// using `..` for files outside of it. If `scan_options.path_options.absolute` is specified, absolute paths are
+/// written.
+///
+/// Returns the counts of hashed files. Unless mimicking cksfv, hashing stops at the first error, which is returned.
+pub fn create_sfv<A>(
+    paths: A,
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let ScanOptions {
+        walk_options,
+        path_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let format = output_options.format;
+    let algorithm = hash_options.algorithm;
+    let style = LineStyle {
+        backslash_separator: path_options.separator.byte() == b'\\',
+        bsd: format == Format::Bsd,
+        raw: false,
+    };
+    let cksfv = manifest_options.compat == Some(Compat::Cksfv);
+    let base = listing_base(path_options)?;
+    // An output file which is updated is found when walking its directory, but must not list itself.
+    let updated = match &manifest_options.out_file {
+        Some(path) if manifest_options.update => Some(absolute_path(path)?),
+        _ => None,
+    };
+    let files = get_all_files(paths, walk_options)?
+        .into_iter()
+        .filter(|path| updated.is_none() || absolute_path(path).ok() != updated)
+        .map(|path| {
+            let file = listed_path(&path, path_options, &base)?;
+            Ok((path, file))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    start_progress(progress, files.iter().map(|(path, _)| path));
+
+    let manifest_format = manifest_options
+        .out_file
+        .as_deref()
+        .map(|path| manifest_options.format(path));
+    let previous = match &manifest_options.out_file {
+        Some(path) if manifest_options.update && path.exists() => {
+            read_previous(path, algorithm, manifest_options)?
+        }
+        _ => Previous::default(),
+    };
+    // The header of an updated file is written again, since the files it lists may have changed.
+    let header_kept = previous.sfv.as_ref().is_some_and(SfvFile::has_header);
+    let mut header = Vec::new();
+    if (manifest_options.header || cksfv || header_kept)
+        && algorithm.is_sfv()
+        && format != Format::Bsd
+        && manifest_format != Some(ManifestFormat::Yaml)
+    {
+        header = sfv_header(&files, path_options, manifest_options);
+        if format == Format::Text && output_options.template.is_none() {
+            for line in &header {
+                print_terminated(progress, line, output_options.terminator());
+            }
+        }
+    }
+    print_start(progress, output_options, files.len())?;
+
+    let mut entries = Vec::new();
+    let mut rows = Vec::new();
+    let mut records = Vec::new();
+    let mut summary = Summary::default();
+    let hashed = now();
+    // The cache only saves time, so failing to open it is not an error.
+    let cache = manifest_options
+        .cache
+        .as_deref()
+        .and_then(|path| match Cache::open(path) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn(&format!("{e:#}, checksums are not cached"));
+                None
+            }
+        });
+    par_map(
+        &files,
+        true,
+        scan_options.jobs,
+        progress,
+        |(path, file)| {
+            if !hash_options.archives || ArchiveKind::detect(path).is_none() {
+                if let Some(entry) = previous.entries.get(&path_options.output(file))
+                    && let Ok(metadata) = fs::metadata(path)
+                    && previous.is_unchanged(
+                        path,
+                        &metadata,
+                        entry,
+                        algorithm,
+                        manifest_options.xattr,
+                    )
+                {
+                    let size = metadata.len();
+                    observe(progress, progress::Event::FileSkipped { path, size });
+                    return Ok(vec![(file.clone(), None, entry.checksum.clone())]);
+                }
+
+                // The modification time is read before hashing, so that changes while hashing are detected later.
+                let store = manifest_options.xattr && path.as_os_str() != STDIN;
+                let mtime =
+                    store.then(|| fs::metadata(path).ok().as_ref().and_then(xattrs::timestamp));
+                let checksum = cached_checksum(path, hash_options, cache.as_ref(), progress)?;
+                if let Some(mtime) = mtime {
+                    let stored = xattrs::Stored {
+                        checksum: checksum.clone(),
+                        algorithm,
+                        mtime: mtime.unwrap_or_default(),
+                    };
+                    xattrs::write(path, &stored)?;
+                }
+                return Ok(vec![(file.clone(), None, checksum)]);
+            }
+
+            // Members are written as if the archive was a directory.
+            // Nested archives are counted by their extension like the archives given.
+            let descend = |member: &Path| {
+                let depth = member
+                    .iter()
+                    .filter(|name| ArchiveKind::detect(Path::new(name)).is_some())
+                    .count();
+                depth < hash_options.archive_depth
+            };
+            archive_checksums(path, |_| vec![algorithm], descend, progress)?
+                .into_iter()
+                .map(|(member, size, checksums)| {
+                    let checksum = checksums?.swap_remove(0);
+                    Ok((file.join(member), Some(size), checksum))
+                })
+                .collect::<Result<Vec<_>>>()
+        },
+        |(path, file), checksums| {
+            let checksums = match checksums {
+                Ok(checksums) => checksums,
+                Err(e) => {
+                    summary.add(Status::Error);
+                    if format == Format::Jsonl {
+                        let record = Record {
+                            path: path_options.output(file).display().to_string(),
+                            checksum: None,
+                            expected: None,
+                            size: None,
+                            modified: None,
+                            status: Status::Error,
+                            error: Some(format!("{e:#}")),
+                        };
+                        print_event(progress, &Event::Error(&record))?;
+                    }
+                    if !cksfv {
+                        return Err(e);
+                    }
+
+                    eprintln!(
+                        "crc32: {}: {}",
+                        path_options.output(file).display(),
+                        error_message(&e)
+                    );
+                    return Ok(());
+                }
+            };
+            for (file, size, checksum) in checksums {
+                let file = path_options.output(&file);
+                let metadata = size.is_none().then(|| fs::metadata(path).ok()).flatten();
+                let record = Record {
+                    path: file.display().to_string(),
+                    checksum: Some(checksum.clone()),
+                    expected: None,
+                    size: size.or(metadata.as_ref().map(fs::Metadata::len)),
+                    modified: metadata.as_ref().and_then(modified_time),
+                    status: Status::Ok,
+                    error: None,
+                };
+                summary.add_record(&record);
+                observe(progress, progress::Event::FileResult(&record));
+                entries.push(Entry {
+                    path: file.clone(),
+                    checksum: checksum.clone(),
+                    size: record.size,
+                    algorithm: None,
+                });
+                if manifest_options.db.is_some() {
+                    rows.push(db::Row {
+                        path: file.clone(),
+                        size: record.size,
+                        mtime: record.modified.clone(),
+                        algorithm,
+                        checksum: checksum.clone(),
+                    });
+                }
+
+                let text = matches!(format, Format::Text | Format::Bsd);
+                if text && output_options.template.is_none() {
+                    let line = algorithm.format_line(
+                        file.as_os_str().as_encoded_bytes(),
+                        &checksum,
+                        LineStyle {
+                            raw: output_options.print0,
+                            ..style
+                        },
+                    );
+                    print_terminated(progress, &line, output_options.terminator());
+                } else if text {
+                    print_text(progress, output_options, &record, algorithm);
+                } else {
+                    print_record(progress, output_options, record, &mut records)?;
+                }
+            }
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+    if let Some(Err(e)) = cache.map(Cache::save) {
+        warn(&format!("{e:#}"));
+    }
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+    // The output file and database would lack the files which were not hashed yet.
+    if summary.cancelled {
+        return Ok(summary);
+    }
+
+    if let (Some(path), Some(manifest_format)) = (&manifest_options.out_file, manifest_format) {
+        let duplicates = dedup(&mut entries)
+            .with_context(|| format!("Failed to write to {}", path.display()))?;
+        for duplicate in duplicates {
+            warn(&format!(
+                "{} was given more than once and is only written once",
+                duplicate.display()
+            ));
+        }
+        if manifest_options.update {
+            print_changes(&previous.entries, &entries);
+        }
+        let out_text = match previous.sfv {
+            Some(mut sfv) if manifest_format == ManifestFormat::Sfv => {
+                sfv.replace(entries, &header);
+                sfv.write(algorithm, style)
+            }
+            _ => manifest_format
+                .write(&entries, algorithm, &header, style)
+                .with_context(|| format!("Failed to write to {}", path.display()))?,
+        };
+        let out_text = manifest_options
+            .encoding
+            .encode(&out_text)
+            .with_context(|| format!("Failed to encode {}", path.display()))?;
+        fs::write(path, out_text)
+            .with_context(|| format!("Failed to write to {}", path.display()))?;
+
+        if let Some(redundancy) = manifest_options.par2 {
+            write_par2(path, files.iter().map(|(path, _)| path), redundancy)?;
+        }
+    }
+
+    if let Some(path) = &manifest_options.db {
+        db::Database::open(path)?.store(&rows, &hashed)?;
+    }
+
+    Ok(summary)
+}
+
+/// Returns the absolute directory which paths in checksum files are relative to, i.e. the directory given by
+/// `path_options.relative_to` or the current directory.
+fn listing_base(path_options: &PathOptions) -> Result<PathBuf> {
+    match &path_options.relative_to {
+        Some(dir) => absolute_path(dir),
+        None => absolute_path(&env::current_dir().context("Failed to get current directory")?),
+    }
+}
+
+/// Returns the path under which the file at `path` is listed in checksum files, before `path_options.output` is
+/// applied.
+///
+/// Paths are relative to `base` as returned by `listing_base`, or absolute if `path_options.absolute` is specified.
+/// Paths outside of `base` are listed as given unless `path_options.relative_to` is specified, as is stdin.
+fn listed_path(path: &Path, path_options: &PathOptions, base: &Path) -> Result<PathBuf> {
+    let canonical = if path.as_os_str() == STDIN {
+        None
+    } else {
+        Some(absolute_path(path)?)
+    };
+    Ok(canonical
+        .and_then(|canonical| match path_options.relative_to {
+            _ if path_options.absolute => Some(canonical),
+            Some(_) => relative_path(&canonical, base),
+            None => canonical.strip_prefix(base).map(Path::to_path_buf).ok(),
+        })
+        .unwrap_or_else(|| path.to_path_buf()))
+}
+
+/// Checksum file updated with `--update`, as read by `read_previous`.
+#[derive(Default)]
+struct Previous {
+    /// Listed entries by path.
+    entries: HashMap<PathBuf, Entry>,
+    /// Files listed in the comment header of an SFV style file by path.
+    header_files: HashMap<PathBuf, HeaderFile>,
+    /// Whole file if it is an SFV style file, whose comments are kept when it is written again.
+    sfv: Option<SfvFile>,
+}
+
+impl Previous {
+    /// Returns whether the file at `path` with `metadata`, which is listed as `entry` with a checksum of `algorithm`,
+    /// is unchanged and its checksum can be kept.
+    ///
+    /// A file is unchanged if its size and modification time match the ones listed in the comment header or, if
+    /// `xattr` is specified, its modification time and checksum match the ones stored in its extended attributes. The
+    /// header only lists modification times to the second. Files without either are considered modified.
+    fn is_unchanged(
+        &self,
+        path: &Path,
+        metadata: &fs::Metadata,
+        entry: &Entry,
+        algorithm: Algorithm,
+        xattr: bool,
+    ) -> bool {
+        if entry.size.is_some_and(|size| size != metadata.len()) {
+            return false;
+        }
+        if let Some(listed) = self.header_files.get(&entry.path) {
+            let modified = metadata.modified().map(DateTime::<Local>::from);
+            return listed.size == metadata.len()
+                && modified.is_ok_and(|modified| {
+                    modified.format(HEADER_TIME).to_string() == listed.modified
+                });
+        }
+        if xattr && let Ok(Some(stored)) = xattrs::read(path) {
+            return stored.algorithm == algorithm
+                && stored.checksum.eq_ignore_ascii_case(&entry.checksum)
+                && xattrs::timestamp(metadata).is_some_and(|mtime| mtime == stored.mtime);
+        }
+        false
+    }
+}
+
+/// Reads the existing checksum file at `path` for `--update`.
+///
+/// Returns the file without entries of other algorithms listed in BSD style lines. Returns an error if the file cannot
+/// be read or lists checksums of an algorithm other than `algorithm`.
+fn read_previous(
+    path: &Path,
+    algorithm: Algorithm,
+    manifest_options: &ManifestOptions,
+) -> Result<Previous> {
+    let data = fs::read(path).with_context(|| format!("Failed to read file {}", path.display()))?;
+    let data = manifest_options.encoding.decode(&data);
+    let manifest_format = manifest_options.read_format(path, &data);
+    if let Some(detected) = manifest_format.detect_algorithm(path, &data)
+        && detected != algorithm
+    {
+        return Err(Error::msg(format!(
+            "{} lists {} checksums, select the algorithm with --algorithm",
+            path.display(),
+            detected.tag()
+        )));
+    }
+
+    // SFV style files are kept whole, so that their comments can be written back.
+    let (listed, sfv) = match manifest_format {
+        ManifestFormat::Sfv => {
+            let sfv = SfvFile::parse(&data, algorithm, manifest_options.strict)
+                .with_context(|| format!("Failed to read file {}", path.display()))?;
+            (sfv.entries.clone(), Some(sfv))
+        }
+        _ => {
+            let listed = manifest_format
+                .read(&data, algorithm, manifest_options.strict)
+                .with_context(|| format!("Failed to read file {}", path.display()))?;
+            (listed, None)
+        }
+    };
+    let entries = listed
+        .into_iter()
+        .filter(|entry| {
+            entry
+                .algorithm
+                .is_none_or(|entry_algorithm| entry_algorithm == algorithm)
+        })
+        .map(|entry| (entry.path.clone(), entry))
+        .collect();
+    Ok(Previous {
+        entries,
+        header_files: sfv.as_ref().map(SfvFile::header_files).unwrap_or_default(),
+        sfv,
+    })
+}
+
+/// Prints on stderr how the entries of a checksum file updated with `--update` differ from the `previous` ones.
+fn print_changes(previous: &HashMap<PathBuf, Entry>, entries: &[Entry]) {
+    let current: HashSet<_> = entries.iter().map(|entry| &entry.path).collect();
+    for entry in entries {
+        match previous.get(&entry.path) {
+            None => eprintln!("{} {}", entry.path.display(), "ADDED".green().bold()),
+            Some(old) if !old.checksum.eq_ignore_ascii_case(&entry.checksum) => eprintln!(
+                "{} {} {} → {}",
+                entry.path.display(),
+                "CHANGED".yellow().bold(),
+                old.checksum,
+                entry.checksum
+            ),
+            Some(_) => {}
+        }
+    }
+
+    let mut removed: Vec<_> = previous
+        .keys()
+        .filter(|path| !current.contains(path))
+        .collect();
+    removed.sort();
+    for path in removed {
+        eprintln!("{} {}", path.display(), "REMOVED".red().bold());
+    }
+}
+
+/// Keeps the checksum file `manifest_options.out_file` of the files in `paths` up to date until interrupted.
+///
+/// Directories in `paths` are searched for files according to `scan_options.walk_options` and watched for changes, as
+/// are the directories of files in `paths`. Whenever files change, the files are collected again and new or modified
+/// files are hashed according to `scan_options.hash_options`, while entries of removed files are dropped. Files
+/// modified within `debounce` are assumed to still be written and are only hashed once they have not been modified for
+/// that long. Entries of an existing output file are kept for files which are unchanged like with `--update`. The
+/// output file is replaced atomically after every change and the changes are printed on stderr unless
+/// `output_options.status` is specified. Files which cannot be read are reported as warnings and hashed again after the
+/// next change.
+///
+/// Only returns if watching fails, with the counts of files by status of the last update.
+pub fn watch_sfv(
+    paths: Vec<PathBuf>,
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    output_options: &OutputOptions,
+    debounce: Duration,
+) -> Result<Summary> {
+    let ScanOptions {
+        walk_options,
+        path_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let out_file = manifest_options
+        .out_file
+        .as_deref()
+        .expect("Watching should require an output file");
+    let out_path = absolute_path(out_file)?;
+    let mut temp_path = out_path.clone().into_os_string();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    let algorithm = hash_options.algorithm;
+    let style = LineStyle {
+        backslash_separator: path_options.separator.byte() == b'\\',
+        bsd: output_options.format == Format::Bsd,
+        raw: false,
+    };
+    let base = listing_base(path_options)?;
+
+    // Entries by path along with the modification time of the file when it was hashed, which is unknown for entries
+    // read from an existing output file.
+    let mut previous = if out_file.exists() {
+        read_previous(out_file, algorithm, manifest_options)?
+    } else {
+        Previous::default()
+    };
+    let mut known: HashMap<PathBuf, (Entry, Option<SystemTime>)> = mem::take(&mut previous.entries)
+        .into_iter()
+        .map(|(path, entry)| (path, (entry, None)))
+        .collect();
+
+    // Updates `known` and the output file, returning whether files were left for later as they are still written.
+    let update = |known: &mut HashMap<PathBuf, (Entry, Option<SystemTime>)>,
+                  summary: &mut Summary|
+     -> Result<bool> {
+        let files = get_all_files(paths.iter().cloned(), walk_options)?;
+        let now = SystemTime::now();
+        let mut current = HashMap::new();
+        let mut pending = false;
+        *summary = Summary::default();
+        for path in files {
+            let absolute = absolute_path(&path)?;
+            if absolute == out_path || absolute == temp_path {
+                continue;
+            }
+            let file = path_options.output(&listed_path(&path, path_options, &base)?);
+            let old = known.get(&file);
+            // Files removed since they were collected are dropped.
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let modified = metadata.modified().ok();
+
+            if modified.is_some_and(|modified| {
+                now.duration_since(modified).is_ok_and(|age| age < debounce)
+            }) {
+                pending = true;
+                if let Some(old) = old {
+                    current.insert(file, old.clone());
+                }
+                continue;
+            }
+            if let Some((entry, hashed)) = old
+                && entry.size.is_none_or(|size| size == metadata.len())
+                && match hashed {
+                    Some(hashed) => modified == Some(*hashed),
+                    None => previous.is_unchanged(
+                        &path,
+                        &metadata,
+                        entry,
+                        algorithm,
+                        manifest_options.xattr,
+                    ),
+                }
+            {
+                summary.add(Status::Ok);
+                current.insert(file, (entry.clone(), *hashed));
+                continue;
+            }
+
+            match checksum(&path, hash_options, None) {
+                Ok(checksum) => {
+                    summary.add(Status::Ok);
+                    let entry = Entry {
+                        path: file.clone(),
+                        checksum,
+                        size: Some(metadata.len()),
+                        algorithm: None,
+                    };
+                    current.insert(file, (entry, modified));
+                }
+                Err(e) => {
+                    summary.add(Status::Error);
+                    warn(&format!("{e:#}"));
+                    if let Some(old) = old {
+                        current.insert(file, old.clone());
+                    }
+                }
+            }
+        }
+
+        let previous: HashMap<_, _> = known
+            .drain()
+            .map(|(path, (entry, _))| (path, entry))
+            .collect();
+        let mut entries: Vec<_> = current.values().map(|(entry, _)| entry.clone()).collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        *known = current;
+        let changed = entries.len() != previous.len()
+            || entries.iter().any(|entry| {
+                previous
+                    .get(&entry.path)
+                    .is_none_or(|old| !old.checksum.eq_ignore_ascii_case(&entry.checksum))
+            });
+        if !changed && out_path.exists() {
+            return Ok(pending);
+        }
+
+        if !output_options.status {
+            print_changes(&previous, &entries);
+        }
+        let out_text = manifest_options
+            .format(out_file)
+            .write(&entries, algorithm, &[], style)
+            .with_context(|| format!("Failed to write to {}", out_file.display()))?;
+        let out_text = manifest_options
+            .encoding
+            .encode(&out_text)
+            .with_context(|| format!("Failed to encode {}", out_file.display()))?;
+        fs::write(&temp_path, out_text)
+            .and_then(|()| fs::rename(&temp_path, &out_path))
+            .with_context(|| format!("Failed to write to {}", out_file.display()))?;
+        Ok(pending)
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).context("Failed to watch files")?;
+    for path in &paths {
+        let path = absolute_path(path)?;
+        let (dir, mode) = if path.is_dir() && walk_options.recursive {
+            (path.as_path(), RecursiveMode::Recursive)
+        } else if path.is_dir() {
+            (path.as_path(), RecursiveMode::NonRecursive)
+        } else {
+            // Files are often replaced rather than modified, so their directory is watched.
+            (path.parent().unwrap_or(&path), RecursiveMode::NonRecursive)
+        };
+        watcher
+            .watch(dir, mode)
+            .with_context(|| format!("Failed to watch {}", path.display()))?;
+    }
+
+    let mut summary = Summary::default();
+    // Time of the last change which the output file has not been updated for yet.
+    let mut changed = update(&mut known, &mut summary)?.then(Instant::now);
+    loop {
+        match receiver.recv_timeout(WATCH_INTERVAL) {
+            // Reading files while hashing them causes access events, which are ignored along with events caused by
+            // writing the output file.
+            Ok(Ok(event))
+                if !event.kind.is_access()
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| *path != out_path && *path != temp_path) =>
+            {
+                changed = Some(Instant::now());
+            }
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {}
+            Ok(Err(e)) => warn(&format!("Failed to watch files: {e}")),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if changed.is_some_and(|time| time.elapsed() >= debounce) {
+            changed = match update(&mut known, &mut summary) {
+                Ok(pending) => pending.then(Instant::now),
+                Err(e) => {
+                    warn(&format!("{e:#}"));
+                    None
+                }
+            };
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Writes PAR2 recovery data for `files` with the given redundancy in percent next to the checksum file at `path`,
+/// replacing its extension with `.par2`.
+///
+/// Names in the recovery file are relative to its directory, so files outside of it cannot be protected and cause an
+/// error.
+fn write_par2<'a, I>(path: &Path, files: I, redundancy: u32) -> Result<()>
+where
+    I: IntoIterator<Item = &'a PathBuf>,
+{
+    let par2_path = path.with_extension("par2");
+    let dir = absolute_path(path)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let mut sources = Vec::new();
+    for file in files {
+        if file.as_os_str() == STDIN {
+            continue;
+        }
+
+        let name = absolute_path(file)?
+            .strip_prefix(&dir)
+            .map(Path::to_path_buf)
+            .with_context(|| {
+                format!(
+                    "Cannot write recovery data for {} outside of {}",
+                    file.display(),
+                    dir.display()
+                )
+            })?;
+        sources.push((file.clone(), name));
+    }
+    par2::create(&par2_path, &sources, redundancy)
+}
+
+/// Merges the checksum files `sfv_files` into the output file of `manifest_options`.
+///
+/// The checksum files are read like when verifying and their entries are written in the order listed, leaving out
+/// entries listing the same path with the same checksum as a previous one. Paths are written according to
+/// `scan_options.path_options`. If `rebase` is specified, relative paths are rewritten to be relative to the directory
+/// of the output file instead of the directory of their checksum file. The output file is written in the format given
+/// by `manifest_options` or detected from its extension and the style given by `output_options`.
+///
+/// Returns the counts of merged entries. If any path is listed with different checksums, the conflicts are printed on
+/// stderr and an error is returned without writing the output file. Checksum files of different algorithms cannot be
+/// merged either.
+pub fn merge_sfv(
+    sfv_files: &[PathBuf],
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    verify_options: &VerifyOptions,
+    output_options: &OutputOptions,
+    rebase: bool,
+) -> Result<Summary> {
+    let ScanOptions {
+        path_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let out_file = manifest_options
+        .out_file
+        .as_deref()
+        .expect("Merging should require an output file");
+    let out_dir = absolute_path(out_file)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let mut algorithm = None;
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut sources: HashMap<PathBuf, (usize, &Path)> = HashMap::new();
+    let mut conflicts = 0;
+    for sfv_file in sfv_files {
+        let data = fs::read(sfv_file)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let data = manifest_options.encoding.decode(&data);
+        let manifest_format = manifest_options.read_format(sfv_file, &data);
+        let file_algorithm = verify_options
+            .detect_algorithm
+            .then(|| manifest_format.detect_algorithm(sfv_file, &data))
+            .flatten()
+            .unwrap_or(hash_options.algorithm);
+        let listed = manifest_format
+            .read(&data, file_algorithm, manifest_options.strict)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let base_dir = absolute_path(sfv_file)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        for mut entry in listed {
+            let entry_algorithm = entry.algorithm.take().unwrap_or(file_algorithm);
+            match algorithm {
+                None => algorithm = Some(entry_algorithm),
+                Some(algorithm) if algorithm != entry_algorithm => {
+                    return Err(Error::msg(format!(
+                        "Cannot merge {} checksums of {} with {} checksums",
+                        entry_algorithm.tag(),
+                        sfv_file.display(),
+                        algorithm.tag()
+                    )));
+                }
+                Some(_) => {}
+            }
+
+            if rebase && entry.path.is_relative() {
+                let path = absolute_path(&base_dir.join(&entry.path))?;
+                entry.path = relative_path(&path, &out_dir).unwrap_or(path);
+            }
+            entry.path = path_options.output(&entry.path);
+            match sources.get(&entry.path) {
+                None => {
+                    sources.insert(entry.path.clone(), (entries.len(), sfv_file));
+                    entries.push(entry);
+                }
+                Some(&(index, source))
+                    if !entries[index]
+                        .checksum
+                        .eq_ignore_ascii_case(&entry.checksum) =>
+                {
+                    conflicts += 1;
+                    eprintln!(
+                        "{} {} {} in {} ≠ {} in {}",
+                        entry.path.display(),
+                        "CONFLICT".red().bold(),
+                        entries[index].checksum,
+                        source.display(),
+                        entry.checksum,
+                        sfv_file.display()
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    if conflicts > 0 {
+        return Err(Error::msg(format!(
+            "Conflicting checksums for {conflicts} paths, {} was not written",
+            out_file.display()
+        )));
+    }
+
+    let algorithm = algorithm.unwrap_or(hash_options.algorithm);
+    let style = LineStyle {
+        backslash_separator: path_options.separator.byte() == b'\\',
+        bsd: output_options.format == Format::Bsd,
+        raw: false,
+    };
+    let out_text = manifest_options
+        .format(out_file)
+        .write(&entries, algorithm, &[], style)
+        .with_context(|| format!("Failed to write to {}", out_file.display()))?;
+    let out_text = manifest_options
+        .encoding
+        .encode(&out_text)
+        .with_context(|| format!("Failed to encode {}", out_file.display()))?;
+    fs::write(out_file, out_text)
+        .with_context(|| format!("Failed to write to {}", out_file.display()))?;
+
+    let mut summary = Summary::default();
+    for _ in &entries {
+        summary.add(Status::Ok);
+    }
+    Ok(summary)
+}
+
+/// Converts the checksum files `sfv_files` into the output file of `manifest_options`.
+///
+/// The output file is written in the format given by `manifest_options` or detected from its extension and the style
+/// given by `output_options`, e.g. to convert SFV files to JSON or coreutils style files to BSD style. Relative paths
+/// are rewritten to be relative to the directory of the output file and according to `scan_options.path_options`. If
+/// `algorithm` differs from the algorithm of a checksum file, its files are read to compute checksums using
+/// `algorithm`, verifying them against the listed checksums at the same time. Files which do not match or cannot be
+/// read are reported like when verifying and left out of the output file, so that corruption is not carried over. If
+/// `progress` is given, the progress of hashing is reported to it. If it cancels, the output file is not written.
+///
+/// Returns the counts of converted entries by status. Checksum files listing a path with different checksums or
+/// converted to different algorithms without selecting one cause an error.
+pub fn convert_sfv(
+    sfv_files: &[PathBuf],
+    algorithm: Option<Algorithm>,
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let path_options = &scan_options.path_options;
+    let out_file = manifest_options
+        .out_file
+        .as_deref()
+        .expect("Converting should require an output file");
+    let out_dir = absolute_path(out_file)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    // Entries along with the file listed, the algorithm they were read with and whether they need rehashing.
+    let mut entries = Vec::new();
+    let mut target = algorithm;
+    for sfv_file in sfv_files {
+        let data = fs::read(sfv_file)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let data = manifest_options.encoding.decode(&data);
+        let manifest_format = manifest_options.read_format(sfv_file, &data);
+        let file_algorithm = manifest_format
+            .detect_algorithm(sfv_file, &data)
+            .or(algorithm)
+            .unwrap_or(Algorithm::Crc32);
+        let listed = manifest_format
+            .read(&data, file_algorithm, manifest_options.strict)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let base_dir = absolute_path(sfv_file)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        for mut entry in listed {
+            let source = entry.algorithm.take().unwrap_or(file_algorithm);
+            let target = *target.get_or_insert(source);
+            if algorithm.is_none() && source != target {
+                return Err(Error::msg(format!(
+                    "{} lists {} checksums, select the algorithm to convert them to with --algorithm",
+                    sfv_file.display(),
+                    source.tag()
+                )));
+            }
+
+            let file = resolve(
+                &base_dir.join(path_options.rewrite(&entry.path)),
+                path_options,
+            );
+            if entry.path.is_relative() {
+                let path = absolute_path(&base_dir.join(&entry.path))?;
+                entry.path = relative_path(&path, &out_dir).unwrap_or(path);
+            }
+            entry.path = path_options.output(&entry.path);
+            entries.push((entry, file, source, source != target));
+        }
+    }
+    let target = target.unwrap_or(Algorithm::Crc32);
+
+    let rehashed: Vec<_> = entries.iter().filter(|(.., rehash)| *rehash).collect();
+    start_progress(progress, rehashed.iter().map(|(_, file, ..)| file));
+    let mut records = Vec::new();
+    let mut summary = Summary::default();
+    let mut converted = HashMap::new();
+    if !rehashed.is_empty() {
+        print_start(progress, output_options, rehashed.len())?;
+    }
+    par_map(
+        &rehashed,
+        true,
+        scan_options.jobs,
+        progress,
+        |(_, file, source, _)| {
+            checksums(
+                file,
+                &[*source, target],
+                scan_options.hash_options.direct,
+                progress,
+            )
+        },
+        |(entry, file, source, _), computed| {
+            let (old, new) = match computed {
+                Ok(computed) => (Ok(computed[0].clone()), Some(computed[1].clone())),
+                Err(e) => (Err(e), None),
+            };
+            let mut record = Record::verified(file, &entry.checksum, old);
+            record.path = entry.path.display().to_string();
+            if record.status == Status::Ok {
+                converted.insert(entry.path.clone(), new);
+            }
+            summary.add_record(&record);
+            observe(progress, progress::Event::FileResult(&record));
+            print_result(progress, output_options, record, *source, &mut records)?;
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+    // The output file would lack the entries which were not converted yet.
+    if summary.cancelled {
+        print_collected(output_options, &records, &summary)?;
+        print_summary(progress, output_options, &summary)?;
+        return Ok(summary);
+    }
+
+    let mut written = Vec::new();
+    for (mut entry, _, _, rehash) in entries {
+        if rehash {
+            match converted.remove(&entry.path) {
+                Some(Some(checksum)) => entry.checksum = checksum,
+                _ => continue,
+            }
+        } else {
+            entry.checksum = target.normalize_hex(&entry.checksum);
+            summary.add(Status::Ok);
+        }
+        written.push(entry);
+    }
+    dedup(&mut written).with_context(|| format!("Failed to convert to {}", out_file.display()))?;
+
+    let style = LineStyle {
+        backslash_separator: path_options.separator.byte() == b'\\',
+        bsd: output_options.format == Format::Bsd,
+        raw: false,
+    };
+    let out_text = manifest_options
+        .format(out_file)
+        .write(&written, target, &[], style)
+        .with_context(|| format!("Failed to write to {}", out_file.display()))?;
+    let out_text = manifest_options
+        .encoding
+        .encode(&out_text)
+        .with_context(|| format!("Failed to encode {}", out_file.display()))?;
+    fs::write(out_file, out_text)
+        .with_context(|| format!("Failed to write to {}", out_file.display()))?;
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Normalizes the checksum files `sfv_files` in place without hashing any files.
+///
+/// Entries are sorted by path and entries listing the same path with the same checksum as a previous one are removed.
+/// Backslashes in paths are taken for separators and paths are rewritten according to `scan_options.path_options`, e.g.
+/// to convert separators, checksums are written in the case used when creating checksums and lines are terminated by
+/// line feeds. Comment lines of SFV style files are kept at the start. BSD style files stay in BSD style, other files
+/// are written in BSD style if selected by `output_options`. Prints a line per checksum file with the number of entries
+/// and removed duplicates.
+///
+/// Returns the counts of normalized checksum files. Files listing a path with different checksums or checksums of
+/// several algorithms are not changed and cause an error.
+pub fn fix_sfv(
+    sfv_files: &[PathBuf],
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    verify_options: &VerifyOptions,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let ScanOptions {
+        path_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let mut summary = Summary::default();
+    for sfv_file in sfv_files {
+        let data = fs::read(sfv_file)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let data = manifest_options.encoding.decode(&data);
+        let manifest_format = manifest_options.read_format(sfv_file, &data);
+        let algorithm = verify_options
+            .detect_algorithm
+            .then(|| manifest_format.detect_algorithm(sfv_file, &data))
+            .flatten()
+            .unwrap_or(hash_options.algorithm);
+        let mut entries = manifest_format
+            .read(&data, algorithm, manifest_options.strict)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        if let Some(other) = entries.iter().find_map(|entry| entry.algorithm) {
+            return Err(Error::msg(format!(
+                "{} lists {} and {} checksums and cannot be normalized",
+                sfv_file.display(),
+                algorithm.tag(),
+                other.tag()
+            )));
+        }
+
+        let count = entries.len();
+        for entry in &mut entries {
+            // Backslashes are taken for separators written on Windows, so that they are converted as well.
+            let bytes = entry.path.as_os_str().as_encoded_bytes();
+            let bytes: Vec<_> = bytes
+                .iter()
+                .map(|&b| if b == b'\\' { b'/' } else { b })
+                .collect();
+            entry.path = path_options.output(&path_from_bytes(&bytes));
+            entry.checksum = algorithm.normalize_hex(&entry.checksum);
+        }
+        entries.sort_by(|a, b| {
+            a.path
+                .as_os_str()
+                .as_encoded_bytes()
+                .cmp(b.path.as_os_str().as_encoded_bytes())
+        });
+        dedup(&mut entries)
+            .with_context(|| format!("Failed to normalize {}", sfv_file.display()))?;
+
+        let lines = || {
+            data.split(|&b| b == b'\n')
+                .map(|line| line.trim_ascii())
+                .filter(|line| !line.is_empty())
+        };
+        let sfv = manifest_format == ManifestFormat::Sfv;
+        let comments: Vec<Vec<u8>> = lines()
+            .filter(|line| sfv && line.starts_with(b";"))
+            .map(<[u8]>::to_vec)
+            .collect();
+        let bsd = output_options.format == Format::Bsd
+            || (sfv && lines().any(|line| Algorithm::from_bsd_line(line).is_some()));
+        let style = LineStyle {
+            backslash_separator: path_options.separator.byte() == b'\\',
+            bsd,
+            raw: false,
+        };
+        let out_text = manifest_format
+            .write(&entries, algorithm, &comments, style)
+            .with_context(|| format!("Failed to write to {}", sfv_file.display()))?;
+        let out_text = manifest_options
+            .encoding
+            .encode(&out_text)
+            .with_context(|| format!("Failed to encode {}", sfv_file.display()))?;
+        fs::write(sfv_file, out_text)
+            .with_context(|| format!("Failed to write to {}", sfv_file.display()))?;
+
+        println!(
+            "{}: {} entries, duplicates removed: {}",
+            sfv_file.display(),
+            entries.len(),
+            count - entries.len()
+        );
+        summary.add(Status::Ok);
+    }
+
+    Ok(summary)
+}
+
+/// Returns the comment lines written at the start of SFV files for the given files and their names.
+///
+/// Like cksfv, the header names the generator and the time of creation, followed by one line per file with its size
+/// and modification time. Names are written according to `path_options`. When mimicking cksfv its project web site is
+/// named as well.
+fn sfv_header(
+    files: &[(PathBuf, PathBuf)],
+    path_options: &PathOptions,
+    manifest_options: &ManifestOptions,
+) -> Vec<Vec<u8>> {
+    let now = Local::now();
+    let mut lines = vec![
+        format!(
+            "; Generated by crc32 v{} on {} at {}",
+            env!("CARGO_PKG_VERSION"),
+            now.format("%Y-%m-%d"),
+            now.format("%H:%M.%S")
+        )
+        .into_bytes(),
+    ];
+    if manifest_options.compat == Some(Compat::Cksfv) {
+        lines.push(CKSFV_WEB_SITE.as_bytes().to_vec());
+    }
+    lines.push(b";".to_vec());
+    for (path, file) in files {
+        let Ok(metadata) = fs::metadata(path) else {
+            continue;
+        };
+        let modified: DateTime<Local> = metadata.modified().map_or(now, DateTime::from);
+        let mut line =
+            format!(";{:>13}  {} ", metadata.len(), modified.format(HEADER_TIME)).into_bytes();
+        line.extend(path_options.output(file).as_os_str().as_encoded_bytes());
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Verify checksum files.
+///
+/// Read each of `sfv_files` according to `manifest_options`, compute checksums of the listed files according to
+/// `scan_options.hash_options` and match them with values in the checksum files. Relative paths are resolved against
+/// `scan_options.path_options.base_dir`, which defaults to the directory containing each checksum file. Paths are
+/// rewritten and files are looked up according to `scan_options.path_options`. If several checksum files are verified,
+/// paths are shown prefixed by the directory of their checksum file. If `progress` is given, the progress of hashing is
+/// reported to it. Results of all checksum files are printed together according to `output_options`, or on stderr like
+/// cksfv does if mimicking it. If `output_options.quiet` is specified, plain text lines are only printed for files
+/// which do not match. If `output_options.status` is specified, nothing is printed. If `verify_options.check_extra` is
+/// specified, files in the base directories which are not listed are reported afterwards. Returns the counts of
+/// verified files by status.
+pub fn verify_sfv(
+    sfv_files: &[PathBuf],
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    verify_options: &VerifyOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let ScanOptions {
+        walk_options,
+        path_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let format = output_options.format;
+    let silent = output_options.status;
+    let cksfv = manifest_options.compat == Some(Compat::Cksfv)
+        && matches!(format, Format::Text | Format::Bsd)
+        && !silent;
+
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    let mut base_dirs = Vec::new();
+    for (index, sfv_file) in sfv_files.iter().enumerate() {
+        let data = fs::read(sfv_file)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let data = manifest_options.encoding.decode(&data);
+        let manifest_format = manifest_options.read_format(sfv_file, &data);
+        let algorithm = verify_options
+            .detect_algorithm
+            .then(|| manifest_format.detect_algorithm(sfv_file, &data))
+            .flatten()
+            .unwrap_or(hash_options.algorithm);
+        let mut listed = manifest_format
+            .read(&data, algorithm, manifest_options.strict)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let duplicates = dedup(&mut listed)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        for duplicate in duplicates.iter().filter(|_| !silent) {
+            warn(&format!(
+                "{} lists {} more than once",
+                sfv_file.display(),
+                duplicate.display()
+            ));
+        }
+
+        let base_dir = match &path_options.base_dir {
+            Some(dir) => dir.clone(),
+            None => fs::canonicalize(sfv_file)
+                .with_context(|| {
+                    format!("Failed to get canonical path for {}", sfv_file.display())
+                })?
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+        };
+        // Paths of several checksum files are distinguished by the directory they were found in.
+        let prefix = match sfv_file.parent() {
+            Some(parent) if sfv_files.len() > 1 && path_options.base_dir.is_none() => {
+                parent.strip_prefix(".").unwrap_or(parent).to_path_buf()
+            }
+            _ => PathBuf::new(),
+        };
+
+        for Entry {
+            path,
+            checksum,
+            algorithm: entry_algorithm,
+            ..
+        } in listed
+        {
+            let selected = verify_options.selects(&path);
+            let path = path_options.rewrite(&path);
+            if !selected {
+                // Skipped files are still listed and must not be reported as extra files.
+                if verify_options.check_extra {
+                    skipped.push(resolve(&base_dir.join(&path), path_options));
+                }
+                continue;
+            }
+
+            let file = resolve(&base_dir.join(&path), path_options);
+            let path = match file.strip_prefix(&base_dir) {
+                Ok(relative) if path.is_relative() => prefix.join(relative),
+                _ => file.clone(),
+            };
+            let hash_options = HashOptions {
+                algorithm: entry_algorithm.unwrap_or(algorithm),
+                ..*hash_options
+            };
+            entries.push((index, path, file, checksum, hash_options));
+        }
+        if !base_dirs.iter().any(|(dir, _)| *dir == base_dir) {
+            base_dirs.push((base_dir, prefix));
+        }
+    }
+
+    // Members of archives are verified together, so that each archive is only read once.
+    let mut units: Vec<(Option<&Path>, Vec<usize>)> = Vec::new();
+    let mut archives = HashMap::new();
+    for (i, (_, _, file, _, _)) in entries.iter().enumerate() {
+        match split_member_path(file) {
+            Some((archive, _)) => {
+                let unit = *archives.entry(archive).or_insert_with(|| {
+                    units.push((Some(archive), Vec::new()));
+                    units.len() - 1
+                });
+                units[unit].1.push(i);
+            }
+            None => units.push((None, vec![i])),
+        }
+    }
+
+    start_progress(
+        progress,
+        units.iter().map(|(archive, unit)| match archive {
+            Some(archive) => archive,
+            None => entries[unit[0]].2.as_path(),
+        }),
+    );
+    if !silent {
+        print_start(progress, output_options, entries.len())?;
+    }
+
+    let mut records = Vec::new();
+    let mut summary = Summary {
+        ignore_missing: verify_options.ignore_missing,
+        ..Summary::default()
+    };
+    let mut report = |record: Record, algorithm: Algorithm, error: Option<String>| -> Result<()> {
+        summary.add_record(&record);
+        observe(progress, progress::Event::FileResult(&record));
+        match format {
+            _ if silent => {}
+            Format::Text | Format::Bsd if cksfv => {
+                if !output_options.quiet || record.status != Status::Ok {
+                    eprintln!("{}", cksfv_line(&record, error.as_deref()))
+                }
+            }
+            _ => print_result(progress, output_options, record, algorithm, &mut records)?,
+        }
+
+        Ok(())
+    };
+    // Like cksfv, a banner is printed before the results of each checksum file.
+    let mut banners = sfv_files.iter().enumerate().peekable();
+    let mut print_banners = |until: usize| {
+        while let Some((_, sfv_file)) = banners.next_if(|&(index, _)| index <= until) {
+            if cksfv {
+                eprintln!("{}", cksfv_banner(sfv_file));
+            }
+        }
+    };
+    par_map(
+        &units,
+        !verify_options.unordered,
+        scan_options.jobs,
+        progress,
+        |(archive, unit)| match archive {
+            Some(archive) => verify_members(archive, unit.iter().map(|&i| &entries[i]), progress),
+            None => {
+                let (_, _, file, _, hash_options) = &entries[unit[0]];
+                vec![(checksum(file, hash_options, progress), None)]
+            }
+        },
+        |(_, unit), results| {
+            for (&i, (computed_checksum, size)) in unit.iter().zip(results) {
+                let (index, path, file, checksum, hash_options) = &entries[i];
+                print_banners(*index);
+                let error = computed_checksum.as_ref().err().map(error_message);
+                let mut record = Record::verified(file, checksum, computed_checksum);
+                record.path = path.display().to_string();
+                record.size = record.size.or(size);
+                report(record, hash_options.algorithm, error)?;
+            }
+
+            Ok(())
+        },
+    )?;
+    let cancelled = is_cancelled(progress);
+    print_banners(sfv_files.len());
+    observe(progress, progress::Event::Finished);
+
+    if verify_options.check_extra && !cancelled {
+        let listed: HashSet<_> = entries
+            .iter()
+            .map(|(_, _, file, _, _)| file.as_path())
+            .chain(units.iter().filter_map(|(archive, _)| *archive))
+            .chain(skipped.iter().map(PathBuf::as_path))
+            .chain(sfv_files.iter().map(PathBuf::as_path))
+            .map(absolute_path)
+            .collect::<Result<_>>()?;
+        let walk_options = WalkOptions {
+            recursive: true,
+            ..walk_options.clone()
+        };
+        for (base_dir, prefix) in &base_dirs {
+            for file in get_files(base_dir, &walk_options)? {
+                if listed.contains(&absolute_path(&file)?) {
+                    continue;
+                }
+
+                let metadata = fs::metadata(&file).ok();
+                let record = Record {
+                    path: prefix
+                        .join(file.strip_prefix(base_dir).unwrap_or(&file))
+                        .display()
+                        .to_string(),
+                    checksum: None,
+                    expected: None,
+                    size: metadata.as_ref().map(fs::Metadata::len),
+                    modified: metadata.as_ref().and_then(modified_time),
+                    status: Status::Extra,
+                    error: None,
+                };
+                report(record, hash_options.algorithm, None)?;
+            }
+        }
+    }
+    summary.cancelled = cancelled;
+
+    if cksfv {
+        for line in cksfv_summary(summary.is_ok()) {
+            eprintln!("{line}");
+        }
+    }
+
+    if !summary.is_ok() && !silent {
+        for sfv_file in sfv_files {
+            report_repair_status(sfv_file);
+        }
+    }
+
+    if silent {
+        return Ok(summary);
+    }
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Verifies the files listed in the SQLite database at `db` and records the results in it.
+///
+/// Relative paths are resolved against `scan_options.path_options.base_dir`, which defaults to the directory containing
+/// the database. Paths are rewritten and files are looked up according to `scan_options.path_options`. If `paths` are
+/// given, only files below them are verified. Each file is hashed with the algorithm it was stored with. If `progress`
+/// is given, the progress of hashing is reported to it. Results are printed according to `output_options`. Returns the
+/// counts of verified files by status.
+pub fn verify_db(
+    db: &Path,
+    paths: &[PathBuf],
+    scan_options: &ScanOptions,
+    verify_options: &VerifyOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let path_options = &scan_options.path_options;
+    let silent = output_options.status;
+    let mut database = db::Database::open(db)?;
+    let base_dir = match &path_options.base_dir {
+        Some(dir) => dir.clone(),
+        None => fs::canonicalize(db)
+            .with_context(|| format!("Failed to get canonical path for {}", db.display()))?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+    let below = paths
+        .iter()
+        .map(|path| absolute_path(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut entries = Vec::new();
+    for row in database.rows()? {
+        if !verify_options.selects(&row.path) {
+            continue;
+        }
+
+        let file = resolve(
+            &base_dir.join(path_options.rewrite(&row.path)),
+            path_options,
+        );
+        if !below.is_empty()
+            && !below
+                .iter()
+                .any(|dir| absolute_path(&file).is_ok_and(|file| file.starts_with(dir)))
+        {
+            continue;
+        }
+        entries.push((row, file));
+    }
+
+    start_progress(progress, entries.iter().map(|(_, file)| file));
+    if !silent {
+        print_start(progress, output_options, entries.len())?;
+    }
+
+    let mut records = Vec::new();
+    let mut results = Vec::new();
+    let mut summary = Summary {
+        ignore_missing: verify_options.ignore_missing,
+        ..Summary::default()
+    };
+    let verified = now();
+    par_map(
+        &entries,
+        !verify_options.unordered,
+        scan_options.jobs,
+        progress,
+        |(row, file)| {
+            let hash_options = HashOptions {
+                algorithm: row.algorithm,
+                link_target: false,
+                archives: false,
+                archive_depth: 0,
+                decompress: false,
+                io: scan_options.hash_options.io,
+                direct: scan_options.hash_options.direct,
+            };
+            checksum(file, &hash_options, progress)
+        },
+        |(row, file), computed| {
+            let mut record = Record::verified(file, &row.checksum, computed);
+            record.path = row.path.display().to_string();
+            summary.add_record(&record);
+            observe(progress, progress::Event::FileResult(&record));
+            results.push((row.path.clone(), record.status.as_str()));
+            if !silent {
+                print_result(
+                    progress,
+                    output_options,
+                    record,
+                    row.algorithm,
+                    &mut records,
+                )?;
+            }
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+    database.record_verification(&results, &verified)?;
+
+    if silent {
+        return Ok(summary);
+    }
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Verifies the checksum files in `paths`, or the database of `manifest_options` if given, periodically until
+/// interrupted.
+///
+/// A round starts every `scrub_options.interval`, aligned to multiples of the interval since the Unix epoch, except for
+/// the first round which starts immediately. If `scrub_options.parts` is greater than one, the listed files are split
+/// into that many parts by the CRC-32 of their path and each round only verifies the part given by the number of the
+/// round, so that all files are verified once in `parts` rounds even if the program is restarted in between. Each round
+/// verifies each of `paths` like `--verify` according to the other options, after which its summary is printed on
+/// stderr and appended to `scrub_options.history` if given. If `scrub_options.metrics` is given, the results of each
+/// path are served as metrics for Prometheus, see the `metrics` module. Errors which end verifying a path early are
+/// reported without ending scrubbing.
+///
+/// Only returns if metrics cannot be served or the history cannot be written.
+pub fn scrub(
+    paths: Vec<PathBuf>,
+    scrub_options: &ScrubOptions,
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    verify_options: &VerifyOptions,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let hash_options = &scan_options.hash_options;
+    let interval = scrub_options.interval.as_secs().max(1);
+    let parts = scrub_options.parts;
+    let algorithms = if verify_options.detect_algorithm {
+        Algorithm::value_variants()
+    } else {
+        &[hash_options.algorithm]
+    };
+    let metrics = scrub_options
+        .metrics
+        .as_deref()
+        .map(metrics::Metrics::serve)
+        .transpose()?;
+    // A database is verified as a whole, otherwise each path given is verified separately so that metrics are
+    // available for each of them.
+    let targets: Vec<_> = match &manifest_options.db {
+        Some(db) => vec![db.clone()],
+        None => paths.clone(),
+    };
+    loop {
+        let start = Instant::now();
+        let round = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            / interval;
+        let part = u32::try_from(round % u64::from(parts)).unwrap_or_default();
+        let verify_options = VerifyOptions {
+            part: (parts > 1).then_some((part, parts)),
+            ..verify_options.clone()
+        };
+        let time = now();
+        if !output_options.status {
+            eprintln!("Scrubbing part {} of {parts} at {time}", part + 1);
+        }
+        log::info!(part = part + 1, parts = parts; "Scrubbing part {} of {parts}", part + 1);
+
+        let mut summary = Summary::default();
+        let mut errors = Vec::new();
+        for target in &targets {
+            let result = match &manifest_options.db {
+                Some(db) => verify_db(
+                    db,
+                    &paths,
+                    scan_options,
+                    &verify_options,
+                    None,
+                    output_options,
+                ),
+                None => {
+                    get_checksum_files(vec![target.clone()], algorithms, scrub_options.recursive)
+                        .and_then(|sfv_files| {
+                            verify_sfv(
+                                &sfv_files,
+                                scan_options,
+                                manifest_options,
+                                &verify_options,
+                                None,
+                                output_options,
+                            )
+                        })
+                }
+            };
+            if let Some(metrics) = &metrics {
+                metrics.record(&target.display().to_string(), result.as_ref().ok());
+            }
+            match result {
+                Ok(result) => summary.merge(&result),
+                Err(e) => {
+                    if !output_options.status {
+                        println!("{} {e:#}", "[ERROR]".red().bold());
+                    }
+                    errors.push(format!("{e:#}"));
+                }
+            }
+        }
+        if !output_options.status {
+            eprintln!("{}", summary.report(start.elapsed()));
+        }
+        verify_options.hooks.notify(&summary);
+        if let Some(history) = &scrub_options.history {
+            let round = ScrubRound {
+                time,
+                part: part + 1,
+                parts,
+                summary: &summary,
+                error: (!errors.is_empty()).then(|| errors.join("; ")),
+            };
+            let mut line = serde_json::to_vec(&round).context("Failed to serialize results")?;
+            line.push(b'\n');
+            File::options()
+                .create(true)
+                .append(true)
+                .open(history)
+                .and_then(|mut file| file.write_all(&line))
+                .with_context(|| format!("Failed to write to {}", history.display()))?;
+        }
+
+        let next = Duration::from_secs((round + 1) * interval);
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        thread::sleep(next.saturating_sub(elapsed));
+    }
+}
+
+/// Prints on stderr whether files damaged since the checksum file at `path` was written can be repaired with the PAR2
+/// recovery files next to it. Nothing is printed if there are no recovery files.
+fn report_repair_status(path: &Path) {
+    let par2_files = par2::find_recovery_files(path);
+    if par2_files.is_empty() {
+        return;
+    }
+
+    let status = match par2::check(&par2_files) {
+        Ok(status) => status,
+        Err(e) => {
+            warn(&format!(
+                "Failed to read recovery data for {}: {e}",
+                path.display()
+            ));
+            return;
+        }
+    };
+    for damage in &status.damaged {
+        eprintln!(
+            "{}: {} of {} blocks damaged",
+            damage.name, damage.damaged, damage.slices
+        );
+    }
+    let damaged: u64 = status.damaged.iter().map(|damage| damage.damaged).sum();
+    if status.repairable() {
+        eprintln!(
+            "{}",
+            format!(
+                "Repair is possible using {} of {} recovery blocks from {}",
+                damaged,
+                status.recovery,
+                par2_files[0].display()
+            )
+            .green()
+        );
+    } else {
+        eprintln!(
+            "{}",
+            format!(
+                "Repair is not possible, {} recovery blocks are needed but only {} are available",
+                damaged, status.recovery
+            )
+            .red()
+        );
+    }
+}
+
+/// Computes the checksums of the given entries of `verify_sfv`, which are all members of `archive`.
+///
+/// Returns the computed checksum and the size of each entry in the same order. Members which are not found in the
+/// archive are reported as missing. If the archive cannot be read, all entries are reported with the error. If
+/// `progress` is provided it is notified after every member.
+fn verify_members<'a, I>(
+    archive: &Path,
+    entries: I,
+    progress: Option<&dyn Observer>,
+) -> Vec<(Result<String>, Option<u64>)>
+where
+    I: IntoIterator<Item = &'a (usize, PathBuf, PathBuf, String, HashOptions)>,
+{
+    let wanted: Vec<_> = entries
+        .into_iter()
+        .map(|(_, _, file, _, hash_options)| {
+            let member = file.strip_prefix(archive).unwrap_or(file);
+            (member, hash_options.algorithm)
+        })
+        .collect();
+    // Entries are indexed by member, so that archives with many members are verified in linear time.
+    let mut algorithms: HashMap<&Path, Vec<Algorithm>> = HashMap::new();
+    let mut ancestors = HashSet::new();
+    for &(member, algorithm) in &wanted {
+        let member_algorithms = algorithms.entry(member).or_default();
+        if !member_algorithms.contains(&algorithm) {
+            member_algorithms.push(algorithm);
+        }
+        ancestors.extend(member.ancestors().skip(1));
+    }
+    let select = |member: &Path| algorithms.get(member).cloned().unwrap_or_default();
+    // Nested archives are read if any of the entries lies inside them.
+    let descend = |path: &Path| ancestors.contains(path);
+    let members = archive_checksums(archive, select, descend, progress);
+    let members = members.map(|members| {
+        let mut by_path = HashMap::new();
+        for (path, size, checksums) in members {
+            by_path.entry(path).or_insert((size, checksums));
+        }
+        by_path
+    });
+
+    wanted
+        .iter()
+        .map(|&(member, algorithm)| {
+            let members = match &members {
+                Ok(members) => members,
+                Err(e) => return (Err(Error::msg(format!("{e:#}"))), None),
+            };
+            let Some((size, checksums)) = members.get(member) else {
+                let error = io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} not found in {}", member.display(), archive.display()),
+                );
+                return (Err(error.into()), None);
+            };
+            let checksum = match checksums {
+                Ok(checksums) => {
+                    let position = algorithms[member]
+                        .iter()
+                        .position(|&a| a == algorithm)
+                        .unwrap_or_default();
+                    Ok(checksums[position].clone())
+                }
+                Err(e) => Err(Error::msg(format!("{e:#}"))),
+            };
+            (checksum, Some(*size))
+        })
+        .collect()
+}
+
+/// Verifies downloaded files against the piece hashes of `torrent_file`.
+///
+/// Files are looked up below `scan_options.path_options.base_dir`, which defaults to the directory containing the
+/// torrent file. Files whose pieces do not match or whose size differs are reported as failed, along with the byte
+/// ranges of the pieces which do not match. If `progress` is given, the progress of hashing is reported to it and
+/// checking stops once it cancels, without reporting any files. Results are printed according to `output_options`.
+/// Missing files do not fail verification if `verify_options.ignore_missing` is specified. Returns the counts of
+/// verified files by status.
+pub fn verify_torrent(
+    torrent_file: &Path,
+    scan_options: &ScanOptions,
+    verify_options: &VerifyOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let path_options = &scan_options.path_options;
+    let silent = output_options.status;
+    let data = fs::read(torrent_file)
+        .with_context(|| format!("Failed to read file {}", torrent_file.display()))?;
+    let torrent = Torrent::parse(&data)
+        .with_context(|| format!("Failed to read torrent {}", torrent_file.display()))?;
+    let base_dir = match &path_options.base_dir {
+        Some(dir) => dir.clone(),
+        None => torrent_file
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+    let files: Vec<_> = torrent.files.iter().filter(|file| !file.padding).collect();
+
+    start_progress(progress, files.iter().map(|file| base_dir.join(&file.path)));
+    if !silent {
+        print_start(progress, output_options, files.len())?;
+    }
+    let results = torrent.check(
+        &base_dir,
+        |bytes| {
+            observe(
+                progress,
+                progress::Event::BytesHashed {
+                    path: torrent_file,
+                    bytes,
+                },
+            );
+        },
+        || is_cancelled(progress),
+    );
+    observe(progress, progress::Event::Finished);
+
+    let mut summary = Summary {
+        ignore_missing: verify_options.ignore_missing,
+        ..Summary::default()
+    };
+    // Pieces left out when cancelling are reported as not matching, so the results of no file can be trusted.
+    if is_cancelled(progress) {
+        summary.cancelled = true;
+        return Ok(summary);
+    }
+
+    let mut records = Vec::new();
+    for (file, result) in torrent.files.iter().zip(results) {
+        if file.padding {
+            continue;
+        }
+
+        let metadata = fs::metadata(base_dir.join(&file.path)).ok();
+        let size = metadata.as_ref().map(fs::Metadata::len);
+        let (status, error) = match result {
+            Err(e) if is_not_found(&e) => (Status::Missing, Some(format!("{e:#}"))),
+            Err(e) => (Status::Error, Some(format!("{e:#}"))),
+            Ok(bad) if bad.is_empty() && size == Some(file.length) => (Status::Ok, None),
+            Ok(bad) => {
+                let mut problems = Vec::new();
+                if let Some(size) = size.filter(|&size| size != file.length) {
+                    problems.push(format!("{size} bytes instead of {}", file.length));
+                }
+                if !bad.is_empty() {
+                    let ranges: Vec<_> = bad
+                        .iter()
+                        .map(|range| format!("{}-{}", range.start, range.end - 1))
+                        .collect();
+                    problems.push(format!("bad bytes {}", ranges.join(", ")));
+                }
+                (Status::Fail, Some(problems.join(", ")))
+            }
+        };
+        let record = Record {
+            path: file.path.display().to_string(),
+            checksum: None,
+            expected: None,
+            size,
+            modified: metadata.as_ref().and_then(modified_time),
+            status,
+            error,
+        };
+        summary.add_record(&record);
+        observe(progress, progress::Event::FileResult(&record));
+        if !silent {
+            print_result(
+                progress,
+                output_options,
+                record,
+                Algorithm::Sha1,
+                &mut records,
+            )?;
+        }
+    }
+
+    if silent {
+        return Ok(summary);
+    }
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Checks a single file against an expected checksum.
+///
+/// `paths` must resolve to exactly one file, which is hashed according to `scan_options.hash_options` and compared with
+/// `expected`. The result is printed according to `output_options`. Returns the counts of checked files by status, or
+/// an error if `expected` is not a checksum of the algorithm.
+pub fn expect_checksum<A>(
+    paths: A,
+    expected: &str,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+    scan_options: &ScanOptions,
+) -> Result<Summary>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let hash_options = &scan_options.hash_options;
+    if !hash_options.algorithm.is_valid_hex(expected) {
+        return Err(Error::msg(format!(
+            "Expected checksum {expected} is not a valid {} checksum",
+            hash_options.algorithm.tag()
+        )));
+    }
+    let files = get_all_files(paths, &WalkOptions::default())?;
+    let [file] = files.as_slice() else {
+        return Err(Error::msg(format!(
+            "Expected exactly one file to check, found {}",
+            files.len()
+        )));
+    };
+
+    start_progress(progress, &files);
+    let computed = checksum(file, hash_options, progress);
+    observe(progress, progress::Event::Finished);
+    if is_cancelled(progress) {
+        return Ok(Summary {
+            cancelled: true,
+            ..Summary::default()
+        });
+    }
+
+    let record = Record::verified(file, expected, computed);
+    let mut summary = Summary::default();
+    summary.add_record(&record);
+    observe(progress, progress::Event::FileResult(&record));
+    match output_options.format {
+        Format::Text | Format::Bsd => {
+            print_text(progress, output_options, &record, hash_options.algorithm)
+        }
+        Format::Json | Format::Junit | Format::Tap => {
+            print_collected(output_options, &[record], &summary)?
+        }
+        Format::Csv | Format::Tsv | Format::Jsonl => {
+            print_start(progress, output_options, 1)?;
+            print_record(progress, output_options, record, &mut Vec::new())?;
+            print_summary(progress, output_options, &summary)?;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Checks files against the CRCs embedded in their names, see `embedded_crc`.
+///
+/// Directories in `paths` are searched for files according to `scan_options.walk_options`. Files whose names contain a
+/// CRC are hashed according to `scan_options.hash_options` and compared with it, other files are skipped. If `progress`
+/// is given, the progress of hashing is reported to it. Results are printed according to `output_options`. Returns the
+/// counts of checked files by status.
+pub fn check_names<A>(
+    paths: A,
+    scan_options: &ScanOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let ScanOptions {
+        walk_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let entries: Vec<_> = get_all_files(paths, walk_options)?
+        .into_iter()
+        .filter_map(|file| embedded_crc(&file).map(|crc| (file, crc)))
+        .collect();
+
+    start_progress(progress, entries.iter().map(|(file, _)| file));
+    print_start(progress, output_options, entries.len())?;
+
+    let mut records = Vec::new();
+    let mut summary = Summary::default();
+    par_map(
+        &entries,
+        true,
+        scan_options.jobs,
+        progress,
+        |(file, _)| checksum(file, hash_options, progress),
+        |(file, crc), computed| {
+            let record = Record::verified(file, crc, computed);
+            summary.add_record(&record);
+            observe(progress, progress::Event::FileResult(&record));
+            print_result(
+                progress,
+                output_options,
+                record,
+                hash_options.algorithm,
+                &mut records,
+            )?;
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Checks files against the checksums stored in their extended attributes, like cshatag.
+///
+/// Directories in `paths` are searched for files according to `scan_options.walk_options`. Files are hashed using the
+/// stored algorithm and reported as `FAIL` if the checksum does not match although the file was not modified since,
+/// which indicates bit rot. Files modified since their checksum was stored are reported with a warning. If `store` is
+/// specified, checksums of such files are updated and checksums of files without one are stored using the algorithm of
+/// `scan_options.hash_options`, otherwise files without a stored checksum are skipped. If `progress` is given, the
+/// progress of hashing is reported to it. Results are printed according to `output_options`. Returns the counts of
+/// checked files by status.
+pub fn check_xattrs<A>(
+    paths: A,
+    scan_options: &ScanOptions,
+    store: bool,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let ScanOptions {
+        walk_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let entries: Vec<_> = get_all_files(paths, walk_options)?
+        .into_iter()
+        .filter_map(|file| match xattrs::read(&file) {
+            Ok(None) if !store => None,
+            stored => Some((file, stored)),
+        })
+        .collect();
+
+    start_progress(progress, entries.iter().map(|(file, _)| file));
+    print_start(progress, output_options, entries.len())?;
+
+    let mut records = Vec::new();
+    let mut summary = Summary::default();
+    par_map(
+        &entries,
+        true,
+        scan_options.jobs,
+        progress,
+        |(file, stored)| {
+            let failed = |e| Record {
+                expected: None,
+                ..Record::verified(file, "", Err(e))
+            };
+            let stored = match stored {
+                Ok(stored) => stored.as_ref(),
+                Err(e) => return (Some(failed(Error::msg(format!("{e:#}")))), None),
+            };
+            // The modification time is read before hashing, so that changes while hashing are detected later.
+            let mtime = fs::metadata(file)
+                .ok()
+                .as_ref()
+                .and_then(xattrs::timestamp)
+                .unwrap_or_default();
+            let modified = stored.is_some_and(|stored| stored.mtime != mtime);
+            let warning = modified.then(|| {
+                let action = if store { "updating" } else { "not checking" };
+                format!(
+                    "{} was modified after its checksum was stored, {action} it",
+                    file.display()
+                )
+            });
+            if modified && !store {
+                return (None, warning);
+            }
+
+            let algorithm = stored.map_or(hash_options.algorithm, |stored| stored.algorithm);
+            let options = HashOptions {
+                algorithm,
+                ..*hash_options
+            };
+            let computed = match checksum(file, &options, progress) {
+                Ok(computed) => computed,
+                Err(e) => return (Some(failed(e)), None),
+            };
+            let record = match stored {
+                Some(stored) if !modified => {
+                    return (
+                        Some(Record::verified(file, &stored.checksum, Ok(computed))),
+                        None,
+                    );
+                }
+                _ => Record {
+                    expected: None,
+                    ..Record::verified(file, &computed, Ok(computed.clone()))
+                },
+            };
+
+            let stored = xattrs::Stored {
+                checksum: computed,
+                algorithm,
+                mtime,
+            };
+            match xattrs::write(file, &stored) {
+                Ok(()) => (Some(record), warning),
+                Err(e) => (Some(failed(e)), None),
+            }
+        },
+        |_, (record, warning)| {
+            if let Some(warning) = warning {
+                warn(&warning);
+            }
+            let Some(record) = record else {
+                return Ok(());
+            };
+            summary.add_record(&record);
+            observe(progress, progress::Event::FileResult(&record));
+            print_result(
+                progress,
+                output_options,
+                record,
+                hash_options.algorithm,
+                &mut records,
+            )?;
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Checks the members of ZIP archives against the CRCs stored in them.
+///
+/// Directories in `paths` are searched for files ending in `.zip` according to `scan_options.walk_options`. Every
+/// member of each archive is decompressed in memory, without extracting it to disk, and reported as
+/// `archive.zip/path/of/member`. If `progress` is given, the progress of reading archives is reported to it. Results
+/// are printed according to `output_options`. Returns the counts of checked members by status.
+pub fn check_zips<A>(
+    paths: A,
+    scan_options: &ScanOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let walk_options = &scan_options.walk_options;
+    let algorithm = Algorithm::Crc32;
+    let archives: Vec<_> = get_all_files(paths, walk_options)?
+        .into_iter()
+        .filter(|file| {
+            file.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+        })
+        .collect();
+
+    start_progress(progress, &archives);
+    print_start(progress, output_options, archives.len())?;
+
+    let mut records = Vec::new();
+    let mut summary = Summary::default();
+    par_map(
+        &archives,
+        true,
+        scan_options.jobs,
+        progress,
+        |archive| {
+            let size = fs::metadata(archive).map(|m| m.len()).unwrap_or(0);
+            observe(
+                progress,
+                progress::Event::FileStarted {
+                    path: archive,
+                    size,
+                },
+            );
+            let mut members = Vec::new();
+            let result = zip_members(archive, |member, reader| {
+                let computed = reader.and_then(|reader| {
+                    hash_reader(reader, algorithm, |_| check_cancelled(progress))
+                        .with_context(|| format!("Error while reading {}", member.path.display()))
+                });
+                let path = archive.join(&member.path).display().to_string();
+                let expected = member
+                    .crc
+                    .map(|crc| algorithm.to_hex(&crc.to_be_bytes()))
+                    .unwrap_or_default();
+                members.push(Record {
+                    size: Some(member.size),
+                    ..Record::checked(path, &expected, computed)
+                });
+                observe(
+                    progress,
+                    progress::Event::BytesHashed {
+                        path: archive,
+                        bytes: member.compressed_size,
+                    },
+                );
+                check_cancelled(progress)?;
+                Ok(())
+            });
+            observe(progress, progress::Event::FileDone { path: archive });
+            result.map(|()| members)
+        },
+        |archive, members| {
+            // Archives which cannot be read at all are reported like a single member.
+            let members = members.unwrap_or_else(|e| {
+                let mut record = Record::checked(archive.display().to_string(), "", Err(e));
+                record.expected = None;
+                vec![record]
+            });
+            for record in members {
+                summary.add_record(&record);
+                observe(progress, progress::Event::FileResult(&record));
+                print_result(progress, output_options, record, algorithm, &mut records)?;
+            }
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+
+    print_collected(output_options, &records, &summary)?;
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Renames files to include, update or remove their checksum according to `rename_options.mode`.
+///
+/// Directories in `paths` are searched for files according to `scan_options.walk_options`. When tagging, files whose
+/// names already contain a CRC are skipped, see `embedded_crc`. When untagging or retagging, only such files are
+/// renamed after checking them against their CRC. Files are hashed according to `scan_options.hash_options` and renamed
+/// according to `rename_options`, printing the old and new path of each file and the result of checking it. If
+/// `progress` is given, the progress of hashing is reported to it. Returns the counts of renamed files by status.
+pub fn rename_files<A>(
+    paths: A,
+    scan_options: &ScanOptions,
+    rename_options: &RenameOptions,
+    progress: Option<&dyn Observer>,
+) -> Result<Summary>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let ScanOptions {
+        walk_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let mode = rename_options.mode;
+    let files: Vec<_> = get_all_files(paths, walk_options)?
+        .into_iter()
+        .filter(|file| file.as_os_str() != STDIN)
+        .filter_map(|file| {
+            let crc = embedded_crc(&file);
+            match mode {
+                RenameMode::Tag => crc.is_none().then_some((file, None)),
+                RenameMode::Untag | RenameMode::Retag => crc.map(|crc| (file, Some(crc))),
+            }
+        })
+        .collect();
+
+    start_progress(progress, files.iter().map(|(file, _)| file));
+    let mut renamed = HashSet::new();
+    let mut summary = Summary::default();
+    par_map(
+        &files,
+        true,
+        scan_options.jobs,
+        progress,
+        |(file, _)| checksum(file, hash_options, progress),
+        |(file, crc), computed| {
+            // Files without CRC are not checked and only reported as errors if they cannot be read.
+            let expected = crc
+                .clone()
+                .or_else(|| computed.as_ref().ok().cloned())
+                .unwrap_or_default();
+            let mut record = Record::verified(file, &expected, computed);
+            if crc.is_none() {
+                record.expected = None;
+            }
+
+            if let Some(checksum) = &record.checksum {
+                let matches = record.status == Status::Ok;
+                let target = new_file_name(file, checksum, matches, rename_options, hash_options)
+                    .and_then(|name| {
+                        let Some(name) = name else {
+                            return Ok(None);
+                        };
+                        let target =
+                            rename_target(file, &name, rename_options.on_conflict, |path| {
+                                path.exists() || renamed.contains(path)
+                            })?;
+                        if !rename_options.dry_run {
+                            fs::rename(file, &target).with_context(|| {
+                                format!(
+                                    "Failed to rename {} to {}",
+                                    file.display(),
+                                    target.display()
+                                )
+                            })?;
+                        }
+                        Ok(Some(target))
+                    });
+                match target {
+                    Ok(Some(target)) => {
+                        record.path = format!("{} -> {}", file.display(), target.display());
+                        renamed.insert(target);
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        record.status = Status::Error;
+                        record.error = Some(format!("{e:#}"));
+                    }
+                }
+            }
+
+            summary.add_record(&record);
+
+            observe(progress, progress::Event::FileResult(&record));
+            let line = match mode {
+                RenameMode::Tag if record.status == Status::Ok => record.path,
+                _ => verified_line(&record),
+            };
+            print_line(progress, line.as_bytes());
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+
+    Ok(summary)
+}
+
+/// Returns the new file name of `file` with the given `checksum` according to `rename_options.mode`, or `None` if the
+/// name is kept.
+///
+/// `matches` tells whether the checksum matches the CRC embedded in the name. Names are rendered from
+/// `rename_options.pattern` with the algorithm of `hash_options` when tagging.
+fn new_file_name(
+    file: &Path,
+    checksum: &str,
+    matches: bool,
+    rename_options: &RenameOptions,
+    hash_options: &HashOptions,
+) -> Result<Option<OsString>> {
+    let name = match rename_options.mode {
+        RenameMode::Tag => {
+            let name = file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| {
+                    Error::msg(format!("File name {} is not valid UTF-8", file.display()))
+                })?;
+            let record = Record {
+                path: name.to_string(),
+                checksum: Some(checksum.to_string()),
+                expected: None,
+                size: None,
+                modified: None,
+                status: Status::Ok,
+                error: None,
+            };
+            let name = rename_options
+                .pattern
+                .render(&record, hash_options.algorithm);
+            OsString::from(String::from_utf8_lossy(&name).into_owned())
+        }
+        RenameMode::Untag => strip_crc(file).ok_or_else(|| {
+            Error::msg(format!("Nothing is left of {} without CRC", file.display()))
+        })?,
+        RenameMode::Retag if matches => return Ok(None),
+        RenameMode::Retag => replace_crc(file, checksum).unwrap_or_default(),
+    };
+
+    Ok(Some(name))
+}
+
+/// Compares two files or directory trees by checksum.
+///
+/// If both `left` and `right` are files, their checksums are compared directly. If both are directories, they are
+/// searched recursively according to `scan_options.walk_options` and files are matched by their path relative to `left`
+/// and `right`. Each path is reported as identical, different or missing on one side, according to `output_options`.
+/// Returns the counts of compared paths by status.
+pub fn compare(
+    left: &Path,
+    right: &Path,
+    scan_options: &ScanOptions,
+    progress: Option<&dyn Observer>,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let ScanOptions {
+        walk_options,
+        hash_options,
+        ..
+    } = scan_options;
+    let format = output_options.format;
+    if let Some(name) = format.report_name() {
+        return Err(Error::msg(format!(
+            "{name} output is not supported when comparing"
+        )));
+    }
+    let mut entries: Vec<(PathBuf, Option<PathBuf>, Option<PathBuf>)> = Vec::new();
+    if left.is_file() && right.is_file() {
+        entries.push((
+            PathBuf::new(),
+            Some(left.to_path_buf()),
+            Some(right.to_path_buf()),
+        ));
+    } else if left.is_dir() && right.is_dir() {
+        let walk_options = WalkOptions {
+            recursive: true,
+            ..walk_options.clone()
+        };
+        let mut files = BTreeMap::new();
+        for file in get_files(left, &walk_options)? {
+            let relative = file.strip_prefix(left).unwrap_or(&file).to_path_buf();
+            files.entry(relative).or_insert((None, None)).0 = Some(file);
+        }
+        for file in get_files(right, &walk_options)? {
+            let relative = file.strip_prefix(right).unwrap_or(&file).to_path_buf();
+            files.entry(relative).or_insert((None, None)).1 = Some(file);
+        }
+        entries.extend(files.into_iter().map(|(path, (l, r))| (path, l, r)));
+    } else {
+        return Err(Error::msg(format!(
+            "{} and {} must both be files or both be directories",
+            left.display(),
+            right.display()
+        )));
+    }
+
+    start_progress(
+        progress,
+        entries
+            .iter()
+            .flat_map(|(_, l, r)| l.iter().chain(r.iter())),
+    );
+    match format.delimiter() {
+        Some(delimiter) => print_line(progress, &table::comparison_header(delimiter)),
+        None if format == Format::Jsonl => print_event(
+            progress,
+            &Event::Start {
+                files: entries.len(),
+            },
+        )?,
+        None => {}
+    }
+
+    let mut comparisons = Vec::new();
+    let mut summary = Summary::default();
+    par_map(
+        &entries,
+        true,
+        scan_options.jobs,
+        progress,
+        |(_, l, r)| {
+            let hash = |file: &Option<PathBuf>| {
+                file.as_ref()
+                    .map(|file| checksum(file, hash_options, progress))
+                    .transpose()
+            };
+            hash(l).and_then(|l| Ok((l, hash(r)?)))
+        },
+        |(path, l, r), checksums| {
+            let path = if path.as_os_str().is_empty() {
+                format!("{} {}", left.display(), right.display())
+            } else {
+                path.display().to_string()
+            };
+            let (status, left, right, error) = match checksums {
+                Ok((Some(left), Some(right))) if left.eq_ignore_ascii_case(&right) => {
+                    (CompareStatus::Identical, Some(left), Some(right), None)
+                }
+                Ok((left, right)) if l.is_some() && r.is_some() => {
+                    (CompareStatus::Different, left, right, None)
+                }
+                Ok((left, right)) if l.is_none() => (CompareStatus::MissingLeft, left, right, None),
+                Ok((left, right)) => (CompareStatus::MissingRight, left, right, None),
+                Err(e) => (CompareStatus::Error, None, None, Some(format!("{e:#}"))),
+            };
+            match status {
+                CompareStatus::Identical => summary.add(Status::Ok),
+                CompareStatus::Different => summary.add(Status::Fail),
+                CompareStatus::MissingLeft | CompareStatus::MissingRight => {
+                    summary.add(Status::Missing)
+                }
+                CompareStatus::Error => summary.add(Status::Error),
+            }
+
+            match format {
+                Format::Text | Format::Bsd => {
+                    let line = match status {
+                        CompareStatus::Identical => {
+                            format!("{path} {}", "IDENTICAL".green().bold())
+                        }
+                        CompareStatus::Different => format!(
+                            "{path} {} {} ≠ {}",
+                            "DIFFERENT".yellow().bold(),
+                            left.as_deref().unwrap_or_default(),
+                            right.as_deref().unwrap_or_default()
+                        ),
+                        CompareStatus::MissingLeft => {
+                            format!("{path} {}", "MISSING LEFT".yellow().bold())
+                        }
+                        CompareStatus::MissingRight => {
+                            format!("{path} {}", "MISSING RIGHT".yellow().bold())
+                        }
+                        CompareStatus::Error => format!(
+                            "{path} {} {}",
+                            "ERROR".red().bold(),
+                            error.as_deref().unwrap_or_default()
+                        ),
+                    };
+                    print_line(progress, line.as_bytes());
+                }
+                Format::Json
+                | Format::Csv
+                | Format::Tsv
+                | Format::Jsonl
+                | Format::Junit
+                | Format::Tap => {
+                    let comparison = Comparison {
+                        path,
+                        left,
+                        right,
+                        status,
+                        error,
+                    };
+                    match format.delimiter() {
+                        Some(delimiter) => {
+                            print_line(progress, &table::comparison_row(&comparison, delimiter))
+                        }
+                        None if format == Format::Jsonl => {
+                            let event = if status == CompareStatus::Error {
+                                Event::CompareError(&comparison)
+                            } else {
+                                Event::Compared(&comparison)
+                            };
+                            print_event(progress, &event)?;
+                        }
+                        None => comparisons.push(comparison),
+                    }
+                }
+            }
+
+            Ok(())
+        },
+    )?;
+    summary.cancelled = is_cancelled(progress);
+    observe(progress, progress::Event::Finished);
+
+    if format == Format::Json {
+        let json =
+            serde_json::to_string_pretty(&comparisons).context("Failed to serialize results")?;
+        println!("{json}");
+    }
+    print_summary(progress, output_options, &summary)?;
+
+    Ok(summary)
+}
+
+/// Reports the differences between the checksum files `old_file` and `new_file`.
+///
+/// Paths are matched as listed after applying `scan_options.path_options`. Paths only listed in `new_file` are reported
+/// as added and paths only listed in `old_file` as removed, unless a removed path has the same checksum as an added
+/// one, in which case the file is reported as renamed. Removed paths with the same file name are preferred when several
+/// match, and files without content are never matched since all of them have the same checksum. Paths listed in both
+/// files with different checksums are reported as changed. Unchanged paths are only counted. Changes are printed
+/// according to `output_options`. Returns the counts of paths by status, where changed and renamed paths count as
+/// failed, added paths as extra and removed paths as missing.
+pub fn diff_sfv(
+    old_file: &Path,
+    new_file: &Path,
+    scan_options: &ScanOptions,
+    manifest_options: &ManifestOptions,
+    verify_options: &VerifyOptions,
+    output_options: &OutputOptions,
+) -> Result<Summary> {
+    let ScanOptions {
+        path_options,
+        hash_options,
+        ..
+    } = scan_options;
+    if let Some(name) = output_options.format.report_name() {
+        return Err(Error::msg(format!(
+            "{name} output is not supported when comparing checksum files"
+        )));
+    }
+    // Checksum and algorithm of each listed path, keeping the first of duplicate paths.
+    let read = |sfv_file: &Path| -> Result<BTreeMap<PathBuf, (String, Algorithm)>> {
+        let data = fs::read(sfv_file)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+        let data = manifest_options.encoding.decode(&data);
+        let manifest_format = manifest_options.read_format(sfv_file, &data);
+        let file_algorithm = verify_options
+            .detect_algorithm
+            .then(|| manifest_format.detect_algorithm(sfv_file, &data))
+            .flatten()
+            .unwrap_or(hash_options.algorithm);
+        let listed = manifest_format
+            .read(&data, file_algorithm, manifest_options.strict)
+            .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
+
+        let mut entries = BTreeMap::new();
+        for entry in listed {
+            let algorithm = entry.algorithm.unwrap_or(file_algorithm);
+            entries
+                .entry(path_options.output(&entry.path))
+                .or_insert((algorithm.normalize_hex(&entry.checksum), algorithm));
+        }
+        Ok(entries)
+    };
+    let old = read(old_file)?;
+    let new = read(new_file)?;
+
+    // Removed paths by algorithm and checksum, to find renamed files.
+    let mut removed: HashMap<(String, &str), Vec<&PathBuf>> = HashMap::new();
+    for (path, (checksum, algorithm)) in &old {
+        let empty = algorithm.to_hex(&Hasher::new(*algorithm).finalize());
+        if !new.contains_key(path) && *checksum != empty {
+            removed
+                .entry((algorithm.tag(), checksum))
+                .or_default()
+                .push(path);
+        }
+    }
+
+    let mut changes = Vec::new();
+    let mut renamed = HashSet::new();
+    for (path, (checksum, algorithm)) in &new {
+        let (status, old_path, old_checksum) = match old.get(path) {
+            Some((old_checksum, old_algorithm))
+                if old_checksum == checksum && old_algorithm == algorithm =>
+            {
+                (ChangeKind::Unchanged, None, Some(old_checksum))
+            }
+            Some((old_checksum, _)) => (ChangeKind::Changed, None, Some(old_checksum)),
+            None => match removed.get_mut(&(algorithm.tag(), checksum.as_str())) {
+                Some(candidates) if !candidates.is_empty() => {
+                    let index = candidates
+                        .iter()
+                        .position(|candidate| candidate.file_name() == path.file_name())
+                        .unwrap_or(0);
+                    let old_path = candidates.remove(index);
+                    renamed.insert(old_path);
+                    (ChangeKind::Renamed, Some(old_path), Some(checksum))
+                }
+                _ => (ChangeKind::Added, None, None),
+            },
+        };
+        changes.push(Change {
+            path: path.display().to_string(),
+            old_path: old_path.map(|path| path.display().to_string()),
+            old: old_checksum.cloned(),
+            new: Some(checksum.clone()),
+            status,
+        });
+    }
+    for (path, (checksum, _)) in &old {
+        if !new.contains_key(path) && !renamed.contains(path) {
+            changes.push(Change {
+                path: path.display().to_string(),
+                old_path: None,
+                old: Some(checksum.clone()),
+                new: None,
+                status: ChangeKind::Removed,
+            });
+        }
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let format = output_options.format;
+    match format.delimiter() {
+        Some(delimiter) => print_line(None, &table::change_header(delimiter)),
+        None if format == Format::Jsonl => print_event(
+            None,
+            &Event::Start {
+                files: changes.len(),
+            },
+        )?,
+        None => {}
+    }
+
+    let mut summary = Summary::default();
+    let mut printed = Vec::new();
+    for change in changes {
+        summary.add(match change.status {
+            ChangeKind::Unchanged => Status::Ok,
+            ChangeKind::Added => Status::Extra,
+            ChangeKind::Removed => Status::Missing,
+            ChangeKind::Changed | ChangeKind::Renamed => Status::Fail,
+        });
+        if change.status == ChangeKind::Unchanged {
+            continue;
+        }
+
+        match format {
+            Format::Text | Format::Bsd => {
+                let path = &change.path;
+                let line = match change.status {
+                    ChangeKind::Unchanged => unreachable!("Unchanged paths should not be printed"),
+                    ChangeKind::Added => format!("{path} {}", "ADDED".green().bold()),
+                    ChangeKind::Removed => format!("{path} {}", "REMOVED".red().bold()),
+                    ChangeKind::Changed => format!(
+                        "{path} {} {} → {}",
+                        "CHANGED".yellow().bold(),
+                        change.old.as_deref().unwrap_or_default(),
+                        change.new.as_deref().unwrap_or_default()
+                    ),
+                    ChangeKind::Renamed => format!(
+                        "{path} {} from {}",
+                        "RENAMED".cyan().bold(),
+                        change.old_path.as_deref().unwrap_or_default()
+                    ),
+                };
+                print_line(None, line.as_bytes());
+            }
+            Format::Json
+            | Format::Csv
+            | Format::Tsv
+            | Format::Jsonl
+            | Format::Junit
+            | Format::Tap => match format.delimiter() {
+                Some(delimiter) => print_line(None, &table::change_row(&change, delimiter)),
+                None if format == Format::Jsonl => print_event(None, &Event::Changed(&change))?,
+                None => printed.push(change),
+            },
+        }
+    }
+
+    if format == Format::Json {
+        let json = serde_json::to_string_pretty(&printed).context("Failed to serialize results")?;
+        println!("{json}");
+    }
+    print_summary(None, output_options, &summary)?;
+
+    Ok(summary)
+}
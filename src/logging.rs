@@ -0,0 +1,269 @@
+//! Log of unattended runs, separate from the results printed on the console.
+//!
+//! Messages are written with their key-value pairs to a log file, to syslog or to the systemd journal. Log files get one
+//! line per message with a timestamp, e.g. `2024-01-01T12:00:00.000+01:00 WARN  photos/a.jpg FAIL path=photos/a.jpg
+//! status=fail`. The journal stores key-value pairs as fields prefixed with `CRC32_`, so that they can be queried
+//! with `journalctl CRC32_STATUS=fail`.
+use std::{
+    fmt::{self, Write as _},
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use chrono::{Local, SecondsFormat};
+use clap::ValueEnum;
+use log::{
+    Level, LevelFilter, Log, Metadata, Record,
+    kv::{self, Key, Value, VisitSource},
+};
+
+/// Name under which messages are logged to syslog and the journal.
+const IDENTIFIER: &str = "crc32";
+
+/// Least severe level of messages which are logged.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    /// Errors which ended a run
+    Error,
+    /// Files which do not match, are missing or cannot be read, and other warnings
+    Warn,
+    /// Start and summary of each run
+    Info,
+    /// Every file processed
+    Debug,
+}
+
+impl LogLevel {
+    /// Level filter of the `log` crate.
+    fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+        }
+    }
+}
+
+/// Where messages are logged.
+pub struct LogOptions<'a> {
+    pub level: LogLevel,
+    /// File to which messages are appended.
+    pub file: Option<&'a Path>,
+    pub syslog: bool,
+    pub journald: bool,
+}
+
+/// Logger writing to all selected targets.
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+    syslog: Option<platform::Socket>,
+    journald: Option<platform::Socket>,
+}
+
+/// Installs the logger for the targets in `options`. Nothing is logged if no target is selected. Any error opening a
+/// target is propagated with added context.
+pub fn init(options: &LogOptions) -> Result<()> {
+    if options.file.is_none() && !options.syslog && !options.journald {
+        return Ok(());
+    }
+
+    let file = options
+        .file
+        .map(|path| {
+            File::options()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(Mutex::new)
+                .with_context(|| format!("Failed to open log file {}", path.display()))
+        })
+        .transpose()?;
+    let syslog = options
+        .syslog
+        .then(|| platform::Socket::connect(platform::SYSLOG).context("Failed to connect to syslog"))
+        .transpose()?;
+    let journald = options
+        .journald
+        .then(|| {
+            platform::Socket::connect(platform::JOURNALD)
+                .context("Failed to connect to the journal")
+        })
+        .transpose()?;
+
+    let level = options.level.filter();
+    log::set_boxed_logger(Box::new(Logger {
+        level,
+        file,
+        syslog,
+        journald,
+    }))
+    .context("Failed to install logger")?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        // Logging must never end a run, so failures to write are ignored.
+        if let Some(file) = &self.file {
+            let mut line = format!(
+                "{} {:<5} {}",
+                Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+                record.level(),
+                record.args()
+            );
+            let _ = record.key_values().visit(&mut Pairs(&mut line));
+            line.push('\n');
+            if let Ok(mut file) = file.lock() {
+                let _ = file.write_all(line.as_bytes());
+            }
+        }
+        if let Some(syslog) = &self.syslog {
+            // Facility user, see RFC 3164.
+            let mut line = format!(
+                "<{}>{IDENTIFIER}[{}]: {}",
+                8 + severity(record.level()),
+                std::process::id(),
+                record.args()
+            );
+            let _ = record.key_values().visit(&mut Pairs(&mut line));
+            let _ = syslog.send(line.as_bytes());
+        }
+        if let Some(journald) = &self.journald {
+            let mut message = Vec::new();
+            field(
+                &mut message,
+                "PRIORITY",
+                &severity(record.level()).to_string(),
+            );
+            field(&mut message, "SYSLOG_IDENTIFIER", IDENTIFIER);
+            field(&mut message, "MESSAGE", &record.args().to_string());
+            let _ = record.key_values().visit(&mut Fields(&mut message));
+            let _ = journald.send(&message);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Returns the syslog severity of `level`.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Appends key-value pairs to a line as ` key=value`, quoting values containing spaces, quotes or `=`. Empty values
+/// are left out.
+struct Pairs<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for Pairs<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = value.to_string();
+        let result = if value.is_empty() {
+            Ok(())
+        } else if value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+            write!(self.0, " {key}={value:?}")
+        } else {
+            write!(self.0, " {key}={value}")
+        };
+        result.map_err(|fmt::Error| kv::Error::msg("Failed to format"))
+    }
+}
+
+/// Appends key-value pairs to a journal message as fields prefixed with `CRC32_`. Empty values are left out.
+struct Fields<'a>(&'a mut Vec<u8>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = value.to_string();
+        if !value.is_empty() {
+            let name = format!("CRC32_{}", key.as_str().to_uppercase());
+            field(self.0, &name, &value);
+        }
+        Ok(())
+    }
+}
+
+/// Appends a field to a message of the native journal protocol.
+///
+/// Values containing line breaks are written with their length in binary, other values as `NAME=value`.
+fn field(message: &mut Vec<u8>, name: &str, value: &str) {
+    message.extend(name.as_bytes());
+    if value.contains('\n') {
+        message.push(b'\n');
+        message.extend((value.len() as u64).to_le_bytes());
+    } else {
+        message.push(b'=');
+    }
+    message.extend(value.as_bytes());
+    message.push(b'\n');
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::{io, os::unix::net::UnixDatagram};
+
+    /// Socket of the local syslog daemon, which is also provided by journald.
+    pub const SYSLOG: &str = "/dev/log";
+    /// Socket of the native journal protocol.
+    pub const JOURNALD: &str = "/run/systemd/journal/socket";
+
+    /// Datagram socket connected to a local log daemon.
+    pub struct Socket(UnixDatagram);
+
+    impl Socket {
+        pub fn connect(path: &str) -> io::Result<Self> {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(path)?;
+            Ok(Socket(socket))
+        }
+
+        pub fn send(&self, message: &[u8]) -> io::Result<()> {
+            self.0.send(message).map(|_| ())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use std::io;
+
+    pub const SYSLOG: &str = "";
+    pub const JOURNALD: &str = "";
+
+    /// Placeholder, since syslog and the journal are only available on Unix.
+    pub struct Socket;
+
+    impl Socket {
+        pub fn connect(_path: &str) -> io::Result<Self> {
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        }
+
+        pub fn send(&self, _message: &[u8]) -> io::Result<()> {
+            Err(io::Error::from(io::ErrorKind::Unsupported))
+        }
+    }
+}
@@ -1,219 +1,1012 @@
-//! Computes the CRC32 checksum of files provided.
-//!
-//! Can also verify SFV and create SFV files.
+//! Command line interface of `crc32`, which parses the arguments and calls the library for the selected mode.
+mod commands;
+mod logging;
+
 use std::{
     env,
-    fmt::Write,
-    fs::{self, File},
-    io::Read,
-    path::{Path, PathBuf},
-    process::ExitCode,
+    ffi::OsString,
+    io,
+    path::PathBuf,
+    process::{self, ExitCode},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context, Error, Result};
-use clap::Parser;
+use clap::{ArgGroup, CommandFactory, Parser, ValueEnum};
 use colored::Colorize;
-use crc32fast::Hasher;
+use glob::Pattern;
+use rayon::ThreadPoolBuilder;
+
+use crc32::{
+    EXIT_CANCELLED, EXIT_ERROR, Format, HashOptions, IoBackend, ManifestOptions, OutputOptions,
+    RenameMode, RenameOptions, ScanOptions, ScrubOptions, VerifyOptions, cache, check_names,
+    check_xattrs, check_zips, compare,
+    compat::Compat,
+    convert_sfv,
+    crc::CrcParams,
+    create_sfv, diff_sfv,
+    encoding::Encoding,
+    expect_checksum, fix_sfv,
+    hash::Algorithm,
+    hooks::Hooks,
+    manifest::ManifestFormat,
+    merge_sfv,
+    names::OnConflict,
+    paths::{Normalization, PathOptions, Separator, parse_mapping},
+    progress::{Event, Observer, ProgressBars},
+    rename_files, report, scrub,
+    table::{Column, DEFAULT_COLUMNS},
+    template::Template,
+    verify_db, verify_sfv, verify_torrent, walk,
+    walk::{
+        SymlinkPolicy, WalkOptions, get_checksum_files, parse_duration, parse_size, parse_time,
+        read_file_list,
+    },
+    watch_sfv,
+};
 
-/// Number of bytes to read at once.
-const CHUNK_SIZE: usize = 1024 * 1024;
+use crate::logging::{LogLevel, LogOptions};
 
 /// Command line arguments.
 #[derive(Parser)]
-#[command(version, about = None, long_about = None)]
+#[command(
+    version,
+    about = "Compute and verify CRC32 and other checksums of files",
+    long_about = "Compute and verify CRC32 and other checksums of files.\n\n\
+        Checksums are printed or written to SFV files, md5sum and sha256sum style files, several other formats of \
+        checksum files or a SQLite database. Checksum files written by other tools can be verified, updated, merged, \
+        converted and compared. Files can also be checked against CRCs embedded in their names, the CRCs stored in ZIP \
+        archives or the piece hashes of torrents. Files are hashed in parallel.",
+    override_usage = "crc32 [COMMAND] [OPTIONS] [PATHS]...",
+    after_help = commands::help(),
+    after_long_help = commands::long_help(),
+    group(ArgGroup::new("verifying").args(["verify", "torrent"]).multiple(true))
+)]
 struct Args {
-    #[arg(required = true, help = "File and directory paths")]
+    #[arg(
+        required_unless_present_any = ["compare", "diff", "files_from", "files_from0", "torrent", "db", "generate_manpage"],
+        help = "File and directory paths"
+    )]
     paths: Vec<PathBuf>,
     #[arg(short, long, help = "Parse directories recursively")]
     recursive: bool,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Only include files matching a glob pattern"
+    )]
+    include: Vec<Pattern>,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        help = "Exclude files and directories matching a glob pattern"
+    )]
+    exclude: Vec<Pattern>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Skip directories with the given name"
+    )]
+    exclude_dir: Vec<OsString>,
+    #[arg(long, help = "Skip files ignored by .gitignore and .ignore files")]
+    ignore_vcs: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Read paths from a file, one per line"
+    )]
+    files_from: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "files_from",
+        help = "Read NUL separated paths from a file"
+    )]
+    files_from0: Option<PathBuf>,
+    #[arg(long, help = "Follow symbolic links to files and directories")]
+    follow_symlinks: bool,
+    #[arg(
+        long,
+        conflicts_with = "follow_symlinks",
+        help = "Skip symbolic links found in directories"
+    )]
+    skip_symlinks: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["follow_symlinks", "skip_symlinks"],
+        help = "Hash the target path of symbolic links instead of their contents"
+    )]
+    hash_link_target: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["verify", "expect", "check_names", "check_zip", "renaming", "compare"],
+        help = "Hash the files inside ZIP, tar and 7z archives instead of the archives themselves"
+    )]
+    archives: bool,
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        default_value_t = 1,
+        requires = "archives",
+        help = "Also hash the files inside archives nested in archives up to DEPTH levels deep"
+    )]
+    archive_depth: usize,
+    #[arg(
+        long,
+        help = "Hash the decompressed contents of .gz, .xz, .zst and .bz2 files instead of the compressed files"
+    )]
+    decompress: bool,
+    #[arg(
+        long,
+        conflicts_with = "io_uring",
+        help = "Hash files through memory mapping instead of reading them, which is faster for cached files"
+    )]
+    mmap: bool,
+    #[arg(
+        long,
+        help = "Read files with io_uring, keeping several reads in flight, which is faster on fast SSDs (Linux only)"
+    )]
+    io_uring: bool,
+    #[arg(
+        long,
+        conflicts_with = "mmap",
+        help = "Bypass the page cache when reading files, so that hashing does not evict data cached for other programs"
+    )]
+    direct: bool,
+    #[arg(
+        long,
+        overrides_with = "no_hidden",
+        help = "Include hidden files and directories [default on all platforms except macOS]"
+    )]
+    hidden: bool,
+    #[arg(
+        long,
+        overrides_with = "hidden",
+        help = "Skip hidden files and directories"
+    )]
+    no_hidden: bool,
+    #[arg(short = 'x', long, help = "Do not cross file system boundaries")]
+    one_file_system: bool,
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, help = "Skip files smaller than SIZE, e.g. 10M")]
+    min_size: Option<u64>,
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, help = "Skip files larger than SIZE, e.g. 2G")]
+    max_size: Option<u64>,
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Skip files not modified after a date or the modification time of a file"
+    )]
+    newer_than: Option<SystemTime>,
+    #[arg(
+        long,
+        value_name = "TIME",
+        value_parser = parse_time,
+        help = "Skip files not modified before a date or the modification time of a file"
+    )]
+    older_than: Option<SystemTime>,
     #[arg(short, long, help = "Output file name")]
     out_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["expect", "check_names", "check_zip", "torrent", "renaming", "check_xattr", "check_extra", "archives", "compare"],
+        help = "Store checksums in or verify them against a SQLite database"
+    )]
+    db: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "Reuse checksums of files whose size and modification time are unchanged when creating checksums, caching them in FILE [default: crc32/cache.sqlite in the user's cache directory]"
+    )]
+    cache: Option<Option<PathBuf>>,
+    #[arg(
+        long,
+        help = "Write a comment header with the size and modification time of each file to SFV files"
+    )]
+    header: bool,
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = clap::value_parser!(u32).range(1..=1000),
+        requires = "out_file",
+        conflicts_with = "verify",
+        help = "Write PAR2 recovery data for a percentage of the input size next to the output file"
+    )]
+    par2: Option<u32>,
+    #[arg(
+        long,
+        requires = "out_file",
+        conflicts_with = "verify",
+        help = "Update an existing output file, only hashing files which are new or modified"
+    )]
+    update: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["verify", "expect", "check_names", "check_zip", "renaming", "archives", "decompress", "hash_link_target"],
+        help = "Store checksums in extended attributes of the hashed files"
+    )]
+    xattr: bool,
+    #[arg(
+        long,
+        requires = "out_file",
+        conflicts_with_all = ["verify", "expect", "check_names", "check_zip", "torrent", "renaming", "check_xattr", "db", "par2", "update", "xattr", "header", "archives", "files_from", "files_from0", "compare"],
+        help = "Keep the output file up to date by hashing files as they are created or modified until interrupted"
+    )]
+    watch: bool,
+    #[arg(
+        long,
+        requires = "verify",
+        conflicts_with_all = ["out_file", "expect", "check_names", "check_zip", "torrent", "renaming", "check_xattr", "merge", "convert", "fix", "watch", "compare", "diff"],
+        help = "Verify the given checksum files or database periodically until interrupted"
+    )]
+    scrub: bool,
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "1d",
+        requires = "scrub",
+        help = "Time between the starts of two rounds of scrubbing, e.g. 12h or 7d"
+    )]
+    interval: Duration,
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "scrub",
+        help = "Split the listed files into N parts and verify one part per round of scrubbing"
+    )]
+    parts: u32,
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "scrub",
+        help = "Append the results of each round of scrubbing to a file as JSON Lines"
+    )]
+    history: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        requires = "scrub",
+        help = "Serve metrics of scrubbing for Prometheus at http://ADDRESS/metrics, e.g. 127.0.0.1:9184"
+    )]
+    metrics: Option<String>,
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 2000,
+        requires = "watch",
+        help = "Time in milliseconds a file must not have been modified for before it is hashed while watching"
+    )]
+    debounce: u64,
+    #[arg(
+        long,
+        value_enum,
+        help = "Mimic the output and exit codes of another tool"
+    )]
+    compat: Option<Compat>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Format of checksum files [default: detected from the file extension]"
+    )]
+    manifest_format: Option<ManifestFormat>,
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8, help = "Encoding of checksum files")]
+    encoding: Encoding,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Normalization::None,
+        help = "Unicode normalization of paths in checksum files"
+    )]
+    normalize: Normalization,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Separator::Native,
+        help = "Path separator used in created checksum files"
+    )]
+    separator: Separator,
+    #[arg(
+        long,
+        help = "Find files whose case differs from the checksum file when verifying"
+    )]
+    ignore_case: bool,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Directory relative paths in a verified checksum file refer to [default: directory of the checksum file]"
+    )]
+    base_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with = "verify",
+        help = "Write paths relative to a directory instead of the current directory"
+    )]
+    relative_to: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["verify", "relative_to"],
+        help = "Write absolute paths"
+    )]
+    absolute: bool,
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        help = "Remove a prefix from paths written to or read from checksum files"
+    )]
+    strip_prefix: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        help = "Add a prefix to paths written to or read from checksum files"
+    )]
+    add_prefix: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "OLD=NEW",
+        value_parser = parse_mapping,
+        help = "Replace a prefix of paths written to or read from checksum files"
+    )]
+    map: Vec<(PathBuf, PathBuf)>,
     #[arg(short, long, help = "Verify a checksum file")]
     verify: bool,
+    #[arg(
+        short,
+        long,
+        requires = "verify",
+        help = "Only print files which fail verification"
+    )]
+    quiet: bool,
+    #[arg(
+        long,
+        requires = "verify",
+        help = "Report files in the base directory which are not listed in the checksum file"
+    )]
+    check_extra: bool,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        requires = "verify",
+        help = "Only verify listed files matching a glob pattern"
+    )]
+    only: Vec<Pattern>,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        requires = "verify",
+        help = "Skip listed files matching a glob pattern"
+    )]
+    skip: Vec<Pattern>,
+    #[arg(
+        long,
+        requires = "verify",
+        conflicts_with = "compat",
+        help = "Print results as soon as files are verified instead of in the order listed"
+    )]
+    unordered: bool,
+    #[arg(
+        long,
+        requires = "verifying",
+        help = "Report missing files without failing verification"
+    )]
+    ignore_missing: bool,
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        requires = "verify",
+        help = "Run a command with the results as JSON on stdin if files do not match or are missing"
+    )]
+    notify_command: Option<String>,
+    #[arg(
+        long,
+        value_name = "URL",
+        requires = "verify",
+        help = "Post the results as JSON to a webhook if files do not match or are missing"
+    )]
+    notify_url: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "verify",
+        conflicts_with = "scrub",
+        help = "Write an HTML report of the verification with the failures and the counts of each directory"
+    )]
+    report: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "verify",
+        conflicts_with = "scrub",
+        help = "Write a Markdown report of the verification like --report, e.g. for issue trackers and wikis"
+    )]
+    report_md: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "verify",
+        help = "Fail on malformed lines in the checksum file instead of skipping them"
+    )]
+    strict: bool,
+    #[arg(
+        long,
+        requires = "verify",
+        conflicts_with = "quiet",
+        help = "Print nothing and only report the result of verification by the exit code"
+    )]
+    status: bool,
+    #[arg(
+        short,
+        long,
+        help = "Print the number of files by status, their size and the throughput on stderr"
+    )]
+    summary: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Append a log of the run with timestamps to a file"
+    )]
+    log_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        value_enum,
+        default_value_t = LogLevel::Info,
+        help = "Least severe level of logged messages"
+    )]
+    log_level: LogLevel,
+    #[arg(long, help = "Log to the local syslog daemon")]
+    syslog: bool,
+    #[arg(long, help = "Log to the systemd journal")]
+    journald: bool,
+    #[arg(short, long, help = "Number of files to hash in parallel")]
+    jobs: Option<usize>,
+    #[arg(short, long, help = "Show progress while hashing")]
+    progress: bool,
+    #[arg(short, long, value_enum, default_value_t = Format::Text, help = "Output format")]
+    format: Format,
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        help = "Columns of CSV and TSV output [default: path,checksum,size,mtime,status]"
+    )]
+    columns: Vec<Column>,
+    #[arg(
+        long,
+        help = "Print plain text results using a template such as '{crc}  {path}  {size}'"
+    )]
+    template: Option<Template>,
+    #[arg(
+        short = '0',
+        long,
+        help = "Terminate plain text lines with NUL instead of a line break and do not escape paths"
+    )]
+    print0: bool,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        help = "Checksum algorithm [default: crc32, or detected from checksum files when verifying]"
+    )]
+    algorithm: Option<Algorithm>,
+    #[arg(
+        long,
+        conflicts_with = "algorithm",
+        help = "Use a CRC with custom parameters, e.g. width=16,poly=0x1021,init=0xffff,refin=false,refout=false,xorout=0"
+    )]
+    crc_params: Option<CrcParams>,
+    #[arg(
+        short,
+        long,
+        value_name = "CHECKSUM",
+        conflicts_with_all = ["verify", "out_file", "recursive"],
+        help = "Check a single file against the given checksum"
+    )]
+    expect: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["verify", "out_file", "expect"],
+        help = "Check files against CRCs embedded in their names such as [1A2B3C4D]"
+    )]
+    check_names: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["verify", "out_file", "expect", "check_names", "algorithm"],
+        help = "Check the members of ZIP archives against the CRCs stored in them"
+    )]
+    check_zip: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["paths", "verify", "out_file", "expect", "check_names", "check_zip", "archives", "compare"],
+        help = "Verify downloaded files against the piece hashes of a torrent file"
+    )]
+    torrent: Option<PathBuf>,
+    #[arg(
+        long,
+        conflicts_with_all = ["verify", "out_file", "expect", "check_names", "check_zip", "torrent", "compare"],
+        help = "Check files against checksums stored in their extended attributes"
+    )]
+    check_xattr: bool,
+    #[arg(
+        long,
+        requires = "out_file",
+        conflicts_with_all = ["verify", "expect", "check_names", "check_zip", "torrent", "renaming", "check_xattr", "db", "update", "par2", "xattr", "header", "compare"],
+        help = "Merge the given checksum files into the output file"
+    )]
+    merge: bool,
+    #[arg(
+        long,
+        requires = "merge",
+        help = "Rewrite relative paths of merged checksum files to be relative to the directory of the output file"
+    )]
+    rebase: bool,
+    #[arg(
+        long,
+        requires = "out_file",
+        conflicts_with_all = ["verify", "expect", "check_names", "check_zip", "torrent", "renaming", "check_xattr", "merge", "db", "update", "par2", "xattr", "header", "compare"],
+        help = "Convert the given checksum files to the format of the output file, rehashing files if another algorithm is selected"
+    )]
+    convert: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["verify", "out_file", "expect", "check_names", "check_zip", "torrent", "renaming", "check_xattr", "merge", "convert", "db", "compare"],
+        help = "Sort, deduplicate and normalize the given checksum files in place without hashing files"
+    )]
+    fix: bool,
+    #[arg(
+        long,
+        group = "renaming",
+        conflicts_with_all = ["verify", "out_file", "expect", "check_names", "format", "template"],
+        help = "Rename files to include their checksum"
+    )]
+    rename: bool,
+    #[arg(
+        long,
+        group = "renaming",
+        conflicts_with_all = ["verify", "out_file", "expect", "check_names", "format", "template"],
+        help = "Check files against CRCs embedded in their names and remove them"
+    )]
+    untag: bool,
+    #[arg(
+        long,
+        group = "renaming",
+        conflicts_with_all = ["verify", "out_file", "expect", "check_names", "format", "template"],
+        help = "Check files against CRCs embedded in their names and replace those which do not match"
+    )]
+    retag: bool,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        default_value = "{name} [{CRC}]{ext}",
+        help = "Template of new file names when renaming"
+    )]
+    rename_pattern: Template,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        default_value_t = OnConflict::Skip,
+        help = "What to do if a new file name is already taken"
+    )]
+    on_conflict: OnConflict,
+    #[arg(
+        short = 'n',
+        long,
+        requires = "renaming",
+        help = "Print new file names without renaming files"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["LEFT", "RIGHT"],
+        conflicts_with_all = ["paths", "verify", "out_file", "expect"],
+        help = "Compare two files or directory trees"
+    )]
+    compare: Option<Vec<PathBuf>>,
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        conflicts_with_all = ["paths", "verify", "out_file", "expect", "compare"],
+        help = "Report files added, removed, changed or renamed between two checksum files"
+    )]
+    diff: Option<Vec<PathBuf>>,
+    /// Print a man page generated from these arguments in roff format, for packaging.
+    #[arg(long, hide = true, exclusive = true)]
+    generate_manpage: bool,
 }
 
-/// Computes the CRC32 of a file.
-///
-/// Reads the provided file in chunks of `CHUNK_SIZE` and uses `crc32fast` to compute the CRC32 checksum. Any error is
-/// propagated with added context.
-fn crc32<P>(file: P) -> Result<u32>
-where
-    P: AsRef<Path>,
-{
-    let file = file.as_ref();
-    let mut fp =
-        File::open(file).with_context(|| format!("Failed to open file {}", file.display()))?;
-    let mut buf = vec![0; CHUNK_SIZE];
-    let mut hasher = Hasher::new();
-
-    loop {
-        let n = fp
-            .read(&mut buf)
-            .with_context(|| format!("Error while reading file {}", file.display()))?;
-
-        if n == 0 {
-            break;
-        }
-
-        hasher.update(&buf[..n]);
-    }
+/// Set on the first Ctrl-C to cancel the mode.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
-    Ok(hasher.finalize())
+/// Observer of the command line, which shows progress bars if enabled and cancels the mode on Ctrl-C.
+struct Cli {
+    bars: Option<ProgressBars>,
 }
 
-/// Retrieves list of files in a directory.
-///
-/// If `recursive` is specified, all subdirectories are searched as well. Errors are propagated with added context.
-fn get_files<P>(dir: P, recursive: bool) -> Result<Vec<PathBuf>>
-where
-    P: AsRef<Path>,
-{
-    let dir = dir.as_ref();
-    let mut files = Vec::new();
-    for entry in
-        fs::read_dir(dir).with_context(|| format!("Failed to read directory {}", dir.display()))?
-    {
-        let path = entry
-            .with_context(|| format!("Error while reading directory {}", dir.display()))?
-            .path();
-
-        if recursive && path.is_dir() {
-            files.append(&mut get_files(&path, true)?);
-        } else if path.is_file() {
-            files.push(path);
+impl Observer for Cli {
+    fn event(&self, event: &Event) {
+        if let Some(bars) = &self.bars {
+            bars.event(event);
         }
     }
 
-    Ok(files)
-}
-
-/// Returns a sorted list of all files in given paths.
-///
-/// If `recursive` is specified, directories are search recusively. Any error is propagated.
-fn get_all_files<A>(paths: A, recursive: bool) -> Result<Vec<PathBuf>>
-where
-    A: IntoIterator<Item = PathBuf>,
-{
-    let mut files = Vec::new();
-    for path in paths {
-        if path.is_dir() {
-            files.append(&mut get_files(&path, recursive)?);
-        } else if path.is_file() {
-            files.push(path);
-        } else {
-            return Err(Error::msg(format!(
-                "{} is neither a file nor a directory",
-                path.display()
-            )));
+    fn print(&self, print: &mut dyn FnMut()) {
+        match &self.bars {
+            Some(bars) => bars.print(print),
+            None => print(),
         }
     }
 
-    files.sort();
-    Ok(files)
+    fn is_cancelled(&self) -> bool {
+        INTERRUPTED.load(Ordering::Relaxed)
+    }
 }
 
-/// Computes CRC32 values of provided paths and prints them on stdout and optionally writes a output file.
-///
-/// If `recursive` is specified any directory in `paths` is recursively searched for files. If `out_file` is `None`, no
-/// output file is written.
-fn create_sfv<A>(paths: A, recursive: bool, out_file: Option<PathBuf>) -> Result<()>
-where
-    A: IntoIterator<Item = PathBuf>,
-{
-    let files = get_all_files(paths, recursive)?;
-
-    let mut out_text = String::default();
-    for file in files {
-        let checksum = crc32(&file)?;
-        let cwd = env::current_dir().context("Failed to get current directory")?;
-        let cwd = fs::canonicalize(&cwd)
-            .with_context(|| format!("Failed to get canonical path for {}", cwd.display()))?;
-        let file_canonical = fs::canonicalize(&file)
-            .with_context(|| format!("Failed to get canonical path for {}", file.display()))?;
-        let file = file_canonical.strip_prefix(cwd).unwrap_or(&file);
-
-        println!("{} {checksum:08X}", file.display());
-
-        writeln!(out_text, "{} {checksum:08X}", file.display())
-            .context("Failed to write to string")?;
+/// Parse command line arguments and call either `compare`, `diff_sfv`, `expect_checksum`, `rename_files`,
+/// `verify_torrent`, `watch_sfv`, `merge_sfv`, `convert_sfv`, `fix_sfv`, `check_xattrs`, `check_zips`, `check_names`,
+/// `scrub`, `verify_db`, `verify_sfv` or `create_sfv` depending on options provided.
+fn main() -> ExitCode {
+    let mut args = Args::parse_from(commands::expand(env::args_os()));
+    if args.generate_manpage {
+        return match commands::write_man_page(Args::command(), &mut io::stdout()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                println!("{} Failed to write man page: {e}", "[ERROR]".red().bold());
+                ExitCode::from(EXIT_ERROR)
+            }
+        };
     }
-
-    if let Some(path) = out_file {
-        fs::write(&path, out_text)
-            .with_context(|| format!("Failed to write to {}", path.display()))?;
+    if let Some(params) = args.crc_params {
+        args.algorithm = Some(Algorithm::Crc(params));
     }
 
-    Ok(())
-}
+    let hash_options = HashOptions {
+        algorithm: args.algorithm.unwrap_or(Algorithm::Crc32),
+        link_target: args.hash_link_target,
+        archives: args.archives,
+        archive_depth: args.archive_depth,
+        decompress: args.decompress,
+        io: if args.io_uring {
+            IoBackend::IoUring
+        } else if args.mmap {
+            IoBackend::Mmap
+        } else {
+            IoBackend::Read
+        },
+        direct: args.direct,
+    };
+    let walk_options = WalkOptions {
+        recursive: args.recursive,
+        include: args.include,
+        exclude: args.exclude,
+        exclude_dirs: args.exclude_dir,
+        ignore_vcs: args.ignore_vcs,
+        symlinks: if args.follow_symlinks {
+            SymlinkPolicy::Follow
+        } else if args.skip_symlinks {
+            SymlinkPolicy::Skip
+        } else if args.hash_link_target {
+            SymlinkPolicy::Target
+        } else {
+            SymlinkPolicy::Files
+        },
+        one_file_system: args.one_file_system,
+        min_size: args.min_size,
+        max_size: args.max_size,
+        newer_than: args.newer_than,
+        older_than: args.older_than,
+        hidden: (walk::HIDDEN_DEFAULT || args.hidden) && !args.no_hidden,
+    };
+    let verify_options = VerifyOptions {
+        check_extra: args.check_extra,
+        only: args.only,
+        skip: args.skip,
+        unordered: args.unordered,
+        ignore_missing: args.ignore_missing,
+        detect_algorithm: args.algorithm.is_none(),
+        hooks: Hooks {
+            command: args.notify_command,
+            url: args.notify_url,
+        },
+        part: None,
+    };
+    let rename_options = RenameOptions {
+        mode: if args.untag {
+            RenameMode::Untag
+        } else if args.retag {
+            RenameMode::Retag
+        } else {
+            RenameMode::Tag
+        },
+        pattern: args.rename_pattern,
+        on_conflict: args.on_conflict,
+        dry_run: args.dry_run,
+    };
+    let path_options = PathOptions {
+        normalization: args.normalize,
+        separator: args.separator,
+        ignore_case: args.ignore_case,
+        base_dir: args.base_dir,
+        relative_to: args.relative_to,
+        absolute: args.absolute,
+        strip_prefix: args.strip_prefix,
+        mappings: args.map,
+        add_prefix: args.add_prefix,
+    };
+    let scan_options = ScanOptions {
+        walk_options,
+        path_options,
+        hash_options,
+        // The global thread pool is sized by `--jobs` below, so that modes do not build a pool of their own each time.
+        jobs: None,
+    };
+    let manifest_options = ManifestOptions {
+        header: args.header,
+        compat: args.compat,
+        out_file: args.out_file,
+        db: args.db,
+        cache: args
+            .cache
+            .and_then(|path| path.or_else(cache::default_path)),
+        par2: args.par2,
+        xattr: args.xattr,
+        update: args.update,
+        encoding: args.encoding,
+        format: args.manifest_format,
+        strict: args.strict,
+    };
+    let output_options = OutputOptions {
+        format: args.format,
+        columns: if args.columns.is_empty() {
+            DEFAULT_COLUMNS.to_vec()
+        } else {
+            args.columns
+        },
+        template: args.template,
+        print0: args.print0,
+        quiet: args.quiet,
+        status: args.status,
+    };
+
+    let file_list = match (&args.files_from, &args.files_from0) {
+        (Some(list), _) => Some(read_file_list(list, b'\n')),
+        (_, Some(list)) => Some(read_file_list(list, b'\0')),
+        _ => None,
+    };
+    match file_list {
+        Some(Ok(paths)) => args.paths.extend(paths),
+        Some(Err(e)) => {
+            println!("{} {e:#}", "[ERROR]".red().bold());
+            return ExitCode::from(EXIT_ERROR);
+        }
+        None => {}
+    }
 
-/// Verify a checksum file.
-///
-/// Read the checksum file, compute CRC values of the provided files and match them with values in file. Switches
-/// current directory to parent directory of SFV file temporarily.
-fn verify_sfv<P>(sfv_file: P) -> Result<()>
-where
-    P: Into<PathBuf>,
-{
-    let sfv_file = sfv_file.into();
-    let data = fs::read_to_string(&sfv_file)
-        .with_context(|| format!("Failed to read file {}", sfv_file.display()))?;
-    let lines = data.lines();
-
-    let cwd = env::current_dir().context("Failed to get current directory")?;
-    if let Some(dir) = fs::canonicalize(&sfv_file)
-        .with_context(|| format!("Failed to get canonical path for {}", sfv_file.display()))?
-        .parent()
+    if let Some(jobs) = args.jobs
+        && let Err(e) = ThreadPoolBuilder::new().num_threads(jobs).build_global()
     {
-        env::set_current_dir(dir)
-            .with_context(|| format!("Failed to set current directory to {}", dir.display()))?;
+        println!("{} {e:#}", "[ERROR]".red().bold());
+        return ExitCode::from(EXIT_ERROR);
     }
 
-    for mut line in lines {
-        line = line.trim();
-        if line.is_empty() || line.starts_with(';') {
-            continue;
-        }
+    let log_options = LogOptions {
+        level: args.log_level,
+        file: args.log_file.as_deref(),
+        syslog: args.syslog,
+        journald: args.journald,
+    };
+    if let Err(e) = logging::init(&log_options) {
+        println!("{} {e:#}", "[ERROR]".red().bold());
+        return ExitCode::from(EXIT_ERROR);
+    }
+    let command_line = env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    log::info!(command = command_line.as_str(); "Started");
 
-        let path = line[..line.len() - 8].trim();
-        let checksum = line[line.len() - 8..].to_uppercase();
-
-        match crc32(path) {
-            Ok(computed_checksum) => {
-                let computed_checksum = format!("{computed_checksum:08X}");
-                if computed_checksum == checksum {
-                    println!("{path} {}", "OK".green().bold());
-                } else {
-                    println!(
-                        "{path} {} {computed_checksum} ≠ {checksum}",
-                        "FAIL".yellow().bold()
-                    );
-                }
-            }
-            Err(e) => {
-                println!("{path} {} {e:#}", "ERROR".red().bold());
+    // The first Ctrl-C stops hashing and reports the files done so far, a second one exits immediately. Watching and
+    // scrubbing run until interrupted, so they exit on the first one.
+    if !args.watch && !args.scrub {
+        let handled = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::Relaxed) {
+                process::exit(EXIT_CANCELLED.into());
             }
+        });
+        if let Err(e) = handled {
+            log::warn!("Failed to handle Ctrl-C: {e}");
         }
     }
+    let cli = Cli {
+        bars: args.progress.then(ProgressBars::new),
+    };
+    let progress = Some(&cli as &dyn Observer);
+    let start = Instant::now();
+    let report_paths = args.paths.clone();
+    let result = if let Some(paths) = args.compare {
+        compare(
+            &paths[0],
+            &paths[1],
+            &scan_options,
+            progress,
+            &output_options,
+        )
+    } else if let Some(paths) = args.diff {
+        diff_sfv(
+            &paths[0],
+            &paths[1],
+            &scan_options,
+            &manifest_options,
+            &verify_options,
+            &output_options,
+        )
+    } else if let Some(expected) = args.expect {
+        expect_checksum(
+            args.paths,
+            &expected,
+            progress,
+            &output_options,
+            &scan_options,
+        )
+    } else if args.rename || args.untag || args.retag {
+        rename_files(args.paths, &scan_options, &rename_options, progress)
+    } else if let Some(torrent_file) = &args.torrent {
+        verify_torrent(
+            torrent_file,
+            &scan_options,
+            &verify_options,
+            progress,
+            &output_options,
+        )
+    } else if args.watch {
+        watch_sfv(
+            args.paths,
+            &scan_options,
+            &manifest_options,
+            &output_options,
+            Duration::from_millis(args.debounce),
+        )
+    } else if args.merge {
+        merge_sfv(
+            &args.paths,
+            &scan_options,
+            &manifest_options,
+            &verify_options,
+            &output_options,
+            args.rebase,
+        )
+    } else if args.convert {
+        convert_sfv(
+            &args.paths,
+            args.algorithm,
+            &scan_options,
+            &manifest_options,
+            progress,
+            &output_options,
+        )
+    } else if args.fix {
+        fix_sfv(
+            &args.paths,
+            &scan_options,
+            &manifest_options,
+            &verify_options,
+            &output_options,
+        )
+    } else if args.check_xattr {
+        check_xattrs(
+            args.paths,
+            &scan_options,
+            args.xattr,
+            progress,
+            &output_options,
+        )
+    } else if args.check_zip {
+        check_zips(args.paths, &scan_options, progress, &output_options)
+    } else if args.check_names {
+        check_names(args.paths, &scan_options, progress, &output_options)
+    } else if args.scrub {
+        let scrub_options = ScrubOptions {
+            interval: args.interval,
+            parts: args.parts,
+            history: args.history,
+            recursive: args.recursive,
+            metrics: args.metrics,
+        };
+        scrub(
+            args.paths,
+            &scrub_options,
+            &scan_options,
+            &manifest_options,
+            &verify_options,
+            &output_options,
+        )
+    } else if let (true, Some(db)) = (args.verify, &manifest_options.db) {
+        verify_db(
+            db,
+            &args.paths,
+            &scan_options,
+            &verify_options,
+            progress,
+            &output_options,
+        )
+    } else if args.verify {
+        let algorithms = if verify_options.detect_algorithm {
+            Algorithm::value_variants()
+        } else {
+            &[scan_options.hash_options.algorithm]
+        };
+        get_checksum_files(args.paths, algorithms, args.recursive).and_then(|sfv_files| {
+            verify_sfv(
+                &sfv_files,
+                &scan_options,
+                &manifest_options,
+                &verify_options,
+                progress,
+                &output_options,
+            )
+        })
+    } else {
+        create_sfv(
+            args.paths,
+            &scan_options,
+            &manifest_options,
+            progress,
+            &output_options,
+        )
+    };
 
-    env::set_current_dir(&cwd)
-        .with_context(|| format!("Failed to set current directory to {}", cwd.display()))?;
-    Ok(())
-}
+    let result = result.and_then(|summary| {
+        if let Some(path) = &args.report {
+            report::write_html(path, &report_paths, &summary, start.elapsed())?;
+        }
+        if let Some(path) = &args.report_md {
+            report::write_markdown(path, &report_paths, &summary, start.elapsed())?;
+        }
+        Ok(summary)
+    });
+    if let Ok(summary) = &result
+        && args.summary
+        && !args.status
+    {
+        eprintln!("{}", summary.report(start.elapsed()));
+    }
+    if let Ok(summary) = &result
+        && args.verify
+    {
+        verify_options.hooks.notify(summary);
+    }
 
-/// Parse command line arguments and call either `verify_sfv` or `create_sfv` depending on options provided.
-fn main() -> ExitCode {
-    let mut args = Args::parse();
+    match &result {
+        Ok(summary) => log::info!(
+            files = summary.files,
+            ok = summary.ok,
+            failed = summary.failed,
+            missing = summary.missing,
+            extra = summary.extra,
+            errors = summary.errors,
+            bytes = summary.bytes;
+            "Finished: {}",
+            summary.report(start.elapsed())
+        ),
+        Err(e) => log::error!(error = format!("{e:#}").as_str(); "Failed: {e:#}"),
+    }
 
-    let mut exit_code = ExitCode::SUCCESS;
-    if args.verify {
-        if let Err(e) = verify_sfv(args.paths.remove(0)) {
+    match result {
+        // cksfv does not distinguish between kinds of failures.
+        Ok(summary) if manifest_options.compat.is_some() && !summary.is_ok() => ExitCode::FAILURE,
+        Ok(summary) => summary.exit_code(),
+        Err(e) => {
             println!("{} {e:#}", "[ERROR]".red().bold());
-            exit_code = ExitCode::FAILURE;
+            ExitCode::from(EXIT_ERROR)
         }
-    } else if let Err(e) = create_sfv(args.paths, args.recursive, args.out_file) {
-        println!("{} {e:#}", "[ERROR]".red().bold());
-        exit_code = ExitCode::FAILURE;
     }
-
-    exit_code
 }
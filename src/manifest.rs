@@ -0,0 +1,782 @@
+//! Formats of checksum files.
+//!
+//! Every format writes and reads a list of entries with paths and checksums. Encodings, paths and comment headers are
+//! handled by the caller, while `SfvFile` keeps the comments of SFV style files in place for callers which need them.
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use csv::ReaderBuilder;
+use quick_xml::{
+    escape::escape,
+    events::{BytesStart, Event},
+    reader::Reader,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compat::CKSFV_WEB_SITE,
+    error::{BoxError, Error, Result},
+    hash::{Algorithm, LineStyle},
+    table,
+    walk::path_from_bytes,
+};
+
+/// Supported formats of checksum files.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ManifestFormat {
+    /// One line per file, in SFV format for CRC algorithms and in the format of `md5sum`/`sha256sum` otherwise
+    Sfv,
+    /// YAML document with one nested mapping per directory
+    Yaml,
+    /// DFXML document as written by `hashdeep -d`
+    Xml,
+    /// JSON array of objects with `path`, `checksum` and `size` fields, as printed by `--format json`
+    Json,
+    /// CSV table with a header row naming `path`, `checksum` and `size` columns, as printed by `--format csv`
+    Csv,
+    /// TSV table like the CSV one, as printed by `--format tsv`
+    Tsv,
+}
+
+/// File listed in a checksum file.
+#[derive(Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub checksum: String,
+    /// Size in bytes, only stored by some formats.
+    pub size: Option<u64>,
+    /// Algorithm of the checksum if it differs from the one the file was read with, e.g. in BSD style files listing
+    /// checksums of several algorithms.
+    pub algorithm: Option<Algorithm>,
+}
+
+/// Checksum file in SFV style, i.e. SFV or the format of `md5sum`, with its comments.
+///
+/// Comments are kept where they were, so that a file which is parsed and written again only differs by its line
+/// breaks, blank lines and malformed lines, which are left out.
+#[derive(Clone, Default)]
+pub struct SfvFile {
+    /// Listed files in the order they are listed.
+    pub entries: Vec<Entry>,
+    pub comments: Vec<Comment>,
+}
+
+/// Comment line of an SFV style file.
+#[derive(Clone)]
+pub struct Comment {
+    /// Number of entries listed before the comment.
+    pub position: usize,
+    /// Line including the leading `;`.
+    pub text: Vec<u8>,
+}
+
+/// File listed in the comment header of an SFV file written by cksfv and `crc32`.
+pub struct HeaderFile {
+    pub size: u64,
+    /// Modification time in local time to the second, e.g. `12:00.00 2024-01-01`, see `HEADER_TIME`.
+    pub modified: String,
+}
+
+/// Format of modification times in comment headers as used by `chrono`.
+pub const HEADER_TIME: &str = "%H:%M.%S %Y-%m-%d";
+
+/// Checksum file in YAML format.
+#[derive(Serialize, Deserialize)]
+struct YamlManifest {
+    /// Name of the algorithm, as used in BSD style lines.
+    algorithm: String,
+    files: BTreeMap<String, YamlNode>,
+}
+
+/// File listed in a checksum file in JSON format.
+///
+/// Other fields of results printed with `--format json` are ignored, as are results without checksum.
+#[derive(Serialize, Deserialize)]
+struct JsonEntry {
+    path: String,
+    checksum: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+}
+
+/// Entry of a directory in a YAML checksum file.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum YamlNode {
+    /// Checksum of a file.
+    File(String),
+    /// Entries of a directory by name.
+    Dir(BTreeMap<String, YamlNode>),
+}
+
+impl ManifestFormat {
+    /// Detects the format of a checksum file from its extension. Files with unknown extensions are assumed to be SFV
+    /// style files.
+    pub fn detect(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") => {
+                ManifestFormat::Yaml
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("xml") => ManifestFormat::Xml,
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ManifestFormat::Json,
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ManifestFormat::Csv,
+            Some(ext) if ext.eq_ignore_ascii_case("tsv") => ManifestFormat::Tsv,
+            _ => ManifestFormat::Sfv,
+        }
+    }
+
+    /// Detects the format of an existing checksum file from its extension or, for unknown extensions such as `.txt`,
+    /// from its contents `data`.
+    pub fn sniff(path: &Path, data: &[u8]) -> Self {
+        let format = Self::detect(path);
+        if format != ManifestFormat::Sfv {
+            return format;
+        }
+
+        let first_line = data
+            .split(|&b| b == b'\n')
+            .map(<[u8]>::trim_ascii)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        let fields = |delimiter| first_line.split(move |&b| b == delimiter);
+        let is_header = |delimiter| {
+            fields(delimiter).any(|field| field == b"path")
+                && fields(delimiter).any(|field| field == b"checksum")
+        };
+        match first_line {
+            [b'[', ..] => ManifestFormat::Json,
+            [b'<', ..] => ManifestFormat::Xml,
+            _ if first_line.starts_with(b"algorithm:") => ManifestFormat::Yaml,
+            _ if is_header(b'\t') => ManifestFormat::Tsv,
+            _ if is_header(b',') => ManifestFormat::Csv,
+            _ => ManifestFormat::Sfv,
+        }
+    }
+
+    /// Guesses the algorithm of the checksum file at `path` with contents `data`.
+    ///
+    /// Extensions such as `.md5` and names such as `SHA256SUMS` name the algorithm, see `Algorithm::from_file_name`.
+    /// Otherwise YAML and XML files are searched for the name of the algorithm, while the algorithm of other files is
+    /// guessed from their first entry, see `Algorithm::from_line` and `Algorithm::from_checksum`. Returns `None` if the
+    /// algorithm cannot be determined.
+    pub fn detect_algorithm(self, path: &Path, data: &[u8]) -> Option<Algorithm> {
+        if let Some(algorithm) = Algorithm::from_file_name(path) {
+            return Some(algorithm);
+        }
+
+        match self {
+            ManifestFormat::Sfv => data
+                .split(|&b| b == b'\n')
+                .map(<[u8]>::trim_ascii)
+                .find(|line| !line.is_empty() && !line.starts_with(b";"))
+                .and_then(Algorithm::from_line),
+            ManifestFormat::Yaml => {
+                let manifest: YamlManifest = serde_yaml_ng::from_slice(data).ok()?;
+                Algorithm::from_tag(&manifest.algorithm)
+            }
+            ManifestFormat::Xml => xml_digest_types(data)
+                .iter()
+                .find_map(|tag| Algorithm::from_tag(tag)),
+            ManifestFormat::Json | ManifestFormat::Csv | ManifestFormat::Tsv => self
+                .read_table(data)
+                .ok()?
+                .first()
+                .and_then(|entry| Algorithm::from_checksum(&entry.checksum)),
+        }
+    }
+
+    /// Writes a checksum file listing `entries`.
+    ///
+    /// `header` contains comment lines written at the start of SFV style files. `style` specifies the form of SFV style
+    /// lines, see `Algorithm::format_line`.
+    pub fn write(
+        self,
+        entries: &[Entry],
+        algorithm: Algorithm,
+        header: &[Vec<u8>],
+        style: LineStyle,
+    ) -> Result<Vec<u8>> {
+        match self {
+            ManifestFormat::Sfv => {
+                Ok(SfvFile::new(entries.to_vec(), header).write(algorithm, style))
+            }
+            ManifestFormat::Yaml => write_yaml(entries, algorithm),
+            ManifestFormat::Xml => write_xml(entries, algorithm),
+            ManifestFormat::Json => write_json(entries),
+            ManifestFormat::Csv => Ok(write_table(entries, b',')),
+            ManifestFormat::Tsv => Ok(write_table(entries, b'\t')),
+        }
+    }
+
+    /// Reads the entries listed in a checksum file.
+    ///
+    /// Comment lines of SFV style files starting with `;` are skipped. Malformed lines are skipped as well, unless
+    /// `strict` is specified, in which case an error listing their line numbers is returned.
+    pub fn read(self, data: &[u8], algorithm: Algorithm, strict: bool) -> Result<Vec<Entry>> {
+        match self {
+            ManifestFormat::Sfv => Ok(SfvFile::parse(data, algorithm, strict)?.entries),
+            ManifestFormat::Yaml => read_yaml(data, algorithm),
+            ManifestFormat::Xml => read_xml(data, algorithm),
+            ManifestFormat::Json | ManifestFormat::Csv | ManifestFormat::Tsv => {
+                self.read_table(data)
+            }
+        }
+    }
+
+    /// Reads the entries of a JSON, CSV or TSV file, which do not depend on the algorithm.
+    fn read_table(self, data: &[u8]) -> Result<Vec<Entry>> {
+        match self {
+            ManifestFormat::Csv => read_table(data, b','),
+            ManifestFormat::Tsv => read_table(data, b'\t'),
+            _ => read_json(data),
+        }
+    }
+}
+
+/// Removes entries listing the same path as a previous entry with the same checksum.
+///
+/// Returns the paths which were listed more than once, or an error if a path is listed with different checksums of the
+/// same algorithm. Checksums are compared ignoring case.
+pub fn dedup(entries: &mut Vec<Entry>) -> Result<Vec<PathBuf>> {
+    let mut checksums = HashMap::new();
+    let mut duplicates = Vec::new();
+    let mut unique = Vec::with_capacity(entries.len());
+    for entry in mem::take(entries) {
+        let key = (entry.path.clone(), entry.algorithm.map(Algorithm::tag));
+        match checksums.get(&key) {
+            None => {
+                checksums.insert(key, entry.checksum.clone());
+                unique.push(entry);
+            }
+            Some(checksum) if checksum.eq_ignore_ascii_case(&entry.checksum) => {
+                if !duplicates.contains(&entry.path) {
+                    duplicates.push(entry.path);
+                }
+            }
+            Some(checksum) => {
+                return Err(Error::ConflictingChecksums {
+                    path: entry.path,
+                    first: checksum.clone(),
+                    second: entry.checksum,
+                });
+            }
+        }
+    }
+    *entries = unique;
+
+    Ok(duplicates)
+}
+
+impl SfvFile {
+    /// Creates a file listing `entries` after the comment lines `header`.
+    pub fn new(entries: Vec<Entry>, header: &[Vec<u8>]) -> Self {
+        let comments = header
+            .iter()
+            .map(|text| Comment {
+                position: 0,
+                text: text.clone(),
+            })
+            .collect();
+        SfvFile { entries, comments }
+    }
+
+    /// Returns the files listed in the comment header written by cksfv and `crc32` by name.
+    pub fn header_files(&self) -> HashMap<PathBuf, HeaderFile> {
+        self.comments
+            .iter()
+            .filter_map(|comment| header_file(&comment.text))
+            .collect()
+    }
+
+    /// Returns whether the file has a comment header as written by cksfv and `crc32` before its first entry, which
+    /// lists the size and modification time of each file.
+    pub fn has_header(&self) -> bool {
+        !self.header().is_empty()
+    }
+
+    /// Replaces the entries by `entries` and the comment header written by cksfv and `crc32`, if any, by the comment
+    /// lines `header`, e.g. when the file is updated.
+    ///
+    /// Other comments are kept at the start of the file or before the first entry they preceded which is still listed,
+    /// or at the end.
+    pub fn replace(&mut self, entries: Vec<Entry>, header: &[Vec<u8>]) {
+        let old_header = self.header();
+        let start = old_header.start;
+        self.comments.drain(old_header);
+        let positions: HashMap<_, _> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.path.as_path(), i))
+            .collect();
+        for comment in self
+            .comments
+            .iter_mut()
+            .filter(|comment| comment.position > 0)
+        {
+            comment.position = self.entries[comment.position..]
+                .iter()
+                .find_map(|entry| positions.get(entry.path.as_path()).copied())
+                .unwrap_or(entries.len());
+        }
+        self.comments.sort_by_key(|comment| comment.position);
+        let header = SfvFile::new(Vec::new(), header).comments;
+        self.comments.splice(start..start, header);
+        self.entries = entries;
+    }
+
+    /// Returns the range of comments forming the header written by cksfv and `crc32` before the first entry, i.e. a
+    /// line such as `; Generated by crc32 v1.0.0 on 2024-01-01 at 12:00.00` followed by the web site of cksfv,
+    /// separators and lines listing files.
+    fn header(&self) -> Range<usize> {
+        let leading = |comment: &&Comment| comment.position == 0;
+        let Some(start) = self
+            .comments
+            .iter()
+            .take_while(leading)
+            .position(|comment| comment.text.starts_with(b"; Generated by "))
+        else {
+            return 0..0;
+        };
+        let len = self.comments[start + 1..]
+            .iter()
+            .take_while(leading)
+            .take_while(|comment| {
+                comment.text == b";"
+                    || comment.text == CKSFV_WEB_SITE.as_bytes()
+                    || header_file(&comment.text).is_some()
+            })
+            .count();
+        start..start + 1 + len
+    }
+
+    /// Parses the SFV style file `data` with checksums of `algorithm`, see `ManifestFormat::read`.
+    ///
+    /// BSD style lines may name other algorithms, which are stored with their entries. Sizes of files are taken from
+    /// comments in the header written by cksfv and `crc32`, which list the size, modification time and name of each file.
+    pub fn parse(data: &[u8], algorithm: Algorithm, strict: bool) -> Result<Self> {
+        let mut file = SfvFile::default();
+        let mut malformed = Vec::new();
+        for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+            let line = line.trim_ascii();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with(b";") {
+                file.comments.push(Comment {
+                    position: file.entries.len(),
+                    text: line.to_vec(),
+                });
+                continue;
+            }
+
+            // BSD style lines name their algorithm, which may differ from line to line.
+            let line_algorithm = Algorithm::from_bsd_line(line).filter(|&a| a != algorithm);
+            match line_algorithm.unwrap_or(algorithm).parse_line(line) {
+                Some((path, checksum)) => file.entries.push(Entry {
+                    path: path_from_bytes(&path),
+                    checksum,
+                    size: None,
+                    algorithm: line_algorithm,
+                }),
+                None => malformed.push(i + 1),
+            }
+        }
+
+        if strict && !malformed.is_empty() {
+            return Err(Error::MalformedLines {
+                line_numbers: malformed,
+            });
+        }
+
+        let listed = file.header_files();
+        for entry in &mut file.entries {
+            entry.size = entry
+                .size
+                .or_else(|| listed.get(&entry.path).map(|listed| listed.size));
+        }
+        Ok(file)
+    }
+
+    /// Writes the file with one line per entry in the form given by `style`, see `Algorithm::format_line`, and each
+    /// comment before the entry following it.
+    pub fn write(&self, algorithm: Algorithm, style: LineStyle) -> Vec<u8> {
+        let mut text = Vec::new();
+        let mut comments = self.comments.iter().peekable();
+        for (i, entry) in self.entries.iter().enumerate() {
+            while let Some(comment) = comments.next_if(|comment| comment.position <= i) {
+                text.extend(&comment.text);
+                text.push(b'\n');
+            }
+            text.extend(algorithm.format_line(
+                entry.path.as_os_str().as_encoded_bytes(),
+                &entry.checksum,
+                style,
+            ));
+            text.push(b'\n');
+        }
+        for comment in comments {
+            text.extend(&comment.text);
+            text.push(b'\n');
+        }
+        text
+    }
+}
+
+/// Returns the name of a file listed in a header comment such as `;        12345  12:00.00 2024-01-01 a.mkv` along
+/// with its size and modification time.
+fn header_file(comment: &[u8]) -> Option<(PathBuf, HeaderFile)> {
+    let rest = comment.strip_prefix(b";")?.trim_ascii_start();
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let size = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+    let mut fields = rest[digits..].trim_ascii_start().splitn(3, |&b| b == b' ');
+    let (time, date, name) = (fields.next()?, fields.next()?, fields.next()?);
+    let is_time = time.len() == 8 && time[2] == b':' && time[5] == b'.';
+    let is_date = date.len() == 10 && date[4] == b'-' && date[7] == b'-';
+    let modified = format!(
+        "{} {}",
+        String::from_utf8_lossy(time),
+        String::from_utf8_lossy(date)
+    );
+    (is_time && is_date && !name.is_empty())
+        .then(|| (path_from_bytes(name), HeaderFile { size, modified }))
+}
+
+/// Writes `entries` as a YAML document, nesting files in one mapping per directory.
+fn write_yaml(entries: &[Entry], algorithm: Algorithm) -> Result<Vec<u8>> {
+    let mut files = BTreeMap::new();
+    for Entry { path, checksum, .. } in entries {
+        let mut names = path
+            .components()
+            .map(|component| utf8_name(Path::new(component.as_os_str()), "YAML"))
+            .collect::<Result<Vec<_>>>()?;
+        let conflict = || Error::ConflictingEntries { path: path.clone() };
+        let name = names.pop().ok_or_else(conflict)?;
+
+        let mut dir = &mut files;
+        for &parent in &names {
+            let node = dir
+                .entry(parent.to_string())
+                .or_insert_with(|| YamlNode::Dir(BTreeMap::new()));
+            dir = match node {
+                YamlNode::Dir(entries) => entries,
+                YamlNode::File(_) => return Err(conflict()),
+            };
+        }
+        if dir
+            .insert(name.to_string(), YamlNode::File(checksum.clone()))
+            .is_some()
+        {
+            return Err(conflict());
+        }
+    }
+
+    let manifest = YamlManifest {
+        algorithm: algorithm.tag(),
+        files,
+    };
+    let yaml = serde_yaml_ng::to_string(&manifest).map_err(|e| Error::SerializeFailed {
+        format: "YAML",
+        source: e.into(),
+    })?;
+    Ok(yaml.into_bytes())
+}
+
+/// Reads the entries of a YAML document written by `write_yaml`.
+///
+/// Returns an error if the document was written for a different algorithm.
+fn read_yaml(data: &[u8], algorithm: Algorithm) -> Result<Vec<Entry>> {
+    let manifest: YamlManifest =
+        serde_yaml_ng::from_slice(data).map_err(|e| parse_error("YAML", e))?;
+    if !algorithm.matches_tag(&manifest.algorithm) {
+        return Err(Error::WrongAlgorithm {
+            found: manifest.algorithm,
+        });
+    }
+
+    let mut entries = Vec::new();
+    let mut pending: Vec<_> = manifest
+        .files
+        .into_iter()
+        .rev()
+        .map(|(name, node)| (PathBuf::from(name), node))
+        .collect();
+    while let Some((path, node)) = pending.pop() {
+        match node {
+            YamlNode::File(checksum) => entries.push(Entry {
+                path,
+                checksum,
+                size: None,
+                algorithm: None,
+            }),
+            YamlNode::Dir(dir) => pending.extend(
+                dir.into_iter()
+                    .rev()
+                    .map(|(name, node)| (path.join(name), node)),
+            ),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Writes `entries` as a DFXML document like `hashdeep -d` does, with one `fileobject` element per file.
+///
+/// Like BSD style lines, XXH3 checksums are written without their `XXH3_` prefix.
+fn write_xml(entries: &[Entry], algorithm: Algorithm) -> Result<Vec<u8>> {
+    let tag = algorithm.tag();
+    let mut xml = format!(
+        concat!(
+            "<?xml version='1.0' encoding='UTF-8'?>\n",
+            "<dfxml xmloutputversion='1.0'>\n",
+            "  <creator>\n",
+            "    <program>crc32</program>\n",
+            "    <version>{}</version>\n",
+            "  </creator>\n",
+        ),
+        env!("CARGO_PKG_VERSION")
+    );
+    for entry in entries {
+        let name = utf8_name(&entry.path, "XML")?;
+        let checksum = entry
+            .checksum
+            .strip_prefix("XXH3_")
+            .unwrap_or(&entry.checksum);
+        xml.push_str("  <fileobject>\n");
+        xml.push_str(&format!("    <filename>{}</filename>\n", escape(name)));
+        if let Some(size) = entry.size {
+            xml.push_str(&format!("    <filesize>{size}</filesize>\n"));
+        }
+        xml.push_str(&format!(
+            "    <hashdigest type='{}'>{checksum}</hashdigest>\n",
+            escape(&tag)
+        ));
+        xml.push_str("  </fileobject>\n");
+    }
+    xml.push_str("</dfxml>\n");
+
+    Ok(xml.into_bytes())
+}
+
+/// Reads the entries of a DFXML document, such as one written by `write_xml` or `hashdeep -d`.
+///
+/// Only `hashdigest` elements whose `type` attribute names `algorithm` are used. Files without such a digest are
+/// skipped, returns an error if no file has one.
+fn read_xml(data: &[u8], algorithm: Algorithm) -> Result<Vec<Entry>> {
+    let mut reader = Reader::from_reader(data);
+    reader.config_mut().trim_text(true);
+
+    let mut entries = Vec::new();
+    let (mut path, mut checksum, mut size) = (None, None, None);
+    let mut element = Vec::new();
+    let mut matches = false;
+    let mut files = 0;
+    let mut buf = Vec::new();
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| parse_error(format!("XML at byte {}", reader.error_position()), e))?;
+        match event {
+            Event::Start(start) => {
+                element = start.local_name().as_ref().to_vec();
+                matches = element == b"hashdigest" && digest_type_matches(&start, algorithm);
+            }
+            Event::Text(text) => {
+                let text = text
+                    .unescape()
+                    .map_err(|e| parse_error("XML text", e))?
+                    .into_owned();
+                match element.as_slice() {
+                    b"filename" => path = Some(PathBuf::from(text)),
+                    b"filesize" => size = text.parse().ok(),
+                    b"hashdigest" if matches => checksum = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(end) => {
+                element.clear();
+                if end.local_name().as_ref() == b"fileobject" {
+                    files += 1;
+                    if let (Some(path), Some(checksum)) = (path.take(), checksum.take()) {
+                        let checksum = match algorithm {
+                            Algorithm::Xxh3 if !checksum.starts_with("XXH3_") => {
+                                format!("XXH3_{checksum}")
+                            }
+                            _ => checksum,
+                        };
+                        entries.push(Entry {
+                            path,
+                            checksum,
+                            size,
+                            algorithm: None,
+                        });
+                    }
+                    size = None;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    if files > 0 && entries.is_empty() {
+        return Err(Error::NoChecksums {
+            algorithm: algorithm.tag(),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Returns the `type` attributes of all `hashdigest` elements of a DFXML document, ignoring parse errors.
+fn xml_digest_types(data: &[u8]) -> Vec<String> {
+    let mut reader = Reader::from_reader(data);
+    let mut types = Vec::new();
+    let mut buf = Vec::new();
+    while let Ok(event) = reader.read_event_into(&mut buf) {
+        match event {
+            Event::Start(start) if start.local_name().as_ref() == b"hashdigest" => {
+                types.extend(digest_type(&start));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    types
+}
+
+/// Returns the `type` attribute of a `hashdigest` element.
+fn digest_type(start: &BytesStart) -> Option<String> {
+    start
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.local_name().as_ref() == b"type")
+        .and_then(|attribute| {
+            attribute
+                .unescape_value()
+                .ok()
+                .map(|value| value.into_owned())
+        })
+}
+
+/// Whether the `type` attribute of a `hashdigest` element names `algorithm`.
+fn digest_type_matches(start: &BytesStart, algorithm: Algorithm) -> bool {
+    digest_type(start).is_some_and(|value| algorithm.matches_tag(&value))
+}
+
+/// Writes `entries` as a JSON array of objects with `path`, `checksum` and `size` fields.
+fn write_json(entries: &[Entry]) -> Result<Vec<u8>> {
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            Ok(JsonEntry {
+                path: utf8_name(&entry.path, "JSON")?.to_string(),
+                checksum: Some(entry.checksum.clone()),
+                size: entry.size,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut json = serde_json::to_vec_pretty(&entries).map_err(|e| Error::SerializeFailed {
+        format: "JSON",
+        source: e.into(),
+    })?;
+    json.push(b'\n');
+    Ok(json)
+}
+
+/// Reads the entries of a JSON array such as one written by `write_json` or printed by `--format json`.
+fn read_json(data: &[u8]) -> Result<Vec<Entry>> {
+    let entries: Vec<JsonEntry> =
+        serde_json::from_slice(data).map_err(|e| parse_error("JSON", e))?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| {
+            Some(Entry {
+                path: PathBuf::from(entry.path),
+                checksum: entry.checksum?,
+                size: entry.size,
+                algorithm: None,
+            })
+        })
+        .collect())
+}
+
+/// Writes `entries` as a table with `path`, `checksum` and `size` columns separated by `delimiter`.
+fn write_table(entries: &[Entry], delimiter: u8) -> Vec<u8> {
+    let mut text = table::row(["path", "checksum", "size"], delimiter);
+    text.push(b'\n');
+    for entry in entries {
+        let size = entry.size.map(|size| size.to_string()).unwrap_or_default();
+        text.extend(table::row(
+            [
+                entry.path.as_os_str().as_encoded_bytes(),
+                entry.checksum.as_bytes(),
+                size.as_bytes(),
+            ],
+            delimiter,
+        ));
+        text.push(b'\n');
+    }
+
+    text
+}
+
+/// Reads the entries of a table with a header row, such as one written by `write_table` or printed by `--format csv`.
+///
+/// The `path` and `checksum` columns are required, a `size` column is used if present. Rows without checksum are
+/// skipped.
+fn read_table(data: &[u8], delimiter: u8) -> Result<Vec<Entry>> {
+    let mut reader = ReaderBuilder::new().delimiter(delimiter).from_reader(data);
+    let header = reader
+        .byte_headers()
+        .map_err(|e| parse_error("header row", e))?
+        .clone();
+    let column = |name: &str| header.iter().position(|field| field == name.as_bytes());
+    let (path, checksum) = column("path")
+        .zip(column("checksum"))
+        .ok_or(Error::MissingColumns)?;
+    let size = column("size");
+
+    let mut entries = Vec::new();
+    for row in reader.byte_records() {
+        let row = row.map_err(|e| parse_error("row", e))?;
+        let checksum = row.get(checksum).unwrap_or_default();
+        if checksum.is_empty() {
+            continue;
+        }
+
+        entries.push(Entry {
+            path: path_from_bytes(row.get(path).unwrap_or_default()),
+            checksum: String::from_utf8_lossy(checksum).into_owned(),
+            size: size
+                .and_then(|size| row.get(size))
+                .and_then(|size| str::from_utf8(size).ok()?.parse().ok()),
+            algorithm: None,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Returns `path` as UTF-8, which is required by `format`.
+fn utf8_name<'a>(path: &'a Path, format: &'static str) -> Result<&'a str> {
+    path.to_str().ok_or_else(|| Error::NotUtf8 {
+        path: path.to_path_buf(),
+        format,
+    })
+}
+
+/// Returns the error of failing to parse `what`, caused by `source`.
+fn parse_error(what: impl Into<String>, source: impl Into<BoxError>) -> Error {
+    Error::ParseFailed {
+        what: what.into(),
+        source: source.into(),
+    }
+}
@@ -0,0 +1,220 @@
+//! Metrics of scrubbing in the Prometheus text format, served over HTTP so that monitoring can alert on corruption.
+//!
+//! Counters cover all rounds since the program was started, while gauges describe the last round of each path given
+//! on the command line, e.g. the time since it was last scrubbed.
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{Summary, warn};
+
+/// Time after which clients which do not send a request are disconnected.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Results of the last round of scrubbing a path.
+#[derive(Default)]
+struct Target {
+    /// End of the last round in seconds since the Unix epoch.
+    scrubbed: f64,
+    files: usize,
+    /// Files which did not match, are missing or could not be read.
+    failures: usize,
+}
+
+/// Metrics collected while scrubbing.
+#[derive(Default)]
+struct State {
+    rounds: u64,
+    /// Rounds which ended early because of an error.
+    errors: u64,
+    ok: u64,
+    failed: u64,
+    missing: u64,
+    extra: u64,
+    unreadable: u64,
+    bytes: u64,
+    targets: BTreeMap<String, Target>,
+}
+
+/// Metrics of scrubbing, which can be shared with the thread serving them.
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<State>,
+}
+
+impl Metrics {
+    /// Starts serving the metrics at `http://{address}/metrics` in a background thread. Any error binding the address
+    /// is propagated with added context.
+    pub fn serve(address: &str) -> Result<Arc<Self>> {
+        let listener =
+            TcpListener::bind(address).with_context(|| format!("Failed to listen on {address}"))?;
+        let metrics = Arc::new(Metrics::default());
+        let served = Arc::clone(&metrics);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| served.respond(stream));
+                if let Err(e) = result {
+                    warn(&format!("Failed to serve metrics: {e}"));
+                }
+            }
+        });
+        Ok(metrics)
+    }
+
+    /// Adds the results of a round of scrubbing `target`, or counts the round as failed if `summary` is `None`.
+    pub fn record(&self, target: &str, summary: Option<&Summary>) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("Metrics lock should not be poisoned");
+        state.rounds += 1;
+        let Some(summary) = summary else {
+            state.errors += 1;
+            return;
+        };
+
+        state.ok += summary.ok as u64;
+        state.failed += summary.failed as u64;
+        state.missing += summary.missing as u64;
+        state.extra += summary.extra as u64;
+        state.unreadable += summary.errors as u64;
+        state.bytes += summary.bytes;
+        state.targets.insert(
+            target.to_string(),
+            Target {
+                scrubbed: seconds(SystemTime::now()),
+                files: summary.files,
+                failures: summary.failed + summary.missing + summary.errors,
+            },
+        );
+    }
+
+    /// Answers a single HTTP request on `stream`, with the metrics for `GET /metrics` and an error otherwise.
+    fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+
+        let mut parts = request.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render()),
+            (Some("GET"), _) => ("404 Not Found", "Not found\n".to_string()),
+            _ => ("405 Method Not Allowed", "Method not allowed\n".to_string()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Returns the metrics in the Prometheus text format.
+    fn render(&self) -> String {
+        let state = self
+            .state
+            .lock()
+            .expect("Metrics lock should not be poisoned");
+        let now = seconds(SystemTime::now());
+        let mut out = String::new();
+
+        let statuses = [
+            ("ok", state.ok),
+            ("fail", state.failed),
+            ("missing", state.missing),
+            ("extra", state.extra),
+            ("error", state.unreadable),
+        ];
+        family(
+            &mut out,
+            ("crc32_files_verified_total", "counter"),
+            "Files verified by status.",
+            statuses.map(|(status, count)| (format!("{{status=\"{status}\"}}"), count as f64)),
+        );
+        family(
+            &mut out,
+            ("crc32_bytes_verified_total", "counter"),
+            "Bytes read while verifying.",
+            [(String::new(), state.bytes as f64)],
+        );
+        family(
+            &mut out,
+            ("crc32_scrub_rounds_total", "counter"),
+            "Rounds of scrubbing, counted for each path.",
+            [(String::new(), state.rounds as f64)],
+        );
+        family(
+            &mut out,
+            ("crc32_scrub_errors_total", "counter"),
+            "Rounds of scrubbing which ended early because of an error.",
+            [(String::new(), state.errors as f64)],
+        );
+
+        let targets = || {
+            state
+                .targets
+                .iter()
+                .map(|(path, target)| (format!("{{path=\"{}\"}}", escape(path)), target))
+        };
+        family(
+            &mut out,
+            ("crc32_last_scrub_timestamp_seconds", "gauge"),
+            "End of the last round of scrubbing of a path.",
+            targets().map(|(labels, target)| (labels, target.scrubbed)),
+        );
+        family(
+            &mut out,
+            ("crc32_scrub_age_seconds", "gauge"),
+            "Time since the last round of scrubbing of a path ended.",
+            targets().map(|(labels, target)| (labels, now - target.scrubbed)),
+        );
+        family(
+            &mut out,
+            ("crc32_last_scrub_files", "gauge"),
+            "Files verified in the last round of scrubbing of a path.",
+            targets().map(|(labels, target)| (labels, target.files as f64)),
+        );
+        family(
+            &mut out,
+            ("crc32_last_scrub_failures", "gauge"),
+            "Files which did not match, were missing or could not be read in the last round of scrubbing of a path.",
+            targets().map(|(labels, target)| (labels, target.failures as f64)),
+        );
+        out
+    }
+}
+
+/// Appends a metric family given by its name and type to `out`, with a sample for each pair of labels, formatted
+/// including braces, and value.
+fn family<I>(out: &mut String, (name, kind): (&str, &str), help: &str, samples: I)
+where
+    I: IntoIterator<Item = (String, f64)>,
+{
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{name}{labels} {value}");
+    }
+}
+
+/// Returns `time` in seconds since the Unix epoch.
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Escapes a label value, see the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
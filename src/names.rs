@@ -0,0 +1,113 @@
+//! CRCs embedded in file names, as common in fansub releases, e.g. `episode [1A2B3C4D].mkv`.
+//!
+//! Files can be checked against such CRCs and renamed to include, update or remove them.
+use std::{
+    ffi::{OsStr, OsString},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, Result};
+use clap::ValueEnum;
+
+use crate::walk::path_from_bytes;
+
+/// Number of hex digits of an embedded CRC.
+const CRC_DIGITS: usize = 8;
+
+/// Returns the CRC embedded in the file name of `path` in uppercase, or `None` if there is none.
+///
+/// CRCs are 8 hex digits enclosed in square brackets or parentheses. If there are several, the last one is used, since
+/// release groups put the CRC at the end of the name.
+pub fn embedded_crc(path: &Path) -> Option<String> {
+    let name = path.file_name()?.as_encoded_bytes();
+    let token = crc_token(name)?;
+    Some(String::from_utf8_lossy(&name[token.start + 1..token.end - 1]).to_uppercase())
+}
+
+/// Returns the file name of `path` with its embedded CRC replaced by `crc`, keeping the brackets.
+pub fn replace_crc(path: &Path, crc: &str) -> Option<OsString> {
+    let name = path.file_name()?.as_encoded_bytes();
+    let token = crc_token(name)?;
+    let name = [
+        &name[..=token.start],
+        crc.as_bytes(),
+        &name[token.end - 1..],
+    ]
+    .concat();
+    Some(path_from_bytes(&name).into_os_string())
+}
+
+/// Returns the file name of `path` without its embedded CRC and the space separating it from the rest of the name.
+///
+/// Returns `None` if there is no CRC or nothing would be left of the name.
+pub fn strip_crc(path: &Path) -> Option<OsString> {
+    let name = path.file_name()?.as_encoded_bytes();
+    let mut token = crc_token(name)?;
+    if token.start > 0 && name[token.start - 1].is_ascii_whitespace() {
+        token.start -= 1;
+    } else if token.start == 0 && name.get(token.end).is_some_and(u8::is_ascii_whitespace) {
+        token.end += 1;
+    }
+    let name = [&name[..token.start], &name[token.end..]].concat();
+    (!name.is_empty()).then(|| path_from_bytes(&name).into_os_string())
+}
+
+/// Returns the range of the last CRC embedded in `name`, including its brackets.
+fn crc_token(name: &[u8]) -> Option<Range<usize>> {
+    let len = CRC_DIGITS + 2;
+    (0..=name.len().checked_sub(len)?)
+        .rev()
+        .map(|start| start..start + len)
+        .find(|token| {
+            let token = &name[token.clone()];
+            let enclosed = matches!((token[0], token[len - 1]), (b'[', b']') | (b'(', b')'));
+            enclosed && token[1..len - 1].iter().all(u8::is_ascii_hexdigit)
+        })
+}
+
+/// What to do if the new name of a renamed file is already taken.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Leave the file as it is and report an error
+    #[default]
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Append a number to the new name, e.g. ` (2)`
+    Number,
+}
+
+/// Returns the path `file` is renamed to if its new file name is `name`.
+///
+/// `taken` tells whether a path already exists or is the new name of another file. Returns an error if the new name
+/// is taken and `on_conflict` is `OnConflict::Skip`.
+pub fn rename_target<F>(
+    file: &Path,
+    name: &OsStr,
+    on_conflict: OnConflict,
+    taken: F,
+) -> Result<PathBuf>
+where
+    F: Fn(&Path) -> bool,
+{
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let target = dir.join(name);
+    if !taken(&target) || on_conflict == OnConflict::Overwrite {
+        return Ok(target);
+    }
+    if on_conflict == OnConflict::Skip {
+        return Err(Error::msg(format!("{} already exists", target.display())));
+    }
+
+    let name = Path::new(name);
+    let stem = name.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = name
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| dir.join(format!("{stem} ({n}){ext}")))
+        .find(|target| !taken(target))
+        .ok_or_else(|| Error::msg(format!("No free name for {}", file.display())))
+}
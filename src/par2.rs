@@ -0,0 +1,557 @@
+//! PAR2 recovery data, which allows damaged files to be repaired by tools such as `par2cmdline`.
+//!
+//! Recovery files are written as described in the Parity Volume Set Specification 2.0. When verifying, existing
+//! recovery files are read to tell whether damaged files could be repaired, repairing them is left to other tools.
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::{Context, Error, Result};
+use md5::{Digest, Md5};
+use rayon::prelude::*;
+
+/// Magic sequence starting every packet.
+const MAGIC: &[u8; 8] = b"PAR2\0PKT";
+/// Size of the packet header.
+const HEADER_SIZE: usize = 64;
+/// Number of bytes whose MD5 hash is part of the file ID.
+const HASH_16K: usize = 16 * 1024;
+/// Number of input slices aimed for when choosing the slice size.
+const TARGET_SLICES: u64 = 2000;
+/// Maximum number of input slices, since each needs a distinct constant of the Galois field.
+const MAX_SLICES: u64 = 32768;
+/// Maximum number of bytes of recovery slices computed at once, more are computed in further passes over the files.
+const RECOVERY_MEMORY: u64 = 256 * 1024 * 1024;
+
+const MAIN_TYPE: &[u8; 16] = b"PAR 2.0\0Main\0\0\0\0";
+const FILE_DESC_TYPE: &[u8; 16] = b"PAR 2.0\0FileDesc";
+const IFSC_TYPE: &[u8; 16] = b"PAR 2.0\0IFSC\0\0\0\0";
+const RECOVERY_TYPE: &[u8; 16] = b"PAR 2.0\0RecvSlic";
+const CREATOR_TYPE: &[u8; 16] = b"PAR 2.0\0Creator\0";
+
+/// Logarithm and antilogarithm tables of the Galois field GF(2^16) used by PAR2.
+struct Field {
+    log: Vec<u32>,
+    alog: Vec<u16>,
+}
+
+/// Order of the multiplicative group of the field.
+const FIELD_LIMIT: u32 = 65535;
+
+impl Field {
+    /// Returns the tables of the field with the generator polynomial of PAR2, computing them on first use.
+    fn get() -> &'static Field {
+        static FIELD: OnceLock<Field> = OnceLock::new();
+        FIELD.get_or_init(|| {
+            let mut log = vec![0; 1 << 16];
+            let mut alog = vec![0; 1 << 16];
+            let mut x: u32 = 1;
+            for i in 0..FIELD_LIMIT {
+                alog[i as usize] = x as u16;
+                log[x as usize] = i;
+                x <<= 1;
+                if x & 0x10000 != 0 {
+                    x ^= 0x1100B;
+                }
+            }
+            Field { log, alog }
+        })
+    }
+
+    /// Multiplies `x` by the field element whose logarithm is `factor_log`.
+    fn mul_log(&self, x: u16, factor_log: u32) -> u16 {
+        if x == 0 {
+            return 0;
+        }
+        self.alog[((self.log[x as usize] + factor_log) % FIELD_LIMIT) as usize]
+    }
+}
+
+/// Returns the logarithms of the constants by which the input slices are multiplied, see the specification.
+fn slice_constants(count: usize) -> Vec<u32> {
+    (1..FIELD_LIMIT)
+        .filter(|n| n % 3 != 0 && n % 5 != 0 && n % 17 != 0 && n % 257 != 0)
+        .take(count)
+        .collect()
+}
+
+/// File protected by recovery data.
+struct Source {
+    /// Path of the file on disk.
+    path: PathBuf,
+    /// Name stored in the recovery file, relative to its directory.
+    name: Vec<u8>,
+    length: u64,
+    /// MD5 hash of the first 16 KiB.
+    md5_16k: [u8; 16],
+    id: [u8; 16],
+}
+
+/// Writes recovery data for `files` to the PAR2 file at `path`.
+///
+/// Each file is given by its path on disk and the name stored in the recovery file, which must be relative to the
+/// directory of the recovery file. Empty files are skipped, since there is nothing to recover. The number of recovery
+/// slices is `redundancy` percent of the number of input slices, rounded up. Any error is propagated with added context.
+pub fn create(path: &Path, files: &[(PathBuf, PathBuf)], redundancy: u32) -> Result<()> {
+    let mut sources = Vec::new();
+    for (file, name) in files {
+        let length = fs::metadata(file)
+            .with_context(|| format!("Failed to read metadata of {}", file.display()))?
+            .len();
+        if length == 0 {
+            continue;
+        }
+
+        let name = name
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .into_bytes();
+        let mut head = Vec::new();
+        File::open(file)
+            .and_then(|fp| fp.take(HASH_16K as u64).read_to_end(&mut head))
+            .with_context(|| format!("Failed to read file {}", file.display()))?;
+        let md5_16k = Md5::digest(&head).into();
+        let id = file_id(&md5_16k, length, &name);
+        sources.push(Source {
+            path: file.clone(),
+            name,
+            length,
+            md5_16k,
+            id,
+        });
+    }
+    sources.sort_by(|a, b| compare_ids(&a.id, &b.id));
+    sources.dedup_by(|a, b| a.id == b.id);
+
+    let slice_size = slice_size(sources.iter().map(|source| source.length));
+    let slices: u64 = sources
+        .iter()
+        .map(|source| source.length.div_ceil(slice_size))
+        .sum();
+    let recovery_count = (slices * u64::from(redundancy)).div_ceil(100).max(1);
+    if recovery_count >= u64::from(FIELD_LIMIT) {
+        return Err(Error::msg(
+            "Too many recovery slices, use a lower redundancy",
+        ));
+    }
+
+    let mut main = Vec::new();
+    main.extend(slice_size.to_le_bytes());
+    main.extend((sources.len() as u32).to_le_bytes());
+    for source in &sources {
+        main.extend(source.id);
+    }
+    let set_id: [u8; 16] = Md5::digest(&main).into();
+
+    let mut out = File::create(path)
+        .map(BufWriter::new)
+        .with_context(|| format!("Failed to create file {}", path.display()))?;
+    let written = write_packets(
+        &mut out,
+        &set_id,
+        &main,
+        &sources,
+        slice_size,
+        recovery_count as u32,
+    )
+    .and_then(|()| Ok(out.flush()?))
+    .with_context(|| format!("Failed to write to {}", path.display()));
+    // A partly written recovery file is removed, so that it is not mistaken for a complete one.
+    if written.is_err() {
+        drop(out);
+        let _ = fs::remove_file(path);
+    }
+    written
+}
+
+/// Writes the packets of the recovery set with the main packet `main` for `sources` to `out`, computing
+/// `recovery_count` recovery slices of `slice_size` bytes.
+///
+/// Recovery slices are computed in batches taking up to `RECOVERY_MEMORY` bytes, reading the files once per batch. The
+/// descriptions of the files are computed while reading them for the first batch.
+fn write_packets(
+    out: &mut impl Write,
+    set_id: &[u8; 16],
+    main: &[u8],
+    sources: &[Source],
+    slice_size: u64,
+    recovery_count: u32,
+) -> Result<()> {
+    let field = Field::get();
+    let slices = sources
+        .iter()
+        .map(|source| source.length.div_ceil(slice_size))
+        .sum::<u64>();
+    let constants = slice_constants(slices as usize);
+    let words = (slice_size / 2) as usize;
+    let batch = (RECOVERY_MEMORY / slice_size).clamp(1, u64::from(recovery_count)) as u32;
+    let mut slice = vec![0; slice_size as usize];
+    for first in (0..recovery_count).step_by(batch as usize) {
+        let exponents = first..(first + batch).min(recovery_count);
+        let describe = first == 0;
+        let mut recovery = vec![vec![0u16; words]; exponents.len()];
+        let mut descriptions = Vec::new();
+        let mut checksums = Vec::new();
+        let mut index = 0;
+        for source in sources {
+            let mut fp = File::open(&source.path)
+                .with_context(|| format!("Failed to open file {}", source.path.display()))?;
+            let mut md5 = Md5::new();
+            let mut slice_checksums = Vec::new();
+            let mut remaining = source.length;
+            while remaining > 0 {
+                let n = remaining.min(slice_size) as usize;
+                slice.fill(0);
+                fp.read_exact(&mut slice[..n]).with_context(|| {
+                    format!("Error while reading file {}", source.path.display())
+                })?;
+                if describe {
+                    md5.update(&slice[..n]);
+                    slice_checksums.extend(Md5::digest(&slice));
+                    slice_checksums.extend(crc32fast::hash(&slice).to_le_bytes());
+                }
+
+                let input: Vec<u16> = slice
+                    .chunks_exact(2)
+                    .map(|word| u16::from_le_bytes([word[0], word[1]]))
+                    .collect();
+                let constant = constants[index];
+                recovery
+                    .par_iter_mut()
+                    .zip(exponents.clone())
+                    .for_each(|(output, exponent)| {
+                        let factor_log = (constant * exponent) % FIELD_LIMIT;
+                        let mut low = [0; 256];
+                        let mut high = [0; 256];
+                        for byte in 0..256 {
+                            low[byte] = field.mul_log(byte as u16, factor_log);
+                            high[byte] = field.mul_log((byte as u16) << 8, factor_log);
+                        }
+                        for (out, &word) in output.iter_mut().zip(&input) {
+                            *out ^= low[(word & 0xff) as usize] ^ high[(word >> 8) as usize];
+                        }
+                    });
+                index += 1;
+                remaining -= n as u64;
+            }
+
+            if describe {
+                let mut body = Vec::new();
+                body.extend(source.id);
+                body.extend(md5.finalize());
+                body.extend(source.md5_16k);
+                body.extend(source.length.to_le_bytes());
+                body.extend(&source.name);
+                descriptions.push(body);
+                checksums.push([&source.id[..], &slice_checksums].concat());
+            }
+        }
+
+        if describe {
+            out.write_all(&packet(set_id, MAIN_TYPE, main))?;
+            for (description, checksums) in descriptions.iter().zip(&checksums) {
+                out.write_all(&packet(set_id, FILE_DESC_TYPE, description))?;
+                out.write_all(&packet(set_id, IFSC_TYPE, checksums))?;
+            }
+        }
+        for (exponent, data) in exponents.zip(&recovery) {
+            let mut body = exponent.to_le_bytes().to_vec();
+            body.extend(data.iter().flat_map(|word| word.to_le_bytes()));
+            out.write_all(&packet(set_id, RECOVERY_TYPE, &body))?;
+        }
+    }
+
+    let creator = format!("Created by crc32 v{}", env!("CARGO_PKG_VERSION"));
+    out.write_all(&packet(set_id, CREATOR_TYPE, creator.as_bytes()))?;
+    Ok(())
+}
+
+/// Returns the file ID of a file, which identifies it within the recovery set.
+fn file_id(md5_16k: &[u8; 16], length: u64, name: &[u8]) -> [u8; 16] {
+    let mut hasher = Md5::new();
+    hasher.update(md5_16k);
+    hasher.update(length.to_le_bytes());
+    hasher.update(name);
+    hasher.finalize().into()
+}
+
+/// Orders file IDs as 128-bit little-endian numbers, which is the order of files in the recovery set.
+fn compare_ids(a: &[u8; 16], b: &[u8; 16]) -> Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+/// Returns a slice size giving about `TARGET_SLICES` input slices for files of the given lengths.
+fn slice_size<I>(lengths: I) -> u64
+where
+    I: IntoIterator<Item = u64> + Clone,
+{
+    let total: u64 = lengths.clone().into_iter().sum();
+    let mut size = total.div_ceil(TARGET_SLICES).next_multiple_of(4).max(4);
+    while lengths
+        .clone()
+        .into_iter()
+        .map(|length| length.div_ceil(size))
+        .sum::<u64>()
+        > MAX_SLICES
+    {
+        size *= 2;
+    }
+    size
+}
+
+/// Returns a packet of the given type with `body`, padded to a multiple of 4 bytes.
+fn packet(set_id: &[u8; 16], packet_type: &[u8; 16], body: &[u8]) -> Vec<u8> {
+    let padded = body.len().next_multiple_of(4);
+    let mut hashed = Vec::with_capacity(32 + padded);
+    hashed.extend(set_id);
+    hashed.extend(packet_type);
+    hashed.extend(body);
+    hashed.resize(32 + padded, 0);
+
+    let mut packet = Vec::with_capacity(HEADER_SIZE + padded);
+    packet.extend(MAGIC);
+    packet.extend(((HEADER_SIZE + padded) as u64).to_le_bytes());
+    packet.extend(Md5::digest(&hashed));
+    packet.extend(hashed);
+    packet
+}
+
+/// Returns the recovery files belonging to the checksum file at `path`, i.e. PAR2 files in the same directory whose
+/// names start with the name of the checksum file without extension, e.g. `set.par2` and `set.vol00+10.par2` for
+/// `set.sfv`.
+pub fn find_recovery_files(path: &Path) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let Some(stem) = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+    else {
+        return Vec::new();
+    };
+    let listed = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+    let Ok(entries) = listed else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| dir.join(entry.file_name()))
+        .filter(|file| {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let lower = name.to_ascii_lowercase();
+            lower.ends_with(".par2")
+                && name
+                    .strip_prefix(&stem)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Damage of a file protected by recovery data.
+pub struct Damage {
+    /// Name of the file as stored in the recovery file.
+    pub name: String,
+    /// Number of slices of the file which are damaged or missing.
+    pub damaged: u64,
+    /// Number of slices of the file.
+    pub slices: u64,
+}
+
+/// Whether damaged files can be repaired with the available recovery data.
+pub struct RepairStatus {
+    /// Files with damaged slices.
+    pub damaged: Vec<Damage>,
+    /// Number of recovery slices found.
+    pub recovery: u64,
+}
+
+impl RepairStatus {
+    /// Whether all damaged slices can be recovered.
+    pub fn repairable(&self) -> bool {
+        self.damaged
+            .iter()
+            .map(|damage| damage.damaged)
+            .sum::<u64>()
+            <= self.recovery
+    }
+}
+
+/// Description of a file found in a recovery file.
+struct Description {
+    name: Vec<u8>,
+    length: u64,
+    /// MD5 hash and CRC-32 of each slice.
+    slices: Vec<([u8; 16], u32)>,
+}
+
+/// Reads the recovery files `par2_files` and checks the protected files, which are looked up relative to the
+/// directory of the first recovery file.
+///
+/// Slices are only compared at their original position, so shifted data, which `par2cmdline` might still find, counts as
+/// damaged. Returns an error if no valid recovery set is found.
+pub fn check(par2_files: &[PathBuf]) -> Result<RepairStatus> {
+    let mut set_id = None;
+    let mut slice_size = 0;
+    let mut descriptions: Vec<([u8; 16], Description)> = Vec::new();
+    let mut exponents = Vec::new();
+    for par2_file in par2_files {
+        let data = fs::read(par2_file)
+            .with_context(|| format!("Failed to read file {}", par2_file.display()))?;
+        for (id, packet_type, body) in packets(&data) {
+            if *set_id.get_or_insert(id) != id {
+                continue;
+            }
+
+            match packet_type {
+                MAIN_TYPE if body.len() >= 8 => {
+                    slice_size =
+                        u64::from_le_bytes(body[..8].try_into().expect("Size should have 8 bytes"));
+                }
+                FILE_DESC_TYPE if body.len() >= 56 => {
+                    let file_id: [u8; 16] = body[..16].try_into().expect("ID should have 16 bytes");
+                    let name = body[56..].iter().copied().take_while(|&b| b != 0).collect();
+                    let length = u64::from_le_bytes(
+                        body[48..56].try_into().expect("Length should have 8 bytes"),
+                    );
+                    match descriptions.iter_mut().find(|(id, _)| *id == file_id) {
+                        Some((_, description)) => {
+                            description.name = name;
+                            description.length = length;
+                        }
+                        None => descriptions.push((
+                            file_id,
+                            Description {
+                                name,
+                                length,
+                                slices: Vec::new(),
+                            },
+                        )),
+                    }
+                }
+                IFSC_TYPE if body.len() >= 16 => {
+                    let file_id: [u8; 16] = body[..16].try_into().expect("ID should have 16 bytes");
+                    let slices = body[16..]
+                        .chunks_exact(20)
+                        .map(|entry| {
+                            let md5 = entry[..16].try_into().expect("Hash should have 16 bytes");
+                            let crc = u32::from_le_bytes(
+                                entry[16..].try_into().expect("CRC should have 4 bytes"),
+                            );
+                            (md5, crc)
+                        })
+                        .collect();
+                    match descriptions.iter_mut().find(|(id, _)| *id == file_id) {
+                        Some((_, description)) => description.slices = slices,
+                        None => descriptions.push((
+                            file_id,
+                            Description {
+                                name: Vec::new(),
+                                length: 0,
+                                slices,
+                            },
+                        )),
+                    }
+                }
+                RECOVERY_TYPE if body.len() >= 4 => {
+                    let exponent = u32::from_le_bytes(
+                        body[..4].try_into().expect("Exponent should have 4 bytes"),
+                    );
+                    if !exponents.contains(&exponent) {
+                        exponents.push(exponent);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if set_id.is_none() || slice_size == 0 {
+        return Err(Error::msg("No valid recovery data found"));
+    }
+
+    let dir = par2_files
+        .first()
+        .and_then(|file| file.parent())
+        .unwrap_or(Path::new(""));
+    let damaged = descriptions
+        .par_iter()
+        .filter(|(_, description)| !description.name.is_empty())
+        .filter_map(|(_, description)| {
+            let name = String::from_utf8_lossy(&description.name).into_owned();
+            let slices = description.length.div_ceil(slice_size);
+            let damaged = damaged_slices(&dir.join(&name), description, slice_size);
+            (damaged > 0).then_some(Damage {
+                name,
+                damaged,
+                slices,
+            })
+        })
+        .collect();
+    Ok(RepairStatus {
+        damaged,
+        recovery: exponents.len() as u64,
+    })
+}
+
+/// Returns the number of slices of the file at `path` which do not match `description`.
+fn damaged_slices(path: &Path, description: &Description, slice_size: u64) -> u64 {
+    let slices = description.length.div_ceil(slice_size);
+    let Ok(mut fp) = File::open(path) else {
+        return slices;
+    };
+    let mut slice = vec![0; slice_size as usize];
+    let mut damaged = 0;
+    for i in 0..slices {
+        let n = (description.length - i * slice_size).min(slice_size) as usize;
+        slice.fill(0);
+        let intact = fp.read_exact(&mut slice[..n]).is_ok()
+            && description
+                .slices
+                .get(i as usize)
+                .is_some_and(|(md5, crc)| {
+                    crc32fast::hash(&slice) == *crc && Md5::digest(&slice)[..] == md5[..]
+                });
+        if !intact {
+            damaged += 1;
+        }
+    }
+    damaged
+}
+
+/// Returns the recovery set ID, type and body of each valid packet in `data`.
+fn packets(data: &[u8]) -> Vec<([u8; 16], &[u8; 16], &[u8])> {
+    let mut packets = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = data[pos..].windows(MAGIC.len()).position(|w| w == MAGIC) {
+        let start = pos + offset;
+        let length = data.get(start + 8..start + 16).map(|length| {
+            u64::from_le_bytes(length.try_into().expect("Length should have 8 bytes")) as usize
+        });
+        let packet = length
+            .filter(|&length| length >= HEADER_SIZE && length % 4 == 0)
+            .and_then(|length| data.get(start..start + length));
+        let Some(packet) = packet.filter(|packet| Md5::digest(&packet[32..])[..] == packet[16..32])
+        else {
+            pos = start + 1;
+            continue;
+        };
+
+        let id = packet[32..48].try_into().expect("ID should have 16 bytes");
+        let packet_type = packet[48..64]
+            .try_into()
+            .expect("Type should have 16 bytes");
+        packets.push((id, packet_type, &packet[HEADER_SIZE..]));
+        pos = start + packet.len();
+    }
+    packets
+}
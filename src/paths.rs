@@ -0,0 +1,262 @@
+//! Transformation and lookup of paths stored in checksum files.
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::{self, Component, MAIN_SEPARATOR, Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::walk::path_from_bytes;
+
+/// Unicode normalization form applied to paths.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Normalization {
+    /// Keep paths as they are
+    #[default]
+    None,
+    /// Composed form, as used by Linux and Windows
+    Nfc,
+    /// Decomposed form, as used by macOS
+    Nfd,
+}
+
+impl Normalization {
+    /// Normalizes `name`. Names which are not valid Unicode are returned unchanged.
+    pub fn apply(self, name: &OsStr) -> OsString {
+        match (self, name.to_str()) {
+            (Normalization::Nfc, Some(name)) => name.nfc().collect::<String>().into(),
+            (Normalization::Nfd, Some(name)) => name.nfd().collect::<String>().into(),
+            _ => name.to_os_string(),
+        }
+    }
+
+    /// Normalizes all components of `path`.
+    pub fn apply_path(self, path: &Path) -> PathBuf {
+        match self {
+            Normalization::None => path.to_path_buf(),
+            _ => self.apply(path.as_os_str()).into(),
+        }
+    }
+}
+
+/// Separator between path components in written paths.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Separator {
+    /// Separator of the current platform
+    #[default]
+    Native,
+    /// Forward slash, as used by Unix
+    Slash,
+    /// Backslash, as used by Windows
+    Backslash,
+}
+
+impl Separator {
+    /// Separator character as a byte.
+    pub fn byte(self) -> u8 {
+        match self {
+            Separator::Native => MAIN_SEPARATOR as u8,
+            Separator::Slash => b'/',
+            Separator::Backslash => b'\\',
+        }
+    }
+}
+
+/// Options controlling how paths are written to and looked up from checksum files.
+#[derive(Clone, Default)]
+pub struct PathOptions {
+    /// Unicode normalization form of written paths, also used to match names when looking up files.
+    pub normalization: Normalization,
+    /// Separator between path components of written paths.
+    pub separator: Separator,
+    /// Match names ignoring case when looking up files.
+    pub ignore_case: bool,
+    /// Directory relative paths in a verified checksum file refer to, instead of the directory containing it.
+    pub base_dir: Option<PathBuf>,
+    /// Directory written paths are relative to, instead of the current directory.
+    pub relative_to: Option<PathBuf>,
+    /// Write absolute paths.
+    pub absolute: bool,
+    /// Prefix removed from paths.
+    pub strip_prefix: Option<PathBuf>,
+    /// Prefixes replaced in paths, the first matching one is used.
+    pub mappings: Vec<(PathBuf, PathBuf)>,
+    /// Prefix added to paths.
+    pub add_prefix: Option<PathBuf>,
+}
+
+impl PathOptions {
+    /// Key by which file names are matched when looking up files.
+    ///
+    /// Names are normalized and, if `ignore_case` is specified, converted to lowercase. Names which are not valid
+    /// Unicode are only converted to lowercase for ASCII characters.
+    fn name_key(&self, name: &OsStr) -> OsString {
+        let key = self.normalization.apply(name);
+        if !self.ignore_case {
+            return key;
+        }
+
+        match key.to_str() {
+            Some(key) => key.to_lowercase().into(),
+            None => key.to_ascii_lowercase(),
+        }
+    }
+
+    /// Rewrites the prefix of a path written to or read from a checksum file.
+    ///
+    /// `strip_prefix` is removed first, then the first matching mapping is applied and finally `add_prefix` is added.
+    /// Prefixes are matched by whole components.
+    pub fn rewrite(&self, path: &Path) -> PathBuf {
+        let path = self
+            .strip_prefix
+            .as_ref()
+            .and_then(|prefix| path.strip_prefix(prefix).ok())
+            .unwrap_or(path);
+        let path = self
+            .mappings
+            .iter()
+            .find_map(|(old, new)| path.strip_prefix(old).ok().map(|rest| new.join(rest)))
+            .unwrap_or_else(|| path.to_path_buf());
+        match &self.add_prefix {
+            Some(prefix) => prefix.join(path),
+            None => path,
+        }
+    }
+
+    /// Transforms a path before it is written to a checksum file.
+    pub fn output(&self, path: &Path) -> PathBuf {
+        let path = self.normalization.apply_path(&self.rewrite(path));
+        let separator = self.separator.byte();
+        if separator == MAIN_SEPARATOR as u8 {
+            return path;
+        }
+
+        let bytes: Vec<_> = path
+            .as_os_str()
+            .as_encoded_bytes()
+            .iter()
+            .map(|&b| {
+                if path::is_separator(char::from(b)) {
+                    separator
+                } else {
+                    b
+                }
+            })
+            .collect();
+        path_from_bytes(&bytes)
+    }
+}
+
+/// Locates the file stored as `path` in a checksum file on disk.
+///
+/// Paths written on Windows use backslashes as separators. If `path` does not exist as is, these are converted to the
+/// separator of the current platform, so that files whose names contain backslashes are still found. If the path still
+/// does not exist, each component which cannot be found is looked up in the directory listing of its parent, comparing
+/// names by `options.name_key`. This finds files whose names were recorded in a different normalization form or case,
+/// e.g. a checksum file created on macOS and verified on Linux. If no match is found, `path` is returned unchanged so
+/// that the error is reported when hashing it.
+pub fn resolve(path: &Path, options: &PathOptions) -> PathBuf {
+    if fs::symlink_metadata(path).is_ok() {
+        return path.to_path_buf();
+    }
+
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let path = if !path::is_separator('\\') && bytes.contains(&b'\\') {
+        let bytes: Vec<_> = bytes
+            .iter()
+            .map(|&b| if b == b'\\' { MAIN_SEPARATOR as u8 } else { b })
+            .collect();
+        path_from_bytes(&bytes)
+    } else {
+        path.to_path_buf()
+    };
+
+    let exact = options.normalization == Normalization::None && !options.ignore_case;
+    if exact || fs::symlink_metadata(&path).is_ok() {
+        return path;
+    }
+
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        if let Component::Normal(name) = component
+            && fs::symlink_metadata(resolved.join(name)).is_err()
+            && let Some(found) = find_entry(&resolved, name, options)
+        {
+            resolved.push(found);
+        } else {
+            resolved.push(component);
+        }
+    }
+
+    resolved
+}
+
+/// Finds the name of an entry in `dir` matching `name` by `options.name_key`.
+fn find_entry(dir: &Path, name: &OsStr, options: &PathOptions) -> Option<OsString> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let key = options.name_key(name);
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.file_name())
+        .find(|entry| options.name_key(entry) == key)
+}
+
+/// Returns the absolute path of `path` with `.` and `..` components removed.
+///
+/// Unlike `fs::canonicalize` symbolic links are not resolved, so that links are listed under their own name rather than
+/// their target.
+pub fn absolute_path(path: &Path) -> Result<PathBuf> {
+    let absolute = path::absolute(path)
+        .with_context(|| format!("Failed to get absolute path for {}", path.display()))?;
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    Ok(normalized)
+}
+
+/// Returns `path` relative to `base`, using `..` components for paths outside of `base`.
+///
+/// Both paths must be absolute and normalized, see `absolute_path`. Returns `None` if they have no common root, e.g.
+/// paths on different drives on Windows.
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    if path_components.peek() != base_components.peek() {
+        return None;
+    }
+
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek())
+        && a == b
+    {
+        path_components.next();
+        base_components.next();
+    }
+
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(path_components);
+    Some(relative)
+}
+
+/// Parses a path mapping of the form `OLD=NEW`.
+pub fn parse_mapping(s: &str) -> Result<(PathBuf, PathBuf), String> {
+    let (old, new) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected OLD=NEW, found {s}"))?;
+    Ok((old.into(), new.into()))
+}
@@ -0,0 +1,180 @@
+//! Progress of hashing, reported as events to an observer, so that applications can show their own progress display.
+//!
+//! The progress bars shown by `--progress` are such an observer, see `ProgressBars`. Events are reported from the
+//! threads hashing files, so observers must be `Sync`. Observers can also cancel a mode, e.g. when a Cancel button is
+//! clicked, in which case the results of the files done so far are still printed and returned.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::Record;
+
+/// Event of the progress of a mode.
+pub enum Event<'a> {
+    /// Hashing of the given number of files with the given total size in bytes started.
+    Started { files: u64, bytes: u64 },
+    /// Hashing a file of the given size started. Archives are reported as a single file with their compressed size.
+    FileStarted { path: &'a Path, size: u64 },
+    /// The given number of bytes of a file were hashed.
+    BytesHashed { path: &'a Path, bytes: u64 },
+    /// Hashing a file finished, successfully or not.
+    FileDone { path: &'a Path },
+    /// A file of the given size is done without hashing it, e.g. because its checksum is cached.
+    FileSkipped { path: &'a Path, size: u64 },
+    /// Result of a file, e.g. whether it matches its checksum.
+    FileResult(&'a Record),
+    /// Hashing finished, results may still follow.
+    Finished,
+}
+
+/// Receiver of progress events.
+pub trait Observer: Sync {
+    /// Handles `event`.
+    fn event(&self, event: &Event);
+
+    /// Runs `print`, which prints a result on stdout. Observers drawing on the terminal hide their display meanwhile.
+    fn print(&self, print: &mut dyn FnMut()) {
+        print();
+    }
+
+    /// Whether the mode should stop. This is checked between files and after every chunk of a file, except when
+    /// verifying torrents, so that even a mode hashing large files stops promptly. Files which are not done are left out
+    /// of the results.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Flag cancelling a mode once it is set, without handling any events.
+impl Observer for AtomicBool {
+    fn event(&self, _event: &Event) {}
+
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress display with an overall bar and one bar per file being hashed.
+#[derive(Default)]
+pub struct ProgressBars {
+    display: Mutex<Option<Display>>,
+}
+
+/// Bars shown between the `Started` and `Finished` events.
+struct Display {
+    multi: MultiProgress,
+    total: ProgressBar,
+    files: u64,
+    done: u64,
+    bars: HashMap<PathBuf, ProgressBar>,
+}
+
+impl Display {
+    /// Creates the bars for the given number of files with the given total size.
+    fn new(files: u64, bytes: u64) -> Self {
+        let multi = MultiProgress::new();
+        let total = multi.add(ProgressBar::new(bytes));
+        total.set_style(
+            ProgressStyle::with_template(
+                "[{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} {binary_bytes_per_sec} ETA {eta} {msg}",
+            )
+            .expect("Progress template should be valid"),
+        );
+        total.set_message(format!("0/{files}"));
+
+        Display {
+            multi,
+            total,
+            files,
+            done: 0,
+            bars: HashMap::new(),
+        }
+    }
+
+    /// Adds a bar for a single file of the given size.
+    fn start_file(&mut self, file: &Path, size: u64) {
+        let bar = self
+            .multi
+            .insert_before(&self.total, ProgressBar::new(size));
+        bar.set_style(
+            ProgressStyle::with_template("{wide_msg} {bytes}/{total_bytes} {percent:>3}%")
+                .expect("Progress template should be valid"),
+        );
+        bar.set_message(file.display().to_string());
+        self.bars.insert(file.to_path_buf(), bar);
+    }
+
+    /// Removes the bar of a finished file.
+    fn finish_file(&mut self, file: &Path) {
+        if let Some(bar) = self.bars.remove(file) {
+            bar.finish_and_clear();
+            self.multi.remove(&bar);
+        }
+        self.count_done();
+    }
+
+    /// Updates the file count after a file is done.
+    fn count_done(&mut self) {
+        self.done += 1;
+        self.total
+            .set_message(format!("{}/{}", self.done, self.files));
+    }
+}
+
+impl ProgressBars {
+    /// Creates a progress display, which is shown once hashing starts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Observer for ProgressBars {
+    fn event(&self, event: &Event) {
+        let Ok(mut display) = self.display.lock() else {
+            return;
+        };
+        if let Event::Started { files, bytes } = *event {
+            *display = Some(Display::new(files, bytes));
+            return;
+        }
+        if let Event::Finished = event {
+            if let Some(display) = display.take() {
+                display.total.finish_and_clear();
+            }
+            return;
+        }
+
+        let Some(display) = display.as_mut() else {
+            return;
+        };
+        match *event {
+            Event::FileStarted { path, size } => display.start_file(path, size),
+            Event::BytesHashed { path, bytes } => {
+                if let Some(bar) = display.bars.get(path) {
+                    bar.inc(bytes);
+                }
+                display.total.inc(bytes);
+            }
+            Event::FileDone { path } => display.finish_file(path),
+            Event::FileSkipped { size, .. } => {
+                display.total.inc(size);
+                display.count_done();
+            }
+            Event::Started { .. } | Event::FileResult(_) | Event::Finished => {}
+        }
+    }
+
+    fn print(&self, print: &mut dyn FnMut()) {
+        match self.display.lock().as_deref() {
+            Ok(Some(display)) => display.multi.suspend(print),
+            _ => print(),
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Python module `crc32`, available with the `python` feature, so that Python code can use the same engine.
+//!
+//! The module is built with maturin using `pyproject.toml`, e.g. `maturin develop --release`. It hashes files, parses
+//! SFV style checksum files and verifies checksum files, reporting progress to a Python callable. Files are hashed
+//! without holding the GIL, so other Python threads keep running meanwhile. Errors are raised as `crc32.Error`.
+use std::{path::PathBuf, sync::Mutex};
+
+use clap::ValueEnum;
+use pyo3::{create_exception, exceptions::PyException, prelude::*, types::PyDict};
+
+use crate::{
+    Format, ManifestOptions, OutputOptions, Record, ScanOptions, Summary, VerifyOptions,
+    encoding::Encoding,
+    hash::{self, Algorithm},
+    manifest::{Entry, SfvFile},
+    progress::{Event, Observer},
+    table::DEFAULT_COLUMNS,
+    verify_sfv,
+};
+
+create_exception!(
+    crc32,
+    Error,
+    PyException,
+    "Error hashing a file or reading a checksum file."
+);
+
+/// Raises `e` as `crc32.Error` with its context.
+fn raise<E: Into<anyhow::Error>>(e: E) -> PyErr {
+    Error::new_err(format!("{:#}", e.into()))
+}
+
+/// Converts the name of an algorithm, such as `"sha256"`, to an algorithm.
+fn algorithm(name: &str) -> PyResult<Algorithm> {
+    Algorithm::from_str(name, true).map_err(|_| Error::new_err(format!("Unknown algorithm {name}")))
+}
+
+/// File listed in a checksum file.
+#[pyclass(name = "Entry", module = "crc32", frozen, get_all)]
+struct PyEntry {
+    path: PathBuf,
+    checksum: String,
+    /// Size in bytes, only stored in comments of some SFV files.
+    size: Option<u64>,
+}
+
+impl From<Entry> for PyEntry {
+    fn from(entry: Entry) -> Self {
+        PyEntry {
+            path: entry.path,
+            checksum: entry.checksum,
+            size: entry.size,
+        }
+    }
+}
+
+/// Result of verifying a single file.
+#[pyclass(name = "Record", module = "crc32", frozen, get_all)]
+struct PyRecord {
+    path: String,
+    /// One of `"ok"`, `"fail"`, `"missing"`, `"extra"` and `"error"`.
+    status: &'static str,
+    checksum: Option<String>,
+    expected: Option<String>,
+    size: Option<u64>,
+    error: Option<String>,
+}
+
+impl From<&Record> for PyRecord {
+    fn from(record: &Record) -> Self {
+        PyRecord {
+            path: record.path.clone(),
+            status: record.status.as_str(),
+            checksum: record.checksum.clone(),
+            expected: record.expected.clone(),
+            size: record.size,
+            error: record.error.clone(),
+        }
+    }
+}
+
+/// Results of verifying a checksum file, with the counts of files by status.
+#[pyclass(name = "Summary", module = "crc32", frozen, get_all)]
+struct PySummary {
+    files: usize,
+    ok: usize,
+    failed: usize,
+    missing: usize,
+    extra: usize,
+    errors: usize,
+    /// Total size of the files which were read.
+    bytes: u64,
+    /// Records of all files in the order they are listed.
+    records: Vec<Py<PyRecord>>,
+}
+
+#[pymethods]
+impl PySummary {
+    /// Whether all files were verified successfully.
+    fn is_ok(&self) -> bool {
+        self.ok == self.files
+    }
+}
+
+/// Observer collecting the records of files and passing progress events to a Python callable.
+struct Callback {
+    progress: Option<Py<PyAny>>,
+    records: Mutex<Vec<PyRecord>>,
+    /// Exception raised by `progress`, which cancels verification.
+    error: Mutex<Option<PyErr>>,
+}
+
+impl Callback {
+    /// Calls `progress` with a dict describing `event`, keeping the exception it raises.
+    fn call(&self, progress: &Py<PyAny>, event: &Event) {
+        Python::attach(|py| {
+            let result = event_dict(py, event).and_then(|dict| progress.call1(py, (dict,)));
+            if let Err(e) = result
+                && let Ok(mut error) = self.error.lock()
+            {
+                error.get_or_insert(e);
+            }
+        });
+    }
+}
+
+impl Observer for Callback {
+    fn event(&self, event: &Event) {
+        if let Event::FileResult(record) = event
+            && let Ok(mut records) = self.records.lock()
+        {
+            records.push(PyRecord::from(*record));
+        }
+        if let Some(progress) = &self.progress
+            && !self.is_cancelled()
+        {
+            self.call(progress, event);
+        }
+    }
+
+    fn print(&self, _print: &mut dyn FnMut()) {}
+
+    fn is_cancelled(&self) -> bool {
+        self.error.lock().map_or(true, |error| error.is_some())
+    }
+}
+
+/// Returns a dict describing `event`, with its kind under `"event"`, e.g. `{"event": "bytes_hashed", "path": "a.mkv",
+/// "bytes": 1048576}`.
+fn event_dict<'py>(py: Python<'py>, event: &Event) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    match *event {
+        Event::Started { files, bytes } => {
+            dict.set_item("event", "started")?;
+            dict.set_item("files", files)?;
+            dict.set_item("bytes", bytes)?;
+        }
+        Event::FileStarted { path, size } => {
+            dict.set_item("event", "file_started")?;
+            dict.set_item("path", path)?;
+            dict.set_item("size", size)?;
+        }
+        Event::BytesHashed { path, bytes } => {
+            dict.set_item("event", "bytes_hashed")?;
+            dict.set_item("path", path)?;
+            dict.set_item("bytes", bytes)?;
+        }
+        Event::FileDone { path } => {
+            dict.set_item("event", "file_done")?;
+            dict.set_item("path", path)?;
+        }
+        Event::FileSkipped { path, size } => {
+            dict.set_item("event", "file_skipped")?;
+            dict.set_item("path", path)?;
+            dict.set_item("size", size)?;
+        }
+        Event::FileResult(record) => {
+            dict.set_item("event", "file_result")?;
+            dict.set_item("record", PyRecord::from(record))?;
+        }
+        Event::Finished => dict.set_item("event", "finished")?,
+    }
+    Ok(dict)
+}
+
+/// Computes the checksum of the file at `path` using `algorithm`, returned as a hex string.
+#[pyfunction]
+#[pyo3(signature = (path, algorithm = "crc32"))]
+fn hash_file(py: Python<'_>, path: PathBuf, algorithm: &str) -> PyResult<String> {
+    let algorithm = self::algorithm(algorithm)?;
+    py.detach(|| hash::hash_file(&path, algorithm))
+        .map_err(raise)
+}
+
+/// Parses the contents of an SFV style checksum file, i.e. SFV or the format of `md5sum`, listing checksums of
+/// `algorithm`, and returns its entries in the order they are listed.
+///
+/// Comment lines are skipped, except that sizes stored in them are returned with the entries. Malformed lines are
+/// skipped, unless `strict` is true, in which case `crc32.Error` is raised listing them.
+#[pyfunction]
+#[pyo3(signature = (data, algorithm = "crc32", strict = false))]
+fn parse_sfv(data: &[u8], algorithm: &str, strict: bool) -> PyResult<Vec<PyEntry>> {
+    let sfv = SfvFile::parse(data, self::algorithm(algorithm)?, strict).map_err(raise)?;
+    Ok(sfv.entries.into_iter().map(PyEntry::from).collect())
+}
+
+/// Verifies the checksum file at `manifest`, like `crc32 --verify`, and returns the results.
+///
+/// Listed files are hashed using `algorithm`, or the algorithm detected from the checksum file if it is `None`, with
+/// up to `jobs` files hashed in parallel. Relative paths are resolved against the directory containing the checksum
+/// file. If `progress` is given, it is called with a dict for every progress event, naming its kind under `"event"`,
+/// e.g. `{"event": "bytes_hashed", "path": "a.mkv", "bytes": 1048576}`. An exception raised by it stops verification
+/// and is raised again. Files which do not match, are missing or cannot be read are reported in the results, only
+/// errors reading the checksum file raise `crc32.Error`.
+#[pyfunction]
+#[pyo3(signature = (manifest, algorithm = None, jobs = None, progress = None))]
+fn verify(
+    py: Python<'_>,
+    manifest: PathBuf,
+    algorithm: Option<&str>,
+    jobs: Option<usize>,
+    progress: Option<Py<PyAny>>,
+) -> PyResult<PySummary> {
+    let algorithm = algorithm.map(self::algorithm).transpose()?;
+    let scan_options = ScanOptions {
+        jobs,
+        ..ScanOptions::new().algorithm(algorithm.unwrap_or(Algorithm::Crc32))
+    };
+    let verify_options = VerifyOptions::new().detect_algorithm(algorithm.is_none());
+    let manifest_options = ManifestOptions {
+        header: false,
+        compat: None,
+        out_file: None,
+        db: None,
+        cache: None,
+        par2: None,
+        xattr: false,
+        update: false,
+        encoding: Encoding::Utf8,
+        format: None,
+        strict: false,
+    };
+    let output_options = OutputOptions {
+        format: Format::Text,
+        columns: DEFAULT_COLUMNS.to_vec(),
+        template: None,
+        print0: false,
+        quiet: false,
+        status: true,
+    };
+    let callback = Callback {
+        progress,
+        records: Mutex::new(Vec::new()),
+        error: Mutex::new(None),
+    };
+
+    let summary = py.detach(|| {
+        verify_sfv(
+            &[manifest],
+            &scan_options,
+            &manifest_options,
+            &verify_options,
+            Some(&callback),
+            &output_options,
+        )
+    });
+    if let Some(e) = callback.error.into_inner().ok().flatten() {
+        return Err(e);
+    }
+    let summary = summary.map_err(raise)?;
+    let records = callback
+        .records
+        .into_inner()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|record| Py::new(py, record))
+        .collect::<PyResult<_>>()?;
+    Ok(py_summary(&summary, records))
+}
+
+/// Converts `summary` along with the records of its files.
+fn py_summary(summary: &Summary, records: Vec<Py<PyRecord>>) -> PySummary {
+    PySummary {
+        files: summary.files,
+        ok: summary.ok,
+        failed: summary.failed,
+        missing: summary.missing,
+        extra: summary.extra,
+        errors: summary.errors,
+        bytes: summary.bytes,
+        records,
+    }
+}
+
+/// Hashing and verification of files with CRC32 and other checksums, as done by the `crc32` command.
+#[pymodule(name = "crc32")]
+fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("Error", m.py().get_type::<Error>())?;
+    m.add_class::<PyEntry>()?;
+    m.add_class::<PyRecord>()?;
+    m.add_class::<PySummary>()?;
+    m.add_function(wrap_pyfunction!(hash_file, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_sfv, m)?)?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    Ok(())
+}
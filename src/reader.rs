@@ -0,0 +1,429 @@
+//! Readers passing the contents of files to the hashers in chunks, using the I/O backend selected by `IoBackend`.
+//!
+//! Files are read with `read()` into a buffer by default, which works for any reader. Regular files are read ahead on
+//! another thread into a second buffer meanwhile, so that reading and hashing overlap even when a single file is hashed
+//! at a time. They can instead be hashed straight from a memory map, or read with io_uring on Linux, which keeps several
+//! reads in flight. Files for which a backend is not available are read ahead as well.
+//!
+//! Files opened with `open_file` for direct I/O bypass the page cache. Such reads must go to buffers aligned to the block
+//! size of the device, so all readers read into a `Buffer`.
+#[cfg(target_os = "macos")]
+use std::os::fd::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(target_os = "linux")]
+use std::{cell::RefCell, collections::VecDeque, os::fd::AsRawFd};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read},
+    mem,
+    ops::{Deref, DerefMut},
+    path::Path,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+#[cfg(target_os = "linux")]
+use io_uring::{IoUring, opcode, types};
+use memmap2::Mmap;
+
+use crate::{CHUNK_SIZE, IoBackend};
+
+/// Minimum size of files read ahead or with another backend, smaller files are read with a single `read()` anyway, which
+/// costs less than starting a thread, mapping them or setting up reads in flight.
+const MIN_SIZE: u64 = CHUNK_SIZE as u64;
+
+/// Alignment of buffers, a multiple of the block size of common devices as required for direct I/O.
+const ALIGNMENT: usize = 4096;
+
+/// Number of reads kept in flight per file by io_uring.
+#[cfg(target_os = "linux")]
+const QUEUE_DEPTH: usize = 4;
+
+/// Source of the contents of a file, read in chunks of up to `CHUNK_SIZE` bytes.
+pub trait ChunkReader {
+    /// Returns the next chunk, which is empty once the end of the file is reached.
+    fn next_chunk(&mut self) -> io::Result<&[u8]>;
+}
+
+/// Buffer of `CHUNK_SIZE` bytes starting at an address aligned to `ALIGNMENT`.
+struct Buffer {
+    data: Vec<u8>,
+    start: usize,
+}
+
+impl Buffer {
+    /// Allocates a zeroed buffer.
+    fn new() -> Self {
+        let data = vec![0; CHUNK_SIZE + ALIGNMENT];
+        let start = (ALIGNMENT - data.as_ptr().addr() % ALIGNMENT) % ALIGNMENT;
+        Buffer { data, start }
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[self.start..self.start + CHUNK_SIZE]
+    }
+}
+
+impl DerefMut for Buffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data[self.start..self.start + CHUNK_SIZE]
+    }
+}
+
+/// Reader copying chunks into a buffer with `read()`.
+pub struct Buffered<R> {
+    inner: R,
+    buf: Buffer,
+}
+
+impl<R: Read> Buffered<R> {
+    /// Creates a reader of `inner` with a buffer of `CHUNK_SIZE` bytes.
+    pub fn new(inner: R) -> Self {
+        Buffered {
+            inner,
+            buf: Buffer::new(),
+        }
+    }
+}
+
+impl<R: Read> ChunkReader for Buffered<R> {
+    fn next_chunk(&mut self) -> io::Result<&[u8]> {
+        let n = self.inner.read(&mut self.buf)?;
+        Ok(&self.buf[..n])
+    }
+}
+
+/// Reader reading the next chunk on another thread while the previous one is hashed.
+///
+/// Two buffers are passed back and forth: the thread reads into one of them while the chunk in the other one is hashed,
+/// which hides the latency of hard disks and network shares. The thread stops once the end of the file is reached or
+/// the reader is dropped.
+struct ReadAhead {
+    /// Buffers with the chunks read by the thread, or the error reading them.
+    full: Receiver<io::Result<(Buffer, usize)>>,
+    /// Buffers passed back to the thread to read the next chunks into.
+    empty: Sender<Buffer>,
+    /// Buffer of the chunk returned last, which is passed back once the next chunk is requested.
+    current: Option<Buffer>,
+    done: bool,
+}
+
+impl ReadAhead {
+    /// Starts reading `fp` on another thread, returning `None` if the thread cannot be started.
+    fn new(fp: &File) -> Option<Self> {
+        let mut file = fp.try_clone().ok()?;
+        let (empty, buffers) = mpsc::channel::<Buffer>();
+        let (chunks, full) = mpsc::channel();
+        thread::Builder::new()
+            .name("read-ahead".to_string())
+            .spawn(move || {
+                for mut buf in buffers {
+                    let result = file.read(&mut buf).map(|n| (buf, n));
+                    let end = !matches!(result, Ok((_, n)) if n > 0);
+                    if chunks.send(result).is_err() || end {
+                        break;
+                    }
+                }
+            })
+            .ok()?;
+        for _ in 0..2 {
+            empty.send(Buffer::new()).ok()?;
+        }
+        Some(ReadAhead {
+            full,
+            empty,
+            current: None,
+            done: false,
+        })
+    }
+}
+
+impl ChunkReader for ReadAhead {
+    fn next_chunk(&mut self) -> io::Result<&[u8]> {
+        if self.done {
+            return Ok(&[]);
+        }
+        if let Some(returned) = self.current.take() {
+            // The thread only stops early at the end of the file or after an error, which were already returned.
+            let _ = self.empty.send(returned);
+        }
+
+        let result = self
+            .full
+            .recv()
+            .unwrap_or_else(|_| Err(io::Error::other("Read-ahead thread stopped")));
+        match result {
+            Ok((buf, n)) if n > 0 => Ok(&self.current.insert(buf)[..n]),
+            result => {
+                self.done = true;
+                result.map(|_| &[][..])
+            }
+        }
+    }
+}
+
+/// Reader of a memory mapped file, whose chunks are hashed without copying them.
+struct Mapped {
+    map: Mmap,
+    pos: usize,
+}
+
+impl Mapped {
+    /// Maps `fp` into memory for reading it sequentially, returning `None` if it cannot be mapped.
+    fn new(fp: &File) -> Option<Self> {
+        // SAFETY: The map is only read. If another process truncates the file meanwhile, reading the removed pages
+        // raises SIGBUS, which is why mapping files is opt-in.
+        let map = unsafe { Mmap::map(fp) }.ok()?;
+        #[cfg(unix)]
+        let _ = map.advise(memmap2::Advice::Sequential);
+        Some(Mapped { map, pos: 0 })
+    }
+}
+
+impl ChunkReader for Mapped {
+    fn next_chunk(&mut self) -> io::Result<&[u8]> {
+        let start = self.pos;
+        self.pos = self.map.len().min(start + CHUNK_SIZE);
+        Ok(&self.map[start..self.pos])
+    }
+}
+
+#[cfg(target_os = "linux")]
+thread_local! {
+    /// Ring of the current thread, reused by the files it reads one after another.
+    static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+}
+
+/// Reader keeping up to `QUEUE_DEPTH` reads of consecutive chunks in flight with io_uring.
+///
+/// Each read has its own buffer, identified by its slot. Chunks are returned in the order of their offsets, and the
+/// buffer of a returned chunk is reused by the next call. Reads are only submitted up to the size the file had when it
+/// was opened, plus a single read finding the end of the file, so that a file which grew meanwhile is still read to the
+/// end.
+#[cfg(target_os = "linux")]
+struct Uring {
+    /// Ring of the current thread, which is returned to `RING` on drop.
+    ring: Option<IoUring>,
+    file: File,
+    size: u64,
+    bufs: Vec<Buffer>,
+    /// Results of finished reads, by slot.
+    results: Vec<Option<io::Result<usize>>>,
+    /// Slots with submitted reads and their offsets, in the order of the offsets.
+    pending: VecDeque<(usize, u64)>,
+    free: Vec<usize>,
+    /// Slot of the chunk returned last, which is free once the next chunk is requested.
+    returned: Option<usize>,
+    /// Offset of the next read to submit.
+    offset: u64,
+    done: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl Uring {
+    /// Prepares reading `fp` with the ring of the current thread, returning `None` if io_uring is not available, e.g.
+    /// on older kernels or when it is blocked by a container.
+    fn new(fp: &File, size: u64) -> Option<Self> {
+        let ring = match RING.with_borrow_mut(Option::take) {
+            Some(ring) => ring,
+            None => IoUring::new(QUEUE_DEPTH as u32).ok()?,
+        };
+        Some(Uring {
+            ring: Some(ring),
+            file: fp.try_clone().ok()?,
+            size,
+            bufs: (0..QUEUE_DEPTH).map(|_| Buffer::new()).collect(),
+            results: (0..QUEUE_DEPTH).map(|_| None).collect(),
+            pending: VecDeque::new(),
+            free: (0..QUEUE_DEPTH).rev().collect(),
+            returned: None,
+            offset: 0,
+            done: false,
+        })
+    }
+
+    fn ring(&mut self) -> &mut IoUring {
+        self.ring.as_mut().expect("Ring is only taken on drop")
+    }
+
+    /// Submits reads of the following chunks into the free slots.
+    fn submit(&mut self) -> io::Result<()> {
+        let mut submitted = false;
+        while let Some(&slot) = self.free.last() {
+            if self.offset >= self.size && !self.pending.is_empty() {
+                break;
+            }
+            self.free.pop();
+            let buf = &mut self.bufs[slot];
+            let read = opcode::Read::new(
+                types::Fd(self.file.as_raw_fd()),
+                buf.as_mut_ptr(),
+                buf.len() as u32,
+            )
+            .offset(self.offset)
+            .build()
+            .user_data(slot as u64);
+            // SAFETY: The buffer and the file stay valid until the read completes, since reads in flight are waited
+            // for before they are dropped. The queue has room for a read per slot.
+            unsafe { self.ring().submission().push(&read) }
+                .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+            self.pending.push_back((slot, self.offset));
+            self.offset += CHUNK_SIZE as u64;
+            submitted = true;
+        }
+        if submitted {
+            retry(|| self.ring().submit())?;
+        }
+        Ok(())
+    }
+
+    /// Waits for at least one read to complete and stores the results of all completed reads.
+    fn wait(&mut self) -> io::Result<()> {
+        retry(|| self.ring().submit_and_wait(1))?;
+        let completed: Vec<_> = self
+            .ring()
+            .completion()
+            .map(|cqe| (cqe.user_data() as usize, cqe.result()))
+            .collect();
+        for (slot, result) in completed {
+            self.results[slot] = Some(if result < 0 {
+                Err(io::Error::from_raw_os_error(-result))
+            } else {
+                Ok(result as usize)
+            });
+        }
+        Ok(())
+    }
+
+    /// Waits for all reads in flight and discards their results.
+    fn drain(&mut self) -> io::Result<()> {
+        while self
+            .pending
+            .iter()
+            .any(|&(slot, _)| self.results[slot].is_none())
+        {
+            self.wait()?;
+        }
+        for (slot, _) in mem::take(&mut self.pending) {
+            self.results[slot] = None;
+            self.free.push(slot);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ChunkReader for Uring {
+    fn next_chunk(&mut self) -> io::Result<&[u8]> {
+        if let Some(slot) = self.returned.take() {
+            self.free.push(slot);
+        }
+        if self.done {
+            return Ok(&[]);
+        }
+
+        self.submit()?;
+        let Some(&(slot, offset)) = self.pending.front() else {
+            return Ok(&[]);
+        };
+        while self.results[slot].is_none() {
+            self.wait()?;
+        }
+        self.pending.pop_front();
+        let n = match self.results[slot].take().expect("Read should be complete") {
+            Ok(n) if n > 0 => n,
+            result => {
+                self.free.push(slot);
+                self.done = true;
+                return result.map(|_| &[][..]);
+            }
+        };
+        self.returned = Some(slot);
+        // After a short read the following reads start at the wrong offset, so they are read again.
+        if n < CHUNK_SIZE {
+            self.drain()?;
+            self.offset = offset + n as u64;
+        }
+        Ok(&self.bufs[slot][..n])
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Uring {
+    fn drop(&mut self) {
+        if self.drain().is_ok() {
+            if let Some(ring) = self.ring.take() {
+                RING.with_borrow_mut(|cached| *cached = Some(ring));
+            }
+        } else {
+            // The kernel may still write to the buffers, so they are leaked rather than freed.
+            mem::forget(mem::take(&mut self.bufs));
+            mem::forget(self.ring.take());
+        }
+    }
+}
+
+/// Runs `f` again as long as it is interrupted by a signal, e.g. by Ctrl-C.
+#[cfg(target_os = "linux")]
+fn retry<T>(mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match f() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Opens `file` for reading, bypassing the page cache if `direct` is true.
+///
+/// Direct I/O is used on Linux and disables caching on macOS. On other platforms, and on file systems which do not
+/// support it such as tmpfs, the file is opened as usual. Files opened for direct I/O must only be read into a `Buffer`.
+pub fn open_file(file: &Path, direct: bool) -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    if direct {
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(file)
+        {
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {}
+            result => return result,
+        }
+    }
+
+    let fp = OpenOptions::new().read(true).open(file)?;
+    #[cfg(target_os = "macos")]
+    if direct {
+        // SAFETY: F_NOCACHE only changes how the file descriptor is cached, failing is harmless.
+        unsafe { libc::fcntl(fp.as_raw_fd(), libc::F_NOCACHE, 1) };
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let _ = direct;
+    Ok(fp)
+}
+
+/// Returns a reader of `fp` using `backend`, reading ahead on another thread if the backend is not available. Returns
+/// `None` if `fp` is not a regular file of at least `MIN_SIZE` bytes, e.g. a pipe, in which case it should be read with
+/// `Buffered`. Memory maps always go through the page cache, so they are not used if `direct` is true.
+pub fn open(fp: &File, backend: IoBackend, direct: bool) -> Option<Box<dyn ChunkReader>> {
+    let metadata = fp.metadata().ok()?;
+    if !metadata.is_file() || metadata.len() < MIN_SIZE {
+        return None;
+    }
+
+    let reader: Option<Box<dyn ChunkReader>> = match backend {
+        IoBackend::Read => None,
+        IoBackend::Mmap if direct => None,
+        IoBackend::Mmap => Mapped::new(fp).map(|reader| Box::new(reader) as _),
+        #[cfg(target_os = "linux")]
+        IoBackend::IoUring => Uring::new(fp, metadata.len()).map(|reader| Box::new(reader) as _),
+        #[cfg(not(target_os = "linux"))]
+        IoBackend::IoUring => None,
+    };
+    reader.or_else(|| Some(Box::new(ReadAhead::new(fp)?)))
+}
@@ -0,0 +1,268 @@
+//! Reports of verifications in HTML or Markdown, e.g. to be sent to others or pasted into an issue after auditing an
+//! archive.
+//!
+//! A report shows the counts of files by status, a table of the files which were not verified successfully and the
+//! counts of files below each directory. In HTML reports both tables can be sorted by clicking a column header. Styles
+//! and the script sorting the tables are embedded, so that the report can be opened anywhere as a single file.
+use std::{fmt::Write as _, fs, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use indicatif::BinaryBytes;
+
+use crate::{Summary, now};
+
+/// Styles of the report.
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+.meta { color: #666; margin-top: 0; }
+.result { display: inline-block; padding: 0.3em 0.8em; border-radius: 4px; color: #fff; font-weight: bold; }
+.passed { background: #2e7d32; }
+.failed { background: #c62828; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ddd; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f5f5f5; }
+table.sortable th { cursor: pointer; user-select: none; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+td.mono { font-family: ui-monospace, monospace; }
+tr.bad td { background: #fdecea; }
+.status-fail, .status-error { color: #c62828; font-weight: bold; }
+.status-missing, .status-extra { color: #ef6c00; font-weight: bold; }
+";
+
+/// Script sorting a table by the column whose header is clicked, numerically if all values are numbers.
+const SCRIPT: &str = "
+document.querySelectorAll('table.sortable').forEach(function (table) {
+  table.querySelectorAll('th').forEach(function (th, column) {
+    var ascending = true;
+    th.addEventListener('click', function () {
+      var body = table.tBodies[0];
+      var rows = Array.from(body.rows);
+      var key = function (row) {
+        var cell = row.cells[column];
+        return cell.dataset.sort !== undefined ? cell.dataset.sort : cell.textContent;
+      };
+      var numeric = rows.every(function (row) { return key(row) !== '' && !isNaN(key(row)); });
+      rows.sort(function (a, b) {
+        var x = key(a), y = key(b);
+        var order = numeric ? x - y : x.localeCompare(y);
+        return ascending ? order : -order;
+      });
+      ascending = !ascending;
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});
+";
+
+/// Writes an HTML report of a verification of `paths` with the results in `summary`, which took `elapsed`, to the file
+/// at `path`. Any error is propagated with added context.
+pub fn write_html(
+    path: &Path,
+    paths: &[impl AsRef<Path>],
+    summary: &Summary,
+    elapsed: Duration,
+) -> Result<()> {
+    fs::write(path, render_html(paths, summary, elapsed))
+        .with_context(|| format!("Failed to write report {}", path.display()))
+}
+
+/// Writes a Markdown report of a verification like `write_html`.
+pub fn write_markdown(
+    path: &Path,
+    paths: &[impl AsRef<Path>],
+    summary: &Summary,
+    elapsed: Duration,
+) -> Result<()> {
+    fs::write(path, render_markdown(paths, summary, elapsed))
+        .with_context(|| format!("Failed to write report {}", path.display()))
+}
+
+/// Returns the HTML of a report.
+fn render_html(paths: &[impl AsRef<Path>], summary: &Summary, elapsed: Duration) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Verification report</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>Verification report</h1>\n"
+    );
+    let paths = paths
+        .iter()
+        .map(|path| escape(&path.as_ref().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let _ = writeln!(
+        out,
+        "<p class=\"meta\">{} &middot; {}</p>",
+        escape(&now()),
+        if paths.is_empty() { "." } else { &paths }
+    );
+    let (class, result) = if summary.is_ok() {
+        ("passed", "PASSED")
+    } else {
+        ("failed", "FAILED")
+    };
+    let _ = writeln!(out, "<p><span class=\"result {class}\">{result}</span></p>");
+    let _ = writeln!(out, "<p>{}</p>", escape(&summary.report(elapsed)));
+
+    out.push_str("<h2>Summary</h2>\n<table>\n");
+    for (label, count) in [
+        ("Files", summary.files),
+        ("OK", summary.ok),
+        ("FAIL", summary.failed),
+        ("MISSING", summary.missing),
+        ("EXTRA", summary.extra),
+        ("ERROR", summary.errors),
+    ] {
+        let _ = writeln!(
+            out,
+            "<tr><th>{label}</th><td class=\"number\">{count}</td></tr>"
+        );
+    }
+    let _ = writeln!(
+        out,
+        "<tr><th>Size</th><td class=\"number\">{}</td></tr>\n</table>",
+        BinaryBytes(summary.bytes)
+    );
+
+    let _ = writeln!(out, "<h2>Failures ({})</h2>", summary.failures.len());
+    if summary.failures.is_empty() {
+        out.push_str("<p>All files were verified successfully.</p>\n");
+    } else {
+        out.push_str("<table class=\"sortable\">\n<thead><tr><th>Path</th><th>Status</th><th>Expected</th><th>Actual</th><th>Error</th></tr></thead>\n<tbody>\n");
+        for failure in &summary.failures {
+            let status = failure.status.as_str();
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td class=\"status-{status}\">{}</td><td class=\"mono\">{}</td><td class=\"mono\">{}</td><td>{}</td></tr>",
+                escape(&failure.path),
+                status.to_uppercase(),
+                escape(failure.expected.as_deref().unwrap_or_default()),
+                escape(failure.checksum.as_deref().unwrap_or_default()),
+                escape(failure.error.as_deref().unwrap_or_default())
+            );
+        }
+        out.push_str("</tbody>\n</table>\n");
+    }
+
+    if !summary.directories.is_empty() {
+        out.push_str("<h2>Directories</h2>\n<table class=\"sortable\">\n<thead><tr><th>Directory</th><th>Files</th><th>OK</th><th>FAIL</th><th>MISSING</th><th>EXTRA</th><th>ERROR</th><th>Size</th></tr></thead>\n<tbody>\n");
+        for (dir, counts) in &summary.directories {
+            let class = if counts.ok == counts.files {
+                ""
+            } else {
+                " class=\"bad\""
+            };
+            let _ = write!(out, "<tr{class}><td>{}</td>", escape(dir));
+            for count in [
+                counts.files,
+                counts.ok,
+                counts.failed,
+                counts.missing,
+                counts.extra,
+                counts.errors,
+            ] {
+                let _ = write!(out, "<td class=\"number\">{count}</td>");
+            }
+            let _ = writeln!(
+                out,
+                "<td class=\"number\" data-sort=\"{}\">{}</td></tr>",
+                counts.bytes,
+                BinaryBytes(counts.bytes)
+            );
+        }
+        out.push_str("</tbody>\n</table>\n");
+    }
+
+    let _ = write!(out, "<script>{SCRIPT}</script>\n</body>\n</html>\n");
+    out
+}
+
+/// Returns the Markdown of a report, using tables as rendered by GitHub, GitLab and most wikis.
+fn render_markdown(paths: &[impl AsRef<Path>], summary: &Summary, elapsed: Duration) -> String {
+    let mut out = String::from("# Verification report\n\n");
+    let paths = paths
+        .iter()
+        .map(|path| escape_markdown(&path.as_ref().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let _ = writeln!(
+        out,
+        "{} · {}\n",
+        now(),
+        if paths.is_empty() { "." } else { &paths }
+    );
+    let result = if summary.is_ok() { "PASSED" } else { "FAILED" };
+    let _ = writeln!(out, "**{result}**: {}\n", summary.report(elapsed));
+
+    out.push_str("| Files | OK | FAIL | MISSING | EXTRA | ERROR | Size |\n");
+    out.push_str("|---:|---:|---:|---:|---:|---:|---:|\n");
+    out.push_str(&counts_row(summary));
+
+    let _ = writeln!(out, "\n## Failures ({})\n", summary.failures.len());
+    if summary.failures.is_empty() {
+        out.push_str("All files were verified successfully.\n");
+    } else {
+        out.push_str("| Path | Status | Expected | Actual | Error |\n");
+        out.push_str("|---|---|---|---|---|\n");
+        for failure in &summary.failures {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} | {} |",
+                escape_markdown(&failure.path),
+                failure.status.as_str().to_uppercase(),
+                escape_markdown(failure.expected.as_deref().unwrap_or_default()),
+                escape_markdown(failure.checksum.as_deref().unwrap_or_default()),
+                escape_markdown(failure.error.as_deref().unwrap_or_default())
+            );
+        }
+    }
+
+    if !summary.directories.is_empty() {
+        out.push_str("\n## Directories\n\n");
+        out.push_str("| Directory | Files | OK | FAIL | MISSING | EXTRA | ERROR | Size |\n");
+        out.push_str("|---|---:|---:|---:|---:|---:|---:|---:|\n");
+        for (dir, counts) in &summary.directories {
+            let _ = write!(out, "| {} ", escape_markdown(dir));
+            out.push_str(&counts_row(counts));
+        }
+    }
+    out
+}
+
+/// Returns the cells of a Markdown table row with the counts of files by status and their size.
+fn counts_row(counts: &Summary) -> String {
+    format!(
+        "| {} | {} | {} | {} | {} | {} | {} |\n",
+        counts.files,
+        counts.ok,
+        counts.failed,
+        counts.missing,
+        counts.extra,
+        counts.errors,
+        BinaryBytes(counts.bytes)
+    )
+}
+
+/// Escapes characters with a meaning in Markdown or in table cells, and replaces line breaks with spaces.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' | '~' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\r' | '\n' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes text for use in HTML content and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
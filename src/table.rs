@@ -0,0 +1,123 @@
+//! Tabular output of results as CSV or TSV.
+use clap::ValueEnum;
+use csv::WriterBuilder;
+
+use crate::{Change, Comparison, Record};
+
+/// Columns of tabular output.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    /// Path of the file
+    Path,
+    /// Computed checksum
+    Checksum,
+    /// Expected checksum when verifying
+    Expected,
+    /// Size in bytes
+    Size,
+    /// Modification time
+    Mtime,
+    /// Result of hashing or verifying the file
+    Status,
+    /// Error message
+    Error,
+}
+
+/// Columns used by default.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Path,
+    Column::Checksum,
+    Column::Size,
+    Column::Mtime,
+    Column::Status,
+];
+
+/// Columns of compared paths, which are always used when comparing.
+const COMPARISON_COLUMNS: &[&str] = &["path", "left", "right", "status", "error"];
+
+/// Columns of changed paths, which are always used when comparing checksum files.
+const CHANGE_COLUMNS: &[&str] = &["path", "old_path", "old", "new", "status"];
+
+/// Formats a single row with fields separated by `delimiter`, without line terminator.
+///
+/// Fields are quoted as needed, i.e. if they contain the delimiter, quotes or line breaks.
+pub fn row<I, S>(fields: I, delimiter: u8) -> Vec<u8>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<[u8]>,
+{
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    // Writing to a vector cannot fail.
+    let _ = writer.write_record(fields);
+    let mut row = writer.into_inner().unwrap_or_default();
+    row.pop();
+    row
+}
+
+/// Formats the header row for `columns`.
+pub fn header(columns: &[Column], delimiter: u8) -> Vec<u8> {
+    row(
+        columns
+            .iter()
+            .filter_map(|column| column.to_possible_value())
+            .map(|value| value.get_name().to_string()),
+        delimiter,
+    )
+}
+
+/// Formats the row of a record for `columns`. Missing values are left empty.
+pub fn record_row(record: &Record, columns: &[Column], delimiter: u8) -> Vec<u8> {
+    row(
+        columns.iter().map(|column| match column {
+            Column::Path => record.path.clone(),
+            Column::Checksum => record.checksum.clone().unwrap_or_default(),
+            Column::Expected => record.expected.clone().unwrap_or_default(),
+            Column::Size => record.size.map(|s| s.to_string()).unwrap_or_default(),
+            Column::Mtime => record.modified.clone().unwrap_or_default(),
+            Column::Status => record.status.as_str().to_string(),
+            Column::Error => record.error.clone().unwrap_or_default(),
+        }),
+        delimiter,
+    )
+}
+
+/// Formats the header row of compared paths.
+pub fn comparison_header(delimiter: u8) -> Vec<u8> {
+    row(COMPARISON_COLUMNS, delimiter)
+}
+
+/// Formats the row of a compared path.
+pub(crate) fn comparison_row(comparison: &Comparison, delimiter: u8) -> Vec<u8> {
+    row(
+        [
+            comparison.path.as_str(),
+            comparison.left.as_deref().unwrap_or_default(),
+            comparison.right.as_deref().unwrap_or_default(),
+            comparison.status.as_str(),
+            comparison.error.as_deref().unwrap_or_default(),
+        ],
+        delimiter,
+    )
+}
+
+/// Formats the header row of changed paths.
+pub fn change_header(delimiter: u8) -> Vec<u8> {
+    row(CHANGE_COLUMNS, delimiter)
+}
+
+/// Formats the row of a changed path.
+pub(crate) fn change_row(change: &Change, delimiter: u8) -> Vec<u8> {
+    row(
+        [
+            change.path.as_str(),
+            change.old_path.as_deref().unwrap_or_default(),
+            change.old.as_deref().unwrap_or_default(),
+            change.new.as_deref().unwrap_or_default(),
+            change.status.as_str(),
+        ],
+        delimiter,
+    )
+}
@@ -0,0 +1,49 @@
+//! Results as a stream of the Test Anything Protocol, so that they can be run by TAP harnesses such as `prove`.
+//!
+//! Every file is a test described by its path. Tests of files which were not verified successfully are followed by a
+//! YAML block with the details, as defined by TAP version 13.
+use std::fmt::Write as _;
+
+use crate::{Record, Status};
+
+/// Returns a TAP stream of `records`. Missing files are reported as skipped tests if `ignore_missing` is set.
+pub fn report(records: &[Record], ignore_missing: bool) -> String {
+    let mut tap = format!("TAP version 13\n1..{}\n", records.len());
+    for (number, record) in records.iter().enumerate() {
+        let number = number + 1;
+        let description = escape(&record.path);
+        let Some(message) = record.problem() else {
+            let _ = writeln!(tap, "ok {number} - {description}");
+            continue;
+        };
+        if record.status == Status::Missing && ignore_missing {
+            let _ = writeln!(tap, "ok {number} - {description} # SKIP {message}");
+            continue;
+        }
+
+        let _ = writeln!(tap, "not ok {number} - {description}");
+        tap.push_str("  ---\n");
+        let _ = writeln!(tap, "  message: {}", quote(&message));
+        let _ = writeln!(tap, "  status: {}", record.status.as_str());
+        for (key, value) in [("expected", &record.expected), ("got", &record.checksum)] {
+            if let Some(value) = value {
+                let _ = writeln!(tap, "  {key}: {}", quote(value));
+            }
+        }
+        tap.push_str("  ...\n");
+    }
+    tap
+}
+
+/// Escapes characters with a meaning in test descriptions, i.e. `#` starting a directive and line breaks.
+fn escape(description: &str) -> String {
+    description
+        .replace('\\', "\\\\")
+        .replace('#', "\\#")
+        .replace('\n', "\\n")
+}
+
+/// Quotes a YAML scalar, using JSON string syntax which is valid YAML.
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
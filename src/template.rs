@@ -0,0 +1,152 @@
+//! User-defined formats of result lines.
+//!
+//! Templates are text with placeholders in braces such as `{crc}  {path}`, which are replaced by fields of each result.
+//! Literal braces are written as `{{` and `}}`.
+use std::{mem, path::Path, str::FromStr};
+
+use crate::{Record, hash::Algorithm};
+
+/// Template of a result line.
+#[derive(Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+/// Part of a template.
+#[derive(Clone)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+/// Fields which can be used as placeholders.
+#[derive(Clone, Copy)]
+enum Field {
+    /// Checksum as written to checksum files, `{checksum}`.
+    Checksum,
+    /// Checksum in lowercase, `{crc}`.
+    Lower,
+    /// Checksum in uppercase, `{CRC}`.
+    Upper,
+    /// Expected checksum when verifying, `{expected}`.
+    Expected,
+    /// Path of the file, `{path}`.
+    Path,
+    /// File name without extension, `{name}`.
+    Name,
+    /// Extension of the file name including the dot, or nothing if there is none, `{ext}`.
+    Ext,
+    /// Size in bytes, `{size}`.
+    Size,
+    /// Modification time in RFC 3339 format, `{mtime}`.
+    Mtime,
+    /// Name of the algorithm, `{algorithm}`.
+    Algorithm,
+    /// Result of hashing or verifying the file, `{status}`.
+    Status,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checksum" => Ok(Field::Checksum),
+            "crc" => Ok(Field::Lower),
+            "CRC" => Ok(Field::Upper),
+            "expected" => Ok(Field::Expected),
+            "path" => Ok(Field::Path),
+            "name" => Ok(Field::Name),
+            "ext" => Ok(Field::Ext),
+            "size" => Ok(Field::Size),
+            "mtime" => Ok(Field::Mtime),
+            "algorithm" => Ok(Field::Algorithm),
+            "status" => Ok(Field::Status),
+            _ => Err(format!(
+                "unknown placeholder {{{s}}}, expected one of checksum, crc, CRC, expected, path, name, ext, size, mtime, \
+                 algorithm or status"
+            )),
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some(i) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..i]);
+            let brace = &rest[i..=i];
+            let after = &rest[i + 1..];
+            if let Some(after) = after.strip_prefix(brace) {
+                literal.push_str(brace);
+                rest = after;
+                continue;
+            }
+            if brace == "}" {
+                return Err("unmatched } in template, use }} for a literal brace".to_string());
+            }
+
+            let end = after
+                .find('}')
+                .ok_or_else(|| "unterminated placeholder in template".to_string())?;
+            if !literal.is_empty() {
+                parts.push(Part::Literal(mem::take(&mut literal)));
+            }
+            parts.push(Part::Field(after[..end].parse()?));
+            rest = &after[end + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template { parts })
+    }
+}
+
+impl Template {
+    /// Renders the line of a result computed with `algorithm`. Missing values are left empty.
+    pub fn render(&self, record: &Record, algorithm: Algorithm) -> Vec<u8> {
+        let checksum = record.checksum.as_deref().unwrap_or_default();
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => line.push_str(text),
+                Part::Field(Field::Checksum) => line.push_str(checksum),
+                Part::Field(Field::Lower) => line.push_str(&checksum.to_lowercase()),
+                Part::Field(Field::Upper) => line.push_str(&checksum.to_uppercase()),
+                Part::Field(Field::Expected) => {
+                    line.push_str(record.expected.as_deref().unwrap_or_default())
+                }
+                Part::Field(Field::Path) => line.push_str(&record.path),
+                Part::Field(Field::Name) => {
+                    if let Some(name) = Path::new(&record.path).file_stem() {
+                        line.push_str(&name.to_string_lossy());
+                    }
+                }
+                Part::Field(Field::Ext) => {
+                    if let Some(ext) = Path::new(&record.path).extension() {
+                        line.push('.');
+                        line.push_str(&ext.to_string_lossy());
+                    }
+                }
+                Part::Field(Field::Size) => {
+                    if let Some(size) = record.size {
+                        line.push_str(&size.to_string());
+                    }
+                }
+                Part::Field(Field::Mtime) => {
+                    line.push_str(record.modified.as_deref().unwrap_or_default())
+                }
+                Part::Field(Field::Algorithm) => line.push_str(&algorithm.tag()),
+                Part::Field(Field::Status) => line.push_str(record.status.as_str()),
+            }
+        }
+
+        line.into_bytes()
+    }
+}
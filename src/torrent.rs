@@ -0,0 +1,556 @@
+//! Torrent files, whose piece hashes are used to verify downloaded files without a torrent client.
+//!
+//! Both BitTorrent v1 torrents, which store SHA-1 hashes of pieces spanning all files, and v2 torrents, which store
+//! SHA-256 merkle trees per file as described in BEP 52, are supported.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{Context, Error, Result};
+use rayon::prelude::*;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::walk::path_from_bytes;
+
+/// Size of the blocks hashed into the merkle trees of v2 torrents.
+const BLOCK_SIZE: u64 = 16 * 1024;
+
+/// Value of the bencode format torrent files are written in.
+enum Value {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(BTreeMap<Vec<u8>, Value>),
+}
+
+impl Value {
+    /// Returns the value stored under `key` if this is a dictionary.
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Dict(dict) => dict.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn as_dict(&self) -> Option<&BTreeMap<Vec<u8>, Value>> {
+        match self {
+            Value::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+}
+
+/// Parser of bencoded data.
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    /// Parses the value starting at the current position.
+    fn value(&mut self) -> Result<Value> {
+        match self.data.get(self.pos) {
+            Some(b'i') => {
+                self.pos += 1;
+                let n = self.until(b'e')?;
+                let n = std::str::from_utf8(n)
+                    .ok()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| self.error())?;
+                Ok(Value::Int(n))
+            }
+            Some(b'l') => {
+                self.pos += 1;
+                let mut list = Vec::new();
+                while !self.end()? {
+                    list.push(self.value()?);
+                }
+                Ok(Value::List(list))
+            }
+            Some(b'd') => {
+                self.pos += 1;
+                let mut dict = BTreeMap::new();
+                while !self.end()? {
+                    let key = self.bytes()?;
+                    dict.insert(key, self.value()?);
+                }
+                Ok(Value::Dict(dict))
+            }
+            Some(b'0'..=b'9') => Ok(Value::Bytes(self.bytes()?)),
+            _ => Err(self.error()),
+        }
+    }
+
+    /// Parses a byte string starting at the current position.
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let len = self.until(b':')?;
+        let len: usize = std::str::from_utf8(len)
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| self.error())?;
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| self.error())?;
+        self.pos += len;
+        Ok(bytes.to_vec())
+    }
+
+    /// Returns the bytes up to the next `delimiter` and moves past it.
+    fn until(&mut self, delimiter: u8) -> Result<&[u8]> {
+        let start = self.pos;
+        let len = self.data[start..]
+            .iter()
+            .position(|&b| b == delimiter)
+            .ok_or_else(|| self.error())?;
+        self.pos += len + 1;
+        Ok(&self.data[start..start + len])
+    }
+
+    /// Whether the current list or dictionary ends at the current position, in which case it moves past the end.
+    fn end(&mut self) -> Result<bool> {
+        match self.data.get(self.pos) {
+            Some(b'e') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(_) => Ok(false),
+            None => Err(self.error()),
+        }
+    }
+
+    fn error(&self) -> Error {
+        Error::msg(format!("Invalid bencode at byte {}", self.pos))
+    }
+}
+
+/// File listed in a torrent.
+pub struct TorrentFile {
+    /// Path of the file relative to the directory the torrent is downloaded to.
+    pub path: PathBuf,
+    /// Size in bytes.
+    pub length: u64,
+    /// Whether this is a padding file of a hybrid torrent, which only consists of zeros and is not stored on disk.
+    pub padding: bool,
+    /// Root of the merkle tree of the file in v2 torrents.
+    pieces_root: Option<[u8; 32]>,
+}
+
+/// Contents of a torrent file needed to verify the downloaded files.
+pub struct Torrent {
+    pub files: Vec<TorrentFile>,
+    piece_length: u64,
+    /// SHA-1 hashes of the pieces of v1 torrents, empty for v2 only torrents.
+    pieces: Vec<[u8; 20]>,
+    /// SHA-256 hashes of the pieces of each file in v2 torrents by the root of its merkle tree.
+    piece_layers: HashMap<[u8; 32], Vec<[u8; 32]>>,
+}
+
+impl Torrent {
+    /// Parses the contents of a torrent file.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut parser = Parser { data, pos: 0 };
+        let root = parser.value()?;
+        let info = root
+            .get("info")
+            .ok_or_else(|| Error::msg("Missing info dictionary"))?;
+        let name = info
+            .get("name")
+            .and_then(Value::as_bytes)
+            .ok_or_else(|| Error::msg("Missing name"))?;
+        let name = safe_path(&[name])?;
+        let piece_length = info
+            .get("piece length")
+            .and_then(Value::as_int)
+            .and_then(|n| u64::try_from(n).ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(|| Error::msg("Missing piece length"))?;
+
+        if let Some(pieces) = info.get("pieces").and_then(Value::as_bytes) {
+            let files = match info.get("files").and_then(Value::as_list) {
+                Some(files) => files
+                    .iter()
+                    .map(|file| v1_file(file, &name))
+                    .collect::<Result<_>>()?,
+                None => vec![TorrentFile {
+                    path: name,
+                    length: length(info)?,
+                    padding: false,
+                    pieces_root: None,
+                }],
+            };
+            let total: u64 = files.iter().map(|file: &TorrentFile| file.length).sum();
+            if pieces.len() % 20 != 0 || (pieces.len() / 20) as u64 != total.div_ceil(piece_length)
+            {
+                return Err(Error::msg(
+                    "Number of pieces does not match the size of the files",
+                ));
+            }
+            let pieces = pieces
+                .chunks_exact(20)
+                .map(|hash| hash.try_into().expect("Chunks should have 20 bytes"))
+                .collect();
+            return Ok(Torrent {
+                files,
+                piece_length,
+                pieces,
+                piece_layers: HashMap::new(),
+            });
+        }
+
+        let tree = info
+            .get("file tree")
+            .ok_or_else(|| Error::msg("Missing pieces or file tree"))?;
+        if !piece_length.is_power_of_two() || piece_length < BLOCK_SIZE {
+            return Err(Error::msg("Invalid piece length"));
+        }
+        let mut files = Vec::new();
+        v2_files(tree, &name, &mut files)?;
+        let mut piece_layers = HashMap::new();
+        for (root, layer) in root
+            .get("piece layers")
+            .and_then(Value::as_dict)
+            .into_iter()
+            .flatten()
+        {
+            let (Ok(root), Some(layer)) = (root[..].try_into(), layer.as_bytes()) else {
+                return Err(Error::msg("Invalid piece layers"));
+            };
+            let layer = layer
+                .chunks_exact(32)
+                .map(|hash| hash.try_into().expect("Chunks should have 32 bytes"))
+                .collect();
+            piece_layers.insert(root, layer);
+        }
+        Ok(Torrent {
+            files,
+            piece_length,
+            pieces: Vec::new(),
+            piece_layers,
+        })
+    }
+
+    /// Checks the listed files below `base` against the piece hashes.
+    ///
+    /// Returns the byte ranges of each file whose pieces do not match in the order listed, or the error opening it,
+    /// e.g. if it does not exist. Padding files are not read and always reported as intact. `on_read` is called with
+    /// the number of bytes read. `cancelled` is called before each piece, once it returns true the remaining pieces are
+    /// reported as not matching without being read.
+    pub fn check<F, C>(&self, base: &Path, on_read: F, cancelled: C) -> Vec<Result<Vec<Range<u64>>>>
+    where
+        F: Fn(u64) + Sync,
+        C: Fn() -> bool + Sync,
+    {
+        if self.pieces.is_empty() {
+            self.files
+                .par_iter()
+                .map(|file| self.check_v2(file, base, &on_read, &cancelled))
+                .collect()
+        } else {
+            self.check_v1(base, &on_read, &cancelled)
+        }
+    }
+
+    /// Checks the files of a v1 torrent, whose pieces span file boundaries.
+    fn check_v1<F, C>(
+        &self,
+        base: &Path,
+        on_read: &F,
+        cancelled: &C,
+    ) -> Vec<Result<Vec<Range<u64>>>>
+    where
+        F: Fn(u64) + Sync,
+        C: Fn() -> bool + Sync,
+    {
+        let mut offsets = Vec::with_capacity(self.files.len());
+        let mut total = 0;
+        for file in &self.files {
+            offsets.push(total);
+            total += file.length;
+        }
+        let opened: Vec<_> = self
+            .files
+            .iter()
+            .map(|file| match file.padding {
+                true => Ok(()),
+                false => open(&base.join(&file.path)).map(drop),
+            })
+            .collect();
+
+        let good: Vec<bool> = (0..self.pieces.len())
+            .into_par_iter()
+            .map(|i| {
+                if cancelled() {
+                    return false;
+                }
+                let start = i as u64 * self.piece_length;
+                let end = (start + self.piece_length).min(total);
+                let mut hasher = Sha1::new();
+                for (j, file) in self.files.iter().enumerate() {
+                    let (file_start, file_end) = (offsets[j], offsets[j] + file.length);
+                    if file_end <= start || file_start >= end {
+                        continue;
+                    }
+
+                    let from = start.max(file_start) - file_start;
+                    let len = end.min(file_end) - file_start - from;
+                    let mut data = vec![0; len as usize];
+                    if !file.padding {
+                        if opened[j].is_err() {
+                            return false;
+                        }
+                        let read = open(&base.join(&file.path)).and_then(|mut fp| {
+                            fp.seek(SeekFrom::Start(from))?;
+                            fp.read_exact(&mut data)?;
+                            Ok(())
+                        });
+                        if read.is_err() {
+                            return false;
+                        }
+                    }
+                    on_read(len);
+                    hasher.update(&data);
+                }
+                hasher.finalize()[..] == self.pieces[i]
+            })
+            .collect();
+
+        self.files
+            .iter()
+            .zip(offsets)
+            .zip(opened)
+            .map(|((file, offset), opened)| {
+                opened?;
+                let pieces = (offset / self.piece_length)
+                    ..(offset + file.length).div_ceil(self.piece_length);
+                let bad = pieces.filter(|&i| !good[i as usize]).map(|i| {
+                    let start = (i * self.piece_length).max(offset) - offset;
+                    let end = ((i + 1) * self.piece_length).min(offset + file.length) - offset;
+                    start..end
+                });
+                Ok(merge(bad))
+            })
+            .collect()
+    }
+
+    /// Checks a file of a v2 torrent against the hashes of its pieces, or the root of its merkle tree if it is not
+    /// larger than a piece.
+    fn check_v2<F, C>(
+        &self,
+        file: &TorrentFile,
+        base: &Path,
+        on_read: &F,
+        cancelled: &C,
+    ) -> Result<Vec<Range<u64>>>
+    where
+        F: Fn(u64) + Sync,
+        C: Fn() -> bool + Sync,
+    {
+        let Some(root) = file
+            .pieces_root
+            .filter(|_| !file.padding && file.length > 0)
+        else {
+            return Ok(Vec::new());
+        };
+        let path = base.join(&file.path);
+        let mut fp = open(&path)?;
+        let blocks_per_piece = (self.piece_length / BLOCK_SIZE) as usize;
+        if file.length <= self.piece_length {
+            let intact = !cancelled()
+                && read_leaves(&mut fp, file.length, on_read).is_ok_and(|leaves| {
+                    let width = leaves.len().next_power_of_two();
+                    merkle_root(leaves, width) == root
+                });
+            let bad = (!intact).then_some(0..file.length);
+            return Ok(bad.into_iter().collect());
+        }
+
+        let layer = self
+            .piece_layers
+            .get(&root)
+            .ok_or_else(|| Error::msg(format!("Missing piece layer of {}", file.path.display())))?;
+        let pieces = file.length.div_ceil(self.piece_length);
+        let mut bad = Vec::new();
+        for i in 0..pieces {
+            let start = i * self.piece_length;
+            let end = (start + self.piece_length).min(file.length);
+            if cancelled() {
+                bad.push(start..file.length);
+                break;
+            }
+            let leaves = read_leaves(&mut fp, end - start, on_read);
+            let intact = leaves.is_ok_and(|leaves| {
+                layer.get(i as usize) == Some(&merkle_root(leaves, blocks_per_piece))
+            });
+            if !intact {
+                bad.push(start..end);
+            }
+        }
+        Ok(merge(bad))
+    }
+}
+
+/// Returns the file of a v1 multi-file torrent, listed below the directory `name`.
+fn v1_file(file: &Value, name: &Path) -> Result<TorrentFile> {
+    let components: Vec<_> = file
+        .get("path")
+        .and_then(Value::as_list)
+        .ok_or_else(|| Error::msg("Missing path of file"))?
+        .iter()
+        .map(|component| {
+            component
+                .as_bytes()
+                .ok_or_else(|| Error::msg("Invalid path of file"))
+        })
+        .collect::<Result<_>>()?;
+    let padding = file
+        .get("attr")
+        .and_then(Value::as_bytes)
+        .is_some_and(|attr| attr.contains(&b'p'));
+    Ok(TorrentFile {
+        path: name.join(safe_path(&components)?),
+        length: length(file)?,
+        padding,
+        pieces_root: None,
+    })
+}
+
+/// Collects the files of the file tree `tree` of a v2 torrent, which is stored below `dir`.
+fn v2_files(tree: &Value, dir: &Path, files: &mut Vec<TorrentFile>) -> Result<()> {
+    let tree = tree
+        .as_dict()
+        .ok_or_else(|| Error::msg("Invalid file tree"))?;
+    for (name, node) in tree {
+        // Files are dictionaries with an empty key, directories map names to further nodes.
+        if name.is_empty() {
+            let pieces_root = node
+                .get("pieces root")
+                .and_then(Value::as_bytes)
+                .and_then(|root| root.try_into().ok());
+            files.push(TorrentFile {
+                path: dir.to_path_buf(),
+                length: length(node)?,
+                padding: false,
+                pieces_root,
+            });
+        } else {
+            v2_files(node, &dir.join(safe_path(&[name])?), files)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the length of a file listed in a torrent.
+fn length(file: &Value) -> Result<u64> {
+    file.get("length")
+        .and_then(Value::as_int)
+        .and_then(|n| u64::try_from(n).ok())
+        .ok_or_else(|| Error::msg("Missing length of file"))
+}
+
+/// Joins the components of a path listed in a torrent, refusing paths which would point outside of the download
+/// directory.
+fn safe_path(components: &[&[u8]]) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    for &component in components {
+        let component = path_from_bytes(component);
+        let mut parts = component.components();
+        match (parts.next(), parts.next()) {
+            (Some(Component::Normal(name)), None) => path.push(name),
+            _ => {
+                return Err(Error::msg(format!(
+                    "Invalid path component {}",
+                    component.display()
+                )));
+            }
+        }
+    }
+
+    Ok(path)
+}
+
+/// Opens a file listed in a torrent.
+fn open(path: &Path) -> Result<File> {
+    File::open(path).with_context(|| format!("Failed to open file {}", path.display()))
+}
+
+/// Reads `len` bytes of `reader` and returns the SHA-256 hashes of their blocks of `BLOCK_SIZE`.
+fn read_leaves<R, F>(reader: &mut R, len: u64, on_read: &F) -> io::Result<Vec<[u8; 32]>>
+where
+    R: Read,
+    F: Fn(u64),
+{
+    let mut leaves = Vec::new();
+    let mut block = vec![0; BLOCK_SIZE as usize];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(BLOCK_SIZE);
+        reader.read_exact(&mut block[..n as usize])?;
+        on_read(n);
+        leaves.push(Sha256::digest(&block[..n as usize]).into());
+        remaining -= n;
+    }
+
+    Ok(leaves)
+}
+
+/// Returns the root of the merkle tree over `leaves`, padded with zero hashes to `width` leaves.
+fn merkle_root(mut layer: Vec<[u8; 32]>, width: usize) -> [u8; 32] {
+    layer.resize(width.max(1), [0; 32]);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair[1]);
+                hasher.finalize().into()
+            })
+            .collect();
+    }
+
+    layer[0]
+}
+
+/// Merges adjacent byte ranges.
+fn merge<I>(ranges: I) -> Vec<Range<u64>>
+where
+    I: IntoIterator<Item = Range<u64>>,
+{
+    let mut merged: Vec<Range<u64>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
@@ -0,0 +1,479 @@
+//! Collection of files to hash from paths given on the command line.
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Error, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use glob::Pattern;
+use ignore::WalkBuilder;
+
+use crate::{STDIN, hash::Algorithm};
+
+/// Names of version control metadata directories skipped when honoring ignore files.
+const VCS_DIRS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// Whether hidden files are included by default.
+///
+/// On macOS hidden files are mostly Finder metadata such as `.DS_Store` and `._*` files, so they are skipped.
+pub const HIDDEN_DEFAULT: bool = !cfg!(target_os = "macos");
+
+/// How symbolic links are handled when searching directories.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Links to files are collected, links to directories are not followed.
+    #[default]
+    Files,
+    /// Links to files and directories are followed.
+    Follow,
+    /// Links are skipped.
+    Skip,
+    /// Links are collected themselves, so that their target path can be hashed.
+    Target,
+}
+
+/// Options controlling which files are collected from directories.
+#[derive(Clone)]
+pub struct WalkOptions {
+    /// Search subdirectories as well.
+    pub recursive: bool,
+    /// If not empty, only files matching one of these patterns are collected.
+    pub include: Vec<Pattern>,
+    /// Files and directories matching any of these patterns are skipped.
+    pub exclude: Vec<Pattern>,
+    /// Directories with any of these names are skipped.
+    pub exclude_dirs: Vec<OsString>,
+    /// Skip files ignored by `.gitignore` and `.ignore` files.
+    pub ignore_vcs: bool,
+    /// How symbolic links are handled.
+    pub symlinks: SymlinkPolicy,
+    /// Do not descend into directories on other file systems.
+    pub one_file_system: bool,
+    /// Skip files smaller than this many bytes.
+    pub min_size: Option<u64>,
+    /// Skip files larger than this many bytes.
+    pub max_size: Option<u64>,
+    /// Skip files not modified after this time.
+    pub newer_than: Option<SystemTime>,
+    /// Skip files not modified before this time.
+    pub older_than: Option<SystemTime>,
+    /// Include hidden files and directories.
+    pub hidden: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions {
+            recursive: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            exclude_dirs: Vec::new(),
+            ignore_vcs: false,
+            symlinks: SymlinkPolicy::default(),
+            one_file_system: false,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            hidden: HIDDEN_DEFAULT,
+        }
+    }
+}
+
+impl WalkOptions {
+    /// Whether a file at `relative` path below the directory being searched passes the include and exclude filters.
+    fn matches(&self, relative: &Path) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(relative)))
+            && !self.is_excluded(relative)
+    }
+
+    /// Whether the file at `path` passes the size and modification time filters.
+    ///
+    /// Files whose metadata cannot be read are kept, so that the error is reported when hashing them.
+    fn matches_metadata(&self, path: &Path) -> bool {
+        if self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.newer_than.is_none()
+            && self.older_than.is_none()
+        {
+            return true;
+        }
+
+        let Ok(metadata) = fs::metadata(path) else {
+            return true;
+        };
+        let size = metadata.len();
+        let modified = metadata.modified().ok();
+        self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+            && self
+                .newer_than
+                .is_none_or(|time| modified.is_none_or(|modified| modified > time))
+            && self
+                .older_than
+                .is_none_or(|time| modified.is_none_or(|modified| modified < time))
+    }
+
+    /// Whether `relative` matches any exclude pattern.
+    fn is_excluded(&self, relative: &Path) -> bool {
+        self.exclude.iter().any(|p| p.matches_path(relative))
+    }
+}
+
+/// Retrieves list of files in a directory.
+///
+/// If `options.recursive` is specified, all subdirectories are searched as well. Include and exclude patterns are
+/// matched against paths relative to `dir`. Excluded directories and directories named in `options.exclude_dirs` are
+/// not descended into. If `options.ignore_vcs` is specified, files ignored by `.gitignore` or `.ignore` files are
+/// skipped, as are version control directories like `.git`. Symbolic links are handled according to
+/// `options.symlinks`. If `options.one_file_system` is specified, mount points of other file systems are not crossed.
+/// Hidden files and directories are skipped unless `options.hidden` is specified. Files outside the size range given by
+/// `options.min_size` and `options.max_size` or the modification time range given by `options.newer_than` and
+/// `options.older_than` are skipped. Errors are propagated with added context.
+pub fn get_files<P>(dir: P, options: &WalkOptions) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let root = dir.to_path_buf();
+    let exclude = options.clone();
+    let walker = WalkBuilder::new(dir)
+        .standard_filters(false)
+        .hidden(!options.hidden)
+        .git_ignore(options.ignore_vcs)
+        .git_global(options.ignore_vcs)
+        .git_exclude(options.ignore_vcs)
+        .ignore(options.ignore_vcs)
+        .parents(options.ignore_vcs)
+        .require_git(false)
+        .follow_links(options.symlinks == SymlinkPolicy::Follow)
+        .same_file_system(options.one_file_system)
+        .max_depth((!options.recursive).then_some(1))
+        .filter_entry(move |entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+            let name = entry.file_name();
+            let is_vcs = exclude.ignore_vcs && VCS_DIRS.iter().any(|vcs| name == *vcs);
+            let is_excluded_dir = exclude.exclude_dirs.iter().any(|dir| name == dir);
+            !is_dir
+                || entry.depth() == 0
+                || !(is_vcs || is_excluded_dir || exclude.is_excluded(relative))
+        })
+        .build();
+
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            // Broken links are skipped like any other entry that is not a file.
+            Err(e)
+                if e.io_error()
+                    .is_some_and(|e| e.kind() == io::ErrorKind::NotFound) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                return Err(Error::new(e))
+                    .with_context(|| format!("Error while reading directory {}", dir.display()));
+            }
+        };
+        let path = entry.path();
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+
+        let collect = match options.symlinks {
+            SymlinkPolicy::Files | SymlinkPolicy::Follow => path.is_file(),
+            SymlinkPolicy::Skip => !entry.path_is_symlink() && path.is_file(),
+            SymlinkPolicy::Target => entry.path_is_symlink() || path.is_file(),
+        };
+        if collect
+            && entry.depth() > 0
+            && options.matches(relative)
+            && options.matches_metadata(path)
+        {
+            files.push(entry.into_path());
+        }
+    }
+
+    Ok(files)
+}
+
+/// Expands glob patterns in given paths.
+///
+/// Paths which exist or contain no glob metacharacters (`*`, `?`, `[`) are returned as is, so shells which already
+/// expanded patterns are not affected. Patterns support `**` to match any number of directories. A pattern that matches
+/// nothing is an error.
+fn expand_globs<A>(paths: A) -> Result<Vec<PathBuf>>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let mut expanded = Vec::new();
+    for path in paths {
+        let pattern = match path.to_str() {
+            Some(pattern) if !path.exists() && pattern.contains(['*', '?', '[']) => pattern,
+            _ => {
+                expanded.push(path);
+                continue;
+            }
+        };
+
+        let len = expanded.len();
+        for entry in glob::glob(pattern).with_context(|| format!("Invalid pattern {pattern}"))? {
+            expanded
+                .push(entry.with_context(|| format!("Error while matching pattern {pattern}"))?);
+        }
+        if expanded.len() == len {
+            return Err(Error::msg(format!("No paths match pattern {pattern}")));
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Returns a sorted list of all files in given paths.
+///
+/// Glob patterns in `paths` are expanded first. Directories are searched according to `options`, size and time filters
+/// apply to files given directly as well. `-` is passed through as is to denote standard input. Any error is propagated.
+pub fn get_all_files<A>(paths: A, options: &WalkOptions) -> Result<Vec<PathBuf>>
+where
+    A: IntoIterator<Item = PathBuf>,
+{
+    let mut files = Vec::new();
+    for path in expand_globs(paths)? {
+        if path.as_os_str() == STDIN {
+            files.push(path);
+        } else if path.is_dir() {
+            files.append(&mut get_files(&path, options)?);
+        } else if path.is_file() {
+            if options.matches_metadata(&path) {
+                files.push(path);
+            }
+        } else {
+            return Err(Error::msg(format!(
+                "{} is neither a file nor a directory",
+                path.display()
+            )));
+        }
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Returns the checksum files to verify for `paths`.
+///
+/// Files are used as they are, directories are replaced by the checksum files for any of `algorithms` inside them, see
+/// `Algorithm::from_file_name`. Subdirectories are searched as well if `recursive` is specified. Checksum files found in a
+/// directory are sorted by path. Returns an error if a directory contains no checksum files.
+pub fn get_checksum_files(
+    paths: Vec<PathBuf>,
+    algorithms: &[Algorithm],
+    recursive: bool,
+) -> Result<Vec<PathBuf>> {
+    let extensions: Vec<_> = algorithms.iter().map(|a| a.extension()).collect();
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+
+        let walker = WalkBuilder::new(&path)
+            .standard_filters(false)
+            .max_depth((!recursive).then_some(1))
+            .build();
+        let mut found = Vec::new();
+        for entry in walker {
+            let entry =
+                entry.with_context(|| format!("Failed to search directory {}", path.display()))?;
+            let is_checksum_file = Algorithm::from_file_name(entry.path())
+                .is_some_and(|algorithm| extensions.contains(&algorithm.extension()));
+            if is_checksum_file && entry.file_type().is_some_and(|t| t.is_file()) {
+                found.push(entry.into_path());
+            }
+        }
+        if found.is_empty() {
+            return Err(Error::msg(format!(
+                "No checksum files found in {}",
+                path.display()
+            )));
+        }
+        found.sort();
+        files.extend(found);
+    }
+
+    Ok(files)
+}
+
+/// Reads a list of paths from `list`, separated by `separator`.
+///
+/// If `list` is `-`, the paths are read from standard input. Empty entries are skipped and for newline separated lists a
+/// trailing carriage return is removed. Errors are propagated with added context.
+pub fn read_file_list(list: &Path, separator: u8) -> Result<Vec<PathBuf>> {
+    let data = if list.as_os_str() == STDIN {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .context("Failed to read file list from standard input")?;
+        data
+    } else {
+        fs::read(list).with_context(|| format!("Failed to read file list {}", list.display()))?
+    };
+
+    Ok(data
+        .split(|&b| b == separator)
+        .map(|entry| match separator {
+            b'\n' => entry.strip_suffix(b"\r").unwrap_or(entry),
+            _ => entry,
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+/// Converts raw bytes read from a file list or checksum file into a path.
+///
+/// This is the inverse of `OsStr::as_encoded_bytes`, so paths which are not valid UTF-8 round-trip unchanged.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+/// Converts raw bytes read from a file list or checksum file into a path.
+///
+/// This is the inverse of `OsStr::as_encoded_bytes`, which produces WTF-8 on Windows. Unpaired surrogates, which are
+/// encoded like UTF-8 characters starting with `0xED 0xA0`-`0xED 0xBF`, are decoded so that names which are not valid
+/// UTF-16 round-trip unchanged. Other invalid sequences are replaced by `U+FFFD`.
+#[cfg(windows)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut wide = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (valid, invalid) = match str::from_utf8(rest) {
+            Ok(valid) => (valid, &[][..]),
+            Err(e) => {
+                let (valid, invalid) = rest.split_at(e.valid_up_to());
+                (str::from_utf8(valid).unwrap_or_default(), invalid)
+            }
+        };
+        wide.extend(valid.encode_utf16());
+        rest = match invalid {
+            [] => invalid,
+            [0xED, b1 @ 0xA0..=0xBF, b2 @ 0x80..=0xBF, rest @ ..] => {
+                wide.push(0xD000 | (u16::from(b1 & 0x3F) << 6) | u16::from(b2 & 0x3F));
+                rest
+            }
+            [_, rest @ ..] => {
+                wide.push(0xFFFD);
+                rest
+            }
+        };
+    }
+
+    PathBuf::from(OsString::from_wide(&wide))
+}
+
+/// Converts raw bytes read from a file list or checksum file into a path.
+#[cfg(not(any(unix, windows)))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Parses a human-friendly size such as `512`, `10K`, `10M` or `2G`.
+///
+/// Suffixes `K`, `M`, `G` and `T` are binary multiples (powers of 1024), an optional trailing `B` or `iB` is accepted.
+/// Matching is case-insensitive.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let upper = s.trim().to_uppercase();
+    let number = upper
+        .strip_suffix("IB")
+        .or_else(|| upper.strip_suffix('B'))
+        .unwrap_or(&upper);
+    let (number, multiplier) = match number.char_indices().last() {
+        Some((i, 'K')) => (&number[..i], 1u64 << 10),
+        Some((i, 'M')) => (&number[..i], 1 << 20),
+        Some((i, 'G')) => (&number[..i], 1 << 30),
+        Some((i, 'T')) => (&number[..i], 1 << 40),
+        _ => (number, 1),
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size {s}"))?;
+    if !number.is_finite() || number < 0.0 {
+        return Err(format!("invalid size {s}"));
+    }
+    let bytes = number * multiplier as f64;
+    // `u64::MAX` rounds up to 2^64 as a float, which is already out of range.
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("size {s} is too large"));
+    }
+
+    Ok(bytes as u64)
+}
+
+/// Parses a duration given as a number with an optional unit, e.g. `90`, `30s`, `15m`, `12h`, `7d` or `2w`.
+///
+/// Numbers without a unit are seconds. Returns a message describing the problem for invalid or zero durations.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 60 * 60),
+        Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+        Some((i, 'w')) => (&s[..i], 7 * 24 * 60 * 60),
+        _ => (s, 1),
+    };
+
+    let number: u64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid duration {s}"))?;
+    if number == 0 {
+        return Err(format!("invalid duration {s}"));
+    }
+
+    Ok(Duration::from_secs(number.saturating_mul(multiplier)))
+}
+
+/// Parses a point in time given as a date, a date and time, or the path of a file whose modification time is used.
+///
+/// Accepted formats are RFC 3339 (`2024-01-01T12:00:00+01:00`), `2024-01-01 12:00:00`, `2024-01-01T12:00:00` and
+/// `2024-01-01`. Times without an offset are interpreted in the local time zone.
+pub fn parse_time(s: &str) -> Result<SystemTime, String> {
+    let path = Path::new(s);
+    if path.exists() {
+        return fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map_err(|e| format!("failed to get modification time of {s}: {e}"));
+    }
+
+    let time = if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        time.with_timezone(&Local)
+    } else {
+        let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+            .or_else(|_| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+            })
+            .map_err(|_| {
+                format!("{s} is neither an existing file nor a date such as 2024-01-01")
+            })?;
+        Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .ok_or_else(|| format!("{s} does not exist in the local time zone"))?
+    };
+
+    Ok(time.into())
+}
@@ -0,0 +1,163 @@
+//! Checksums stored in extended attributes of files, so that files can be checked for bit rot without checksum files.
+//!
+//! Like cshatag, the modification time of the file is stored along with the checksum. A checksum which does not match
+//! although the modification time is unchanged indicates corruption, otherwise the file was modified on purpose.
+//!
+//! On Unix the values are stored in the attributes `user.crc32.checksum`, `user.crc32.algorithm` and `user.crc32.mtime`.
+//! Windows has no such attributes, instead they are stored as `name=value` lines in the NTFS alternate data stream
+//! `file.ext:crc32`, which is kept when the file is copied between NTFS volumes.
+use std::{fs::Metadata, io, path::Path, time::UNIX_EPOCH};
+
+use anyhow::{Context, Error, Result};
+
+use crate::hash::Algorithm;
+
+/// Name of the value holding the checksum in hex.
+const CHECKSUM: &str = "checksum";
+/// Name of the value holding the algorithm of the checksum, see `Algorithm::tag`.
+const ALGORITHM: &str = "algorithm";
+/// Name of the value holding the modification time of the file when it was hashed, see `timestamp`.
+const MTIME: &str = "mtime";
+
+/// Checksum stored in the extended attributes of a file.
+pub struct Stored {
+    pub checksum: String,
+    pub algorithm: Algorithm,
+    /// Modification time of the file when it was hashed, see `timestamp`.
+    pub mtime: String,
+}
+
+/// Returns the modification time in `metadata` in the format stored in extended attributes, e.g.
+/// `1712345678.123456789`.
+pub fn timestamp(metadata: &Metadata) -> Option<String> {
+    let duration = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "{}.{:09}",
+        duration.as_secs(),
+        duration.subsec_nanos()
+    ))
+}
+
+/// Reads the checksum stored in the extended attributes of the file at `path`.
+///
+/// Returns `None` if no checksum is stored. Any error, including an unknown algorithm, is propagated with added
+/// context.
+pub fn read(path: &Path) -> Result<Option<Stored>> {
+    let values = read_values(path)
+        .with_context(|| format!("Failed to read extended attributes of {}", path.display()))?;
+    let get = |name: &str| {
+        values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    let (Some(checksum), Some(algorithm)) = (get(CHECKSUM), get(ALGORITHM)) else {
+        return Ok(None);
+    };
+    let algorithm = Algorithm::from_tag(&algorithm).ok_or_else(|| {
+        Error::msg(format!(
+            "Unknown algorithm {algorithm} stored in extended attributes of {}",
+            path.display()
+        ))
+    })?;
+    Ok(Some(Stored {
+        checksum,
+        algorithm,
+        mtime: get(MTIME).unwrap_or_default(),
+    }))
+}
+
+/// Stores `stored` in the extended attributes of the file at `path`, replacing any stored checksum. Any error is
+/// propagated with added context.
+pub fn write(path: &Path, stored: &Stored) -> Result<()> {
+    let algorithm = stored.algorithm.tag();
+    let values = [
+        (CHECKSUM, stored.checksum.as_str()),
+        (ALGORITHM, algorithm.as_str()),
+        (MTIME, stored.mtime.as_str()),
+    ];
+    write_values(path, &values)
+        .with_context(|| format!("Failed to write extended attributes of {}", path.display()))
+}
+
+/// Prefix of the names of extended attributes.
+#[cfg(unix)]
+const PREFIX: &str = "user.crc32.";
+
+/// Returns the stored values of the file at `path` as pairs of name and value.
+#[cfg(unix)]
+fn read_values(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut values = Vec::new();
+    for name in [CHECKSUM, ALGORITHM, MTIME] {
+        if let Some(value) = xattr::get(path, format!("{PREFIX}{name}"))? {
+            values.push((
+                name.to_string(),
+                String::from_utf8_lossy(&value).into_owned(),
+            ));
+        }
+    }
+    Ok(values)
+}
+
+/// Stores `values` as pairs of name and value for the file at `path`.
+#[cfg(unix)]
+fn write_values(path: &Path, values: &[(&str, &str)]) -> io::Result<()> {
+    values
+        .iter()
+        .try_for_each(|(name, value)| xattr::set(path, format!("{PREFIX}{name}"), value.as_bytes()))
+}
+
+/// Returns the path of the alternate data stream of the file at `path`.
+#[cfg(windows)]
+fn stream_path(path: &Path) -> std::path::PathBuf {
+    let mut stream = path.as_os_str().to_owned();
+    stream.push(":crc32");
+    stream.into()
+}
+
+/// Returns the stored values of the file at `path` as pairs of name and value.
+#[cfg(windows)]
+fn read_values(path: &Path) -> io::Result<Vec<(String, String)>> {
+    let data = match std::fs::read(stream_path(path)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(String::from_utf8_lossy(&data)
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect())
+}
+
+/// Stores `values` as pairs of name and value for the file at `path`.
+///
+/// Writing a stream updates the modification time of the file, so it is restored afterwards.
+#[cfg(windows)]
+fn write_values(path: &Path, values: &[(&str, &str)]) -> io::Result<()> {
+    use std::fs::{self, File};
+
+    let modified = fs::metadata(path)?.modified()?;
+    let data: String = values
+        .iter()
+        .map(|(name, value)| format!("{name}={value}\r\n"))
+        .collect();
+    fs::write(stream_path(path), data)?;
+    File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(modified)
+}
+
+/// Returns the stored values of the file at `path` as pairs of name and value.
+#[cfg(not(any(unix, windows)))]
+fn read_values(_path: &Path) -> io::Result<Vec<(String, String)>> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Stores `values` as pairs of name and value for the file at `path`.
+#[cfg(not(any(unix, windows)))]
+fn write_values(_path: &Path, _values: &[(&str, &str)]) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
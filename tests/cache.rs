@@ -0,0 +1,27 @@
+//! Tests of the cache of checksums.
+use std::fs;
+
+use crc32::{
+    cache::{Cache, Key},
+    hash::Algorithm,
+};
+
+/// Checksums cached for a CRC with custom parameters must not be returned for a CRC with other parameters.
+#[test]
+fn custom_crcs_are_cached_separately() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("a.bin");
+    fs::write(&file, b"hello\n").unwrap();
+    let crc16 = Algorithm::Crc("width=16,poly=0x1021,init=0xffff".parse().unwrap());
+    let crc32 = Algorithm::Crc("width=32,poly=0x04c11db7".parse().unwrap());
+
+    let path = dir.path().join("cache.sqlite");
+    let cache = Cache::open(&path).unwrap();
+    cache.insert(Key::new(&file).unwrap(), crc16, "2475".to_string());
+    cache.save().unwrap();
+
+    let cache = Cache::open(&path).unwrap();
+    let key = Key::new(&file).unwrap();
+    assert_eq!(cache.get(&key, crc16).as_deref(), Some("2475"));
+    assert_eq!(cache.get(&key, crc32), None);
+}